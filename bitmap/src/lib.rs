@@ -1,16 +1,18 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 use std::{
+    iter::FusedIterator,
     marker::PhantomData,
-    ops::{Range, RangeBounds},
+    ops::{BitAndAssign, BitOrAssign, BitXorAssign, Range, RangeBounds},
     ptr::NonNull,
     sync::atomic::AtomicU8,
 };
 
 use aliasing::{Aliasing, UnaliasedAliasing, UnaliasedInnerBytesAliasing};
-use copy_range::CopyRange;
 use either::Either;
 use mutability::{ConstMutability, MutMutability, Mutability};
 
+pub use copy_range::CopyRange;
+
 pub use aliasing::{
     Aliased, AliasedEdgesOnly, AliasedNoEdges, JustAnEdge, Unaliased,
     UnaliasedNoEdges,
@@ -44,6 +46,7 @@ pub mod aliasing;
 pub mod mutability;
 
 /// A 2-D bitmap
+#[derive(Clone)]
 pub struct BitMap {
     /// Packed 8-bits to a byte, with each row starting on a new byte
     data: Vec<u8>,
@@ -90,6 +93,30 @@ impl BitMap {
         (self.height, self.width)
     }
 
+    /// Sets every bit to `value`, leaving the padding after each row clear.
+    pub fn fill(&mut self, value: bool) {
+        if !value {
+            self.data.fill(0);
+            return;
+        }
+        if self.stride == 0 {
+            return;
+        }
+        let whole = self.width / 8;
+        let last_bits = self.width % 8;
+        for row in self.data.chunks_exact_mut(self.stride) {
+            row[..whole].fill(0xff);
+            if last_bits != 0 {
+                row[whole] = (1u8 << last_bits) - 1;
+            }
+        }
+    }
+
+    /// Clears every bit, i.e. `fill(false)`.
+    pub fn clear(&mut self) {
+        self.fill(false);
+    }
+
     /// Calls `f` with each index whose bit is `true` (row, col)
     pub fn for_each_true(&self, mut f: impl FnMut(usize, usize)) {
         for row in 0..self.height {
@@ -105,6 +132,81 @@ impl BitMap {
         }
     }
 
+    /// Returns the first index (row, col) whose bit is `true`, in row-major
+    /// order.
+    pub fn find_first_set(&self) -> Option<(usize, usize)> {
+        self.next_set_bit((0, 0))
+    }
+
+    /// Returns the first index (row, col) at or after `from`, in row-major
+    /// order, whose bit is `true`. `from` may be past the end of its row, or
+    /// past the last row.
+    ///
+    /// Skips a whole byte of `false` bits at a time, so this is much faster
+    /// than checking each bit when few are set.
+    pub fn next_set_bit(
+        &self,
+        (mut row, mut col): (usize, usize),
+    ) -> Option<(usize, usize)> {
+        while row < self.height {
+            if col < self.width {
+                let bytes = &self.data[row * self.stride..][..self.stride];
+                let mut byte_col = col / 8;
+                // Ignore the bits before `col` in its byte.
+                let mut byte = bytes[byte_col] & (0xff << (col % 8));
+                loop {
+                    if byte != 0 {
+                        let found =
+                            byte_col * 8 + byte.trailing_zeros() as usize;
+                        // Only padding is left in this row.
+                        if found >= self.width {
+                            break;
+                        }
+                        return Some((row, found));
+                    }
+                    byte_col += 1;
+                    if byte_col == bytes.len() {
+                        break;
+                    }
+                    byte = bytes[byte_col];
+                }
+            }
+            row += 1;
+            col = 0;
+        }
+        None
+    }
+
+    /// Returns an iterator over the columns in `row` whose bits are `true`,
+    /// in order. Like [`BitMap::next_set_bit`], this skips a whole byte of
+    /// `false` bits at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of range.
+    pub fn iter_true_in_row(
+        &self,
+        row: usize,
+    ) -> impl Iterator<Item = usize> + '_ {
+        if row >= self.height {
+            panic!("index out of range");
+        }
+        let bytes = &self.data[row * self.stride..][..self.stride];
+        bytes
+            .iter()
+            .enumerate()
+            .flat_map(|(byte_col, &byte)| {
+                let mut byte = byte;
+                std::iter::from_fn(move || {
+                    let bit = (byte != 0).then(|| byte.trailing_zeros())?;
+                    byte &= byte - 1;
+                    Some(byte_col * 8 + bit as usize)
+                })
+            })
+            // Ignore padding.
+            .take_while(|&col| col < self.width)
+    }
+
     /// Calls `f` with each index whose bit is `false` (row, col)
     pub fn for_each_false(&self, mut f: impl FnMut(usize, usize)) {
         'rows: for row in 0..self.height {
@@ -126,28 +228,296 @@ impl BitMap {
     }
 
     pub fn count_ones(&self) -> usize {
-        let mut count_ones = 0;
-        'rows: for row in 0..self.height {
-            let start_byte = row * self.stride;
-            for (byte_col, byte) in
-                self.data[start_byte..][..self.stride].iter().enumerate()
-            {
-                if byte_col * 8 + 7 < self.width {
-                    count_ones += byte.count_ones() as usize;
+        (0..self.height).map(|row| self.count_ones_in_row(row)).sum()
+    }
+
+    /// Returns the number of `true` bits in each row.
+    pub fn count_ones_per_row(&self) -> Vec<usize> {
+        (0..self.height).map(|row| self.count_ones_in_row(row)).collect()
+    }
+
+    fn count_ones_in_row(&self, row: usize) -> usize {
+        let bytes = &self.data[row * self.stride..][..self.stride];
+        // Only the first `width` bits of each row are used.
+        let (whole, rest) = bytes.split_at(self.width / 8);
+        let mut count_ones: usize =
+            whole.iter().map(|byte| byte.count_ones() as usize).sum();
+        let last_bits = self.width % 8;
+        if last_bits != 0 {
+            let mask = (1u8 << last_bits) - 1;
+            count_ones += (rest[0] & mask).count_ones() as usize;
+        }
+        count_ones
+    }
+
+    /// Clears every bit that is not set in `mask`, i.e. sets this bitmap to
+    /// the bitwise AND of it and `mask`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mask` is not the same size as this bitmap.
+    pub fn apply_mask(&mut self, mask: &BitMap) {
+        self.bitand_assign(mask);
+    }
+
+    /// Sets this bitmap to the bitwise AND of it and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same size as this bitmap.
+    pub fn bitand_assign(&mut self, other: &BitMap) {
+        self.combine(other, |a, b| a & b);
+    }
+
+    /// Sets this bitmap to the bitwise OR of it and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same size as this bitmap.
+    pub fn bitor_assign(&mut self, other: &BitMap) {
+        self.combine(other, |a, b| a | b);
+    }
+
+    /// Sets this bitmap to the bitwise XOR of it and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same size as this bitmap.
+    pub fn bitxor_assign(&mut self, other: &BitMap) {
+        self.combine(other, |a, b| a ^ b);
+    }
+
+    /// Returns a bitmap with the bits set where this bitmap and `other`
+    /// differ, i.e. their bitwise XOR.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same size as this bitmap.
+    pub fn diff(&self, other: &BitMap) -> BitMap {
+        let mut diff = self.clone();
+        diff.bitxor_assign(other);
+        diff
+    }
+
+    /// Returns how many bits differ between this bitmap and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same size as this bitmap.
+    pub fn diff_count(&self, other: &BitMap) -> usize {
+        self.diff(other).count_ones()
+    }
+
+    /// Replaces each byte of this bitmap with `op` of it and the matching byte
+    /// of `other`, leaving the padding bits after each row alone.
+    fn combine(&mut self, other: &BitMap, op: impl Fn(u8, u8) -> u8) {
+        assert!(
+            self.size() == other.size(),
+            "bitmap sizes differ: {:?} and {:?}",
+            self.size(),
+            other.size()
+        );
+        if self.stride == 0 {
+            return;
+        }
+        // Same size means same stride, so the bytes line up.
+        let rows = self.data.chunks_exact_mut(self.stride);
+        for (row, other_row) in rows.zip(other.data.chunks_exact(self.stride)) {
+            let (whole, rest) = row.split_at_mut(self.width / 8);
+            for (byte, &other_byte) in whole.iter_mut().zip(other_row) {
+                *byte = op(*byte, other_byte);
+            }
+            let last_bits = self.width % 8;
+            if last_bits != 0 {
+                let mask = (1u8 << last_bits) - 1;
+                let other_byte = other_row[self.width / 8];
+                rest[0] = (rest[0] & !mask) | (op(rest[0], other_byte) & mask);
+            }
+        }
+    }
+
+    /// Returns a bitmap `factor` times smaller in each dimension (rounded up),
+    /// where each bit is set if at least `threshold` of the bits in the
+    /// corresponding `factor`x`factor` block of this bitmap are set, or if any
+    /// are set if `threshold` is `0.0`. Blocks on the bottom and right edges
+    /// may be smaller than `factor`x`factor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn downscale(&self, factor: usize, threshold: f32) -> BitMap {
+        assert!(factor > 0, "downscale factor must be nonzero");
+        let width = self.width.div_ceil(factor);
+        let mut downscaled =
+            BitMap::new(self.height.div_ceil(factor), width).unwrap();
+        // The number of set bits in each block of the current block row.
+        let mut counts = vec![0; width];
+        let view = self.as_view_ref::<ConstUnsync>();
+        for (row, bits) in view.into_rows().enumerate() {
+            for (block_col, count) in counts.iter_mut().enumerate() {
+                let start = block_col * factor;
+                *count += bits.skip(start).take(factor).count_ones();
+            }
+            let block_row = row / factor;
+            let block_height = row + 1 - block_row * factor;
+            if block_height < factor && row + 1 < self.height {
+                continue;
+            }
+            for (block_col, count) in counts.iter_mut().enumerate() {
+                let block_width = factor.min(self.width - block_col * factor);
+                let set = if threshold <= 0.0 {
+                    *count > 0
                 } else {
-                    for bit_col in 0..8 {
-                        let col = (byte_col << 3) | bit_col;
-                        if col >= self.width {
-                            continue 'rows;
-                        }
-                        if byte & (1 << bit_col) != 0 {
-                            count_ones += 1;
-                        }
+                    let area = block_height * block_width;
+                    *count as f32 >= threshold * area as f32
+                };
+                downscaled.set((block_row, block_col), set);
+                *count = 0;
+            }
+        }
+        downscaled
+    }
+
+    /// Returns a bitmap `factor` times larger in each dimension, where each
+    /// bit of this bitmap becomes a `factor`x`factor` block, or `None` if the
+    /// new size overflows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn upscale(&self, factor: usize) -> Option<BitMap> {
+        assert!(factor > 0, "upscale factor must be nonzero");
+        let mut upscaled = BitMap::new(
+            self.height.checked_mul(factor)?,
+            self.width.checked_mul(factor)?,
+        )?;
+        let stride = upscaled.stride;
+        if stride == 0 {
+            return Some(upscaled);
+        }
+        // Each row is expanded once, and then copied `factor` times.
+        let mut expanded = vec![0u8; stride];
+        for row in 0..self.height {
+            expanded.fill(0);
+            let bytes = &self.data[row * self.stride..][..self.stride];
+            for (byte_col, &byte) in bytes.iter().enumerate() {
+                let mut byte = byte;
+                while byte != 0 {
+                    let col = byte_col * 8 + byte.trailing_zeros() as usize;
+                    // Bits after `width` are unused.
+                    if col >= self.width {
+                        break;
                     }
+                    let block = col * factor..(col + 1) * factor;
+                    set_bit_range(&mut expanded, block);
+                    byte &= byte - 1;
                 }
             }
+            upscaled.data[row * factor * stride..][..factor * stride]
+                .chunks_exact_mut(stride)
+                .for_each(|dest| dest.copy_from_slice(&expanded));
         }
-        count_ones
+        Some(upscaled)
+    }
+
+    /// Returns this bitmap's height and width, each as 8 little-endian bytes,
+    /// followed by its bits in row-major order, packed 8 to a byte (the first
+    /// in the lowest bit) without any padding between rows. Any bits after
+    /// the last one are clear.
+    pub fn to_packed_bytes(&self) -> Vec<u8> {
+        let len = PACKED_HEADER_LEN + (self.height * self.width).div_ceil(8);
+        let mut packed = Vec::with_capacity(len);
+        packed.extend_from_slice(&(self.height as u64).to_le_bytes());
+        packed.extend_from_slice(&(self.width as u64).to_le_bytes());
+        packed.resize(len, 0);
+        let bits = &mut packed[PACKED_HEADER_LEN..];
+        for row in 0..self.height {
+            let bytes = &self.data[row * self.stride..][..self.stride];
+            for (byte_col, &byte) in bytes.iter().enumerate() {
+                // Each byte lands across at most two bytes of `bits`.
+                let start = row * self.width + byte_col * 8;
+                let byte = byte & self.byte_mask(byte_col);
+                let shifted = u16::from(byte) << (start % 8);
+                bits[start / 8] |= shifted as u8;
+                if shifted >> 8 != 0 {
+                    bits[start / 8 + 1] |= (shifted >> 8) as u8;
+                }
+            }
+        }
+        packed
+    }
+
+    /// Reads a bitmap written by [`BitMap::to_packed_bytes`]. Any bits after
+    /// the last one are ignored.
+    pub fn from_packed_bytes(bytes: &[u8]) -> Result<BitMap, ParseError> {
+        let (header, bits) = bytes
+            .split_first_chunk::<PACKED_HEADER_LEN>()
+            .ok_or(ParseError::MissingHeader)?;
+        let height = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let width = u64::from_le_bytes(header[8..].try_into().unwrap());
+        let too_large = || ParseError::TooLarge { height, width };
+        let (height, width) = usize::try_from(height)
+            .ok()
+            .zip(usize::try_from(width).ok())
+            .ok_or_else(too_large)?;
+        // The dimensions are untrusted, so check that the data is really
+        // there before allocating anything for them.
+        let expected = height
+            .checked_mul(width)
+            .and_then(|bits| bits.div_ceil(8).checked_add(PACKED_HEADER_LEN))
+            .ok_or_else(too_large)?;
+        if bytes.len() != expected {
+            return Err(ParseError::WrongLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let mut bitmap = BitMap::new(height, width).ok_or_else(too_large)?;
+        for row in 0..bitmap.height {
+            for byte_col in 0..bitmap.stride {
+                let start = row * bitmap.width + byte_col * 8;
+                let low = u16::from(bits[start / 8]);
+                let high = bits.get(start / 8 + 1).map_or(0, |&b| b.into());
+                let byte = ((low | high << 8) >> (start % 8)) as u8;
+                bitmap.data[row * bitmap.stride + byte_col] =
+                    byte & bitmap.byte_mask(byte_col);
+            }
+        }
+        Ok(bitmap)
+    }
+
+    /// The bits of byte `byte_col` of each row that are not padding.
+    fn byte_mask(&self, byte_col: usize) -> u8 {
+        match self.width - byte_col * 8 {
+            8.. => 0xff,
+            bits => (1u8 << bits) - 1,
+        }
+    }
+
+    /// Returns an iterator over the rows of this bitmap, where each row is an
+    /// iterator over exactly `width` bits.
+    pub fn rows_as_bits(
+        &self,
+    ) -> impl Iterator<Item = impl Iterator<Item = bool> + '_> + '_ {
+        self.rows().map(BaseBitSlice::into_bits)
+    }
+
+    /// Returns the bits of `row`, covering `0..width`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of range.
+    pub fn row(&self, row: usize) -> UnaliasedBitSlice<'_, ConstSync> {
+        if row >= self.height {
+            panic!("index out of range");
+        }
+        self.as_view_ref().row_unchecked(row)
+    }
+
+    /// Returns an iterator over the rows of this bitmap, like
+    /// [`BitMap::row`] for each row in order.
+    pub fn rows(&self) -> Rows<'_, ConstSync, Unaliased> {
+        self.as_view_ref().into_rows()
     }
 
     pub fn as_view_ref<M: ConstMutability>(
@@ -177,6 +547,148 @@ impl BitMap {
             _edge_aliasing: PhantomData,
         }
     }
+
+    /// Returns a view of the bits in `rows` and `cols`, which is empty if
+    /// either range is. The view's indices are relative to the rectangle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range ends before it starts, or past the edge of
+    /// this bitmap.
+    pub fn view_rect(
+        &self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> BitMapView<'_, ConstSync, AliasedEdgesOnly> {
+        assert!(
+            rows.start <= rows.end && rows.end <= self.height,
+            "rows {rows:?} out of range for height {}",
+            self.height
+        );
+        assert!(
+            cols.start <= cols.end && cols.end <= self.width,
+            "columns {cols:?} out of range for width {}",
+            self.width
+        );
+        BitMapView {
+            data: NonNull::from(&self.data[..]).cast(),
+            stride: self.stride,
+            columns: CopyRange::from(cols),
+            rows: CopyRange::from(rows),
+            _lifetime: PhantomData,
+            _mutability: PhantomData,
+            _edge_aliasing: PhantomData,
+        }
+    }
+}
+
+impl BitAndAssign<&BitMap> for BitMap {
+    fn bitand_assign(&mut self, other: &BitMap) {
+        BitMap::bitand_assign(self, other);
+    }
+}
+
+impl BitOrAssign<&BitMap> for BitMap {
+    fn bitor_assign(&mut self, other: &BitMap) {
+        BitMap::bitor_assign(self, other);
+    }
+}
+
+impl BitXorAssign<&BitMap> for BitMap {
+    fn bitxor_assign(&mut self, other: &BitMap) {
+        BitMap::bitxor_assign(self, other);
+    }
+}
+
+/// Bitmaps are equal if they are the same size and have the same bits set.
+/// The padding after each row is ignored.
+impl PartialEq for BitMap {
+    fn eq(&self, other: &BitMap) -> bool {
+        if self.size() != other.size() {
+            return false;
+        }
+        if self.stride == 0 {
+            return true;
+        }
+        let whole = self.width / 8;
+        let mask = (1u8 << (self.width % 8)).wrapping_sub(1);
+        // Same size means same stride, so the bytes line up.
+        let rows = self.data.chunks_exact(self.stride);
+        rows.zip(other.data.chunks_exact(self.stride)).all(|(row, other)| {
+            row[..whole] == other[..whole]
+                && (mask == 0 || (row[whole] ^ other[whole]) & mask == 0)
+        })
+    }
+}
+
+impl Eq for BitMap {}
+
+/// Shows the size and how many bits are set, not every bit.
+impl std::fmt::Debug for BitMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BitMap")
+            .field("height", &self.height)
+            .field("width", &self.width)
+            .field("ones", &self.count_ones())
+            .finish()
+    }
+}
+
+/// The length of the dimensions header of [`BitMap::to_packed_bytes`].
+const PACKED_HEADER_LEN: usize = 16;
+
+/// An error from [`BitMap::from_packed_bytes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The buffer is too short to hold the dimensions.
+    MissingHeader,
+    /// The dimensions are too large for a bitmap on this platform.
+    TooLarge { height: u64, width: u64 },
+    /// The buffer's length (in bytes) does not match its dimensions.
+    WrongLength { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MissingHeader => {
+                write!(f, "packed bitmap is missing its dimensions")
+            }
+            ParseError::TooLarge { height, width } => {
+                write!(f, "packed bitmap is too large ({width}x{height})")
+            }
+            ParseError::WrongLength { expected, actual } => write!(
+                f,
+                "packed bitmap should be {expected} bytes, but is {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Range arithmetic for [`CopyRange`], which itself only provides `start`,
+/// `end`, [`len`](CopyRange::len), [`is_empty`](CopyRange::is_empty), and
+/// [`contains`](CopyRange::contains).
+pub trait CopyRangeExt<Idx> {
+    /// The indices in both `self` and `other`. If they are disjoint, this is
+    /// an empty range starting at the larger of the two starts.
+    fn intersect(self, other: Self) -> Self;
+
+    /// Converts to a [`Range`], e.g. for slicing.
+    fn into_range(self) -> Range<Idx>;
+}
+
+impl<Idx: Ord + Copy> CopyRangeExt<Idx> for CopyRange<Idx> {
+    fn intersect(self, other: Self) -> Self {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end).max(start);
+        CopyRange { start, end }
+    }
+
+    fn into_range(self) -> Range<Idx> {
+        self.into_std()
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -208,6 +720,30 @@ impl ByteBitRange {
         self.mask().count_ones() as usize
     }
 
+    /// Iterates over the index of each bit in the range, in order. Like
+    /// [`mask`](Self::mask), an `end` above 8 is treated as 8.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = u8> + ExactSizeIterator {
+        self.start..self.end.min(8)
+    }
+
+    /// Whether bit `idx` is in the range. Like [`mask`](Self::mask), an
+    /// `end` above 8 is treated as 8.
+    pub const fn contains(&self, idx: u8) -> bool {
+        idx < 8 && self.mask() & (1 << idx) != 0
+    }
+
+    /// The bits in both `self` and `other`, as a canonical range (an empty
+    /// intersection is always `0..0`).
+    pub fn intersection(&self, other: ByteBitRange) -> ByteBitRange {
+        let mask = self.mask() & other.mask();
+        // The intersection of two contiguous masks is contiguous.
+        let range = ByteBitRange::from_mask(mask);
+        debug_assert!(range.is_some(), "non-contiguous mask {mask:#010b}");
+        range.unwrap_or_else(ByteBitRange::empty)
+    }
+
     pub fn pop_first(&mut self) -> Option<u8> {
         if !self.is_empty() {
             let value = self.start;
@@ -379,6 +915,84 @@ impl<'a, M: Mutability, A: Aliasing> BaseBitSlice<'a, M, A> {
         self.bits.len()
     }
 
+    /// Whether this slice may observe changes to its bits that were not made
+    /// through it, i.e. `A::SEMANTICALLY_ALIASED`. If so, bytes read from it
+    /// should not be cached.
+    pub const fn is_semantically_aliased(&self) -> bool {
+        A::SEMANTICALLY_ALIASED
+    }
+
+    /// Returns the number of `true` bits in this slice.
+    pub fn count_ones(&self) -> usize {
+        self.raw_bytes()
+            .map(|(byte, bits)| {
+                let byte = unsafe { A::load_byte::<M>(byte, bits.len() == 8) };
+                (byte & bits.mask()).count_ones() as usize
+            })
+            .sum()
+    }
+
+    /// Returns the number of bits that differ between this slice and `other`
+    /// (i.e. the Hamming distance).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slices have different lengths.
+    pub fn count_differences<M2: Mutability, A2: Aliasing>(
+        &self,
+        other: &BaseBitSlice<'_, M2, A2>,
+    ) -> usize {
+        self.count_combined(other, |a, b| a ^ b, |a, b| a != b)
+    }
+
+    /// Returns the number of bits that are `true` in both this slice and
+    /// `mask`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slices have different lengths.
+    pub fn count_ones_where<M2: Mutability, A2: Aliasing>(
+        &self,
+        mask: &BaseBitSlice<'_, M2, A2>,
+    ) -> usize {
+        self.count_combined(mask, |a, b| a & b, |a, b| a & b)
+    }
+
+    /// Counts the `true` bits of `self` and `other` combined bitwise with
+    /// `byte_op` (or `bit_op`, for the bits of slices whose bits are at
+    /// different offsets within their bytes).
+    fn count_combined<M2: Mutability, A2: Aliasing>(
+        &self,
+        other: &BaseBitSlice<'_, M2, A2>,
+        byte_op: impl Fn(u8, u8) -> u8,
+        bit_op: impl Fn(bool, bool) -> bool,
+    ) -> usize {
+        assert!(
+            self.len() == other.len(),
+            "bit slice lengths differ: {} and {}",
+            self.len(),
+            other.len()
+        );
+        if self.bits.start % 8 != other.bits.start % 8 {
+            return self
+                .bits()
+                .zip(other.bits())
+                .filter(|&(a, b)| bit_op(a, b))
+                .count();
+        }
+        // With the same offset, both slices have the same bit ranges in each
+        // of their bytes.
+        self.raw_bytes()
+            .zip(other.raw_bytes())
+            .map(|((a, bits), (b, _))| {
+                let is_inner = bits.len() == 8;
+                let a = unsafe { A::load_byte::<M>(a, is_inner) };
+                let b = unsafe { A2::load_byte::<M2>(b, is_inner) };
+                (byte_op(a, b) & bits.mask()).count_ones() as usize
+            })
+            .sum()
+    }
+
     pub fn into_const(self) -> BaseBitSlice<'a, M::Const, A> {
         transmute!(self as BaseBitSlice)
     }
@@ -528,7 +1142,8 @@ impl<'a, M: Mutability, A: UnaliasedInnerBytesAliasing> BaseBitSlice<'a, M, A> {
     ///
     /// # Panic
     ///
-    /// Panics if `at >= self.len()`.
+    /// Panics if `at > self.len()`. See [`BaseBitSlice::split_at_checked`]
+    /// for a non-panicking version.
     #[doc(alias = "split_at_mut")]
     pub fn split_at(
         self,
@@ -537,22 +1152,55 @@ impl<'a, M: Mutability, A: UnaliasedInnerBytesAliasing> BaseBitSlice<'a, M, A> {
         BaseBitSlice<'a, M, AliasedEdgesOnly>,
         BaseBitSlice<'a, M, AliasedEdgesOnly>,
     ) {
+        let len = self.len();
+        match self.split_at_checked(at) {
+            Some(halves) => halves,
+            None => panic!("split index {at} out of range for length {len}"),
+        }
+    }
+
+    /// Like [`BaseBitSlice::split_at`], but returns `None` if
+    /// `at > self.len()`.
+    #[doc(alias = "split_at_mut_checked")]
+    pub fn split_at_checked(
+        self,
+        at: usize,
+    ) -> Option<(
+        BaseBitSlice<'a, M, AliasedEdgesOnly>,
+        BaseBitSlice<'a, M, AliasedEdgesOnly>,
+    )> {
         if at == 0 {
-            (Default::default(), self.into_aliased_edges())
+            Some((Default::default(), self.into_aliased_edges()))
         } else if at == self.len() {
-            (self.into_aliased_edges(), Default::default())
+            Some((self.into_aliased_edges(), Default::default()))
         } else if at > self.len() {
-            todo!("panic")
+            None
         } else {
             let new_split = self.bits.start + at;
             let mut head = transmute!(self as BaseBitSlice);
             let mut tail = transmute!(self as BaseBitSlice);
             head.bits.end = new_split;
             tail.bits.start = new_split;
-            (head, tail)
+            Some((head, tail))
         }
     }
 
+    /// Returns the first `n` bits of this slice, or the whole slice if
+    /// `n >= self.len()`.
+    #[doc(alias = "take_mut")]
+    pub fn take(self, n: usize) -> BaseBitSlice<'a, M, AliasedEdgesOnly> {
+        let n = n.min(self.len());
+        self.split_at(n).0
+    }
+
+    /// Returns all but the first `n` bits of this slice, or an empty slice if
+    /// `n >= self.len()`.
+    #[doc(alias = "skip_mut")]
+    pub fn skip(self, n: usize) -> BaseBitSlice<'a, M, AliasedEdgesOnly> {
+        let n = n.min(self.len());
+        self.split_at(n).1
+    }
+
     /// Unlike [`BaseBitSlice::split_at`], this method does not require changing
     /// the aliasing type. However, it only supports splitting at byte
     /// boundaries, or at the edges of the slice.
@@ -602,6 +1250,91 @@ impl<'a, M: MutMutability, A: Aliasing> BaseBitSlice<'a, M, A> {
     pub fn fill(&mut self, value: bool) {
         A::fill(self.reborrow_mut(), value)
     }
+
+    /// Copies the bits of `src` into this slice. The other bits of bytes that
+    /// are only partially in this slice are left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slices have different lengths.
+    pub fn copy_from<M2: Mutability, A2: Aliasing>(
+        &mut self,
+        src: &BaseBitSlice<'_, M2, A2>,
+    ) {
+        self.combine_assign(src, |_, b| b)
+    }
+
+    /// Sets each bit of this slice to whether it and the corresponding bit of
+    /// `other` are both `true`. Like [`BaseBitSlice::copy_from`], bits outside
+    /// this slice are left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slices have different lengths.
+    pub fn bitand_assign<M2: Mutability, A2: Aliasing>(
+        &mut self,
+        other: &BaseBitSlice<'_, M2, A2>,
+    ) {
+        self.combine_assign(other, |a, b| a & b)
+    }
+
+    /// Like [`BaseBitSlice::bitand_assign`], but whether either bit is `true`.
+    pub fn bitor_assign<M2: Mutability, A2: Aliasing>(
+        &mut self,
+        other: &BaseBitSlice<'_, M2, A2>,
+    ) {
+        self.combine_assign(other, |a, b| a | b)
+    }
+
+    /// Like [`BaseBitSlice::bitand_assign`], but whether the bits differ.
+    pub fn bitxor_assign<M2: Mutability, A2: Aliasing>(
+        &mut self,
+        other: &BaseBitSlice<'_, M2, A2>,
+    ) {
+        self.combine_assign(other, |a, b| a ^ b)
+    }
+
+    /// Sets the bits of this slice to themselves combined bitwise with those
+    /// of `other` by `op`, storing only the bits in this slice.
+    fn combine_assign<M2: Mutability, A2: Aliasing>(
+        &mut self,
+        other: &BaseBitSlice<'_, M2, A2>,
+        op: impl Fn(u8, u8) -> u8,
+    ) {
+        assert!(
+            self.len() == other.len(),
+            "bit slice lengths differ: {} and {}",
+            self.len(),
+            other.len()
+        );
+        let same_offset = self.bits.start % 8 == other.bits.start % 8;
+        let dst = self.reborrow_mut().into_raw_bytes();
+        let combine = |dst: *mut u8, bits: ByteBitRange, value: u8| {
+            let is_inner = bits.len() == 8;
+            unsafe {
+                let current = A::load_byte::<M>(dst, is_inner);
+                let value = op(current, value);
+                A::store_byte::<M>(dst, is_inner, value, bits.mask());
+            }
+        };
+        if same_offset {
+            // With the same offset, both slices have the same bit ranges in
+            // each of their bytes, so whole bytes can be combined.
+            for ((dst, bits), (src, _)) in dst.zip(other.raw_bytes()) {
+                let is_inner = bits.len() == 8;
+                let value = unsafe { A2::load_byte::<M2>(src, is_inner) };
+                combine(dst, bits, value);
+            }
+            return;
+        }
+        let mut src = other.bits();
+        for (dst, bits) in dst {
+            let value = (bits.start..bits.end)
+                .zip(&mut src)
+                .fold(0, |value, (bit, set)| value | u8::from(set) << bit);
+            combine(dst, bits, value);
+        }
+    }
 }
 
 impl<'a, M: MutMutability, A: UnaliasedInnerBytesAliasing>
@@ -722,6 +1455,56 @@ impl<'a, M: ConstMutability, A: Aliasing> BaseBitSlice<'a, M, A> {
         }
     }
 
+    /// A safe version of [`BaseBitSlice::from_bytes`] for bytes owned by a
+    /// `Vec`.
+    ///
+    /// Trait bounds on `M` and `A` cannot make `from_bytes` safe, since any
+    /// bit slice can be converted into one that reads its edges atomically
+    /// (e.g. `into_sync` followed by `split_at`), and a `&[u8]` may always
+    /// refer to read-only memory. A `Vec`'s buffer is allocated by the global
+    /// allocator, so it is never read-only, and any bit slice may be derived
+    /// from the returned one. For bytes elsewhere (e.g. on the stack), use
+    /// [`BaseBitSlice::from_bytes_mut`].
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if an out-of-bounds bit range is passed, or if
+    /// the bit range is invalid for the edge aliasing type (e.g.
+    /// `JustAnEdge`).
+    ///
+    /// ```rust
+    /// # use bitmap::{AliasedBitSlice, BitSlice, ConstSync, ConstUnsync};
+    /// let bytes = vec![42];
+    /// let slice = AliasedBitSlice::<ConstSync>::from_vec(&bytes, 0..6);
+    /// assert_eq!(
+    ///     slice.bits().collect::<Vec<bool>>(),
+    ///     [false, true, false, true, false, true]
+    /// );
+    /// let slice = BitSlice::<ConstUnsync>::from_vec(&bytes, 1..3);
+    /// assert_eq!(slice.bits().collect::<Vec<bool>>(), [true, false]);
+    /// ```
+    ///
+    /// Bytes that may be in read-only memory are not accepted:
+    ///
+    /// ```rust,compile_fail,E0308
+    /// # use bitmap::{AliasedBitSlice, ConstSync};
+    /// static BYTES: [u8; 1] = [42];
+    /// let slice = AliasedBitSlice::<ConstSync>::from_vec(&BYTES, 0..6);
+    /// ```
+    ///
+    /// And the returned slice is immutable:
+    ///
+    /// ```rust,compile_fail,E0599
+    /// # use bitmap::{BitSlice, MutableSync};
+    /// let bytes = vec![42];
+    /// let slice = BitSlice::<MutableSync>::from_vec(&bytes, 0..6);
+    /// ```
+    #[allow(clippy::ptr_arg)]
+    pub fn from_vec(bytes: &'a Vec<u8>, bits: impl RangeBounds<usize>) -> Self {
+        // SAFETY: `bytes` is not in read-only memory (see above).
+        unsafe { Self::from_bytes(bytes, bits) }
+    }
+
     /// Produce an immutable [`BaseBitSlice`] referencing static memory of a
     /// value.
     ///
@@ -873,9 +1656,52 @@ impl<'a, M: MutMutability, A: UnaliasedAliasing> BaseBitSlice<'a, M, A> {
             std::slice::from_raw_parts_mut(ptr, byte_idx_end - byte_idx_start)
         }
     }
-}
 
-fn div_ceil_8(val: usize) -> usize {
+    /// Sets the bits of partially referenced bytes that are not in this slice
+    /// to `value`, so that the bytes returned by [`BaseBitSlice::as_bytes`]
+    /// are deterministic (e.g. for serialization).
+    ///
+    /// Bits in this slice are not changed.
+    pub fn normalize_edges(&mut self, value: bool) {
+        // Masks of the bits outside of this slice in the first and last byte.
+        let first_mask = (1u8 << (self.bits.start % 8)) - 1;
+        let last_mask = match self.bits.end % 8 {
+            0 => 0,
+            end_bit_idx => !((1u8 << end_bit_idx) - 1),
+        };
+        let set = |byte: &mut u8, mask: u8| {
+            if value {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        };
+        match self.as_bytes_mut() {
+            [] => {}
+            [byte] => set(byte, first_mask | last_mask),
+            [first, .., last] => {
+                set(first, first_mask);
+                set(last, last_mask);
+            }
+        }
+    }
+}
+
+/// Sets the (nonempty) range of `bits` in `bytes` to `true`.
+fn set_bit_range(bytes: &mut [u8], bits: Range<usize>) {
+    let (first, last) = (bits.start / 8, (bits.end - 1) / 8);
+    let start = (bits.start % 8) as u8;
+    let end = ((bits.end - 1) % 8 + 1) as u8;
+    if first == last {
+        bytes[first] |= ByteBitRange { start, end }.mask();
+    } else {
+        bytes[first] |= ByteBitRange { start, end: 8 }.mask();
+        bytes[first + 1..last].fill(0xff);
+        bytes[last] |= ByteBitRange { start: 0, end }.mask();
+    }
+}
+
+fn div_ceil_8(val: usize) -> usize {
     if val % 8 == 0 { val / 8 } else { (val / 8) + 1 }
 }
 
@@ -930,12 +1756,31 @@ impl<'a, M: MutMutability, A: Aliasing> BitMapView<'a, M, A> {
         transmute!(self as BitMapView)
     }
 
-    pub fn rows_mut(&mut self) -> impl Iterator<Item = BaseBitSlice<'_, M, A>> {
+    pub fn rows_mut(&mut self) -> Rows<'_, M, A> {
         self.reborrow_mut().into_rows()
     }
 
+    /// Like [`BitMapView::chunks`], but the slices are mutable.
+    pub fn chunks_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (usize, usize, BaseBitSlice<'_, M, A>)> {
+        self.reborrow_mut().into_chunks()
+    }
+
     pub fn fill(&mut self, value: bool) {
-        self.rows_mut().for_each(|mut row| row.fill(value));
+        self.chunks_mut().for_each(|(_, _, mut slice)| slice.fill(value));
+    }
+
+    /// Sets the bit at `(row, col)`, relative to this view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(row, col)` is outside of this view.
+    pub fn set(&mut self, (row, col): (usize, usize), value: bool) {
+        let (byte, is_inner, bit_idx) = self.locate((row, col));
+        let value = if value { 0xff } else { 0 };
+        let mask = 1 << bit_idx;
+        unsafe { A::store_byte::<M>(byte.as_ptr(), is_inner, value, mask) }
     }
 }
 
@@ -955,59 +1800,147 @@ impl<'a, M: Mutability, A: Aliasing> BitMapView<'a, M, A> {
         transmute!(self as BitMapView)
     }
 
-    pub fn into_rows(self) -> impl Iterator<Item = BaseBitSlice<'a, M, A>> {
-        self.rows.into_iter().map(move |row| {
-            let start_byte_idx = self.stride.checked_mul(row).unwrap();
-            let data =
-                NonNull::new(self.data.as_ptr().wrapping_add(start_byte_idx))
-                    .unwrap();
-            BaseBitSlice {
-                data,
-                bits: self.columns,
-                _lifetime: PhantomData,
-                _mutability: PhantomData,
-                _edge_aliasing: PhantomData,
-            }
-        })
+    /// Returns the bit at `(row, col)`, relative to this view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(row, col)` is outside of this view.
+    pub fn get(&self, (row, col): (usize, usize)) -> bool {
+        let (byte, is_inner, bit_idx) = self.locate((row, col));
+        let byte = unsafe { A::load_byte::<M>(byte.as_ptr(), is_inner) };
+        (byte & (1 << bit_idx)) != 0
+    }
+
+    /// Returns the byte containing the bit at `(row, col)` relative to this
+    /// view, whether that byte is wholly referenced by this view's columns,
+    /// and the index of the bit in that byte.
+    fn locate(&self, (row, col): (usize, usize)) -> (NonNull<u8>, bool, u8) {
+        if row >= self.rows.len() || col >= self.columns.len() {
+            panic!("index out of range");
+        }
+        let row = self.rows.start + row;
+        let col = self.columns.start + col;
+        let byte_col = col / 8;
+        let is_inner = byte_col * 8 >= self.columns.start
+            && byte_col * 8 + 8 <= self.columns.end;
+        let byte_idx = self.stride * row + byte_col;
+        let byte =
+            NonNull::new(self.data.as_ptr().wrapping_add(byte_idx)).unwrap();
+        (byte, is_inner, (col % 8) as u8)
+    }
+
+    pub fn into_rows(self) -> Rows<'a, M, A> {
+        Rows { view: self }
     }
 
     /// This can inherit the aliasing type, because it takes &self so TODO.
-    pub fn rows(&self) -> impl Iterator<Item = BaseBitSlice<'_, M::Const, A>> {
+    pub fn rows(&self) -> Rows<'_, M::Const, A> {
         self.reborrow().into_rows()
     }
 
-    #[cfg(any())]
+    /// Returns the bits of `row` (not relative to this view). If this view is
+    /// mutable, the caller must not return the same row more than once.
+    fn row_unchecked(&self, row: usize) -> BaseBitSlice<'a, M, A> {
+        let start_byte_idx = self.stride.checked_mul(row).unwrap();
+        let data =
+            NonNull::new(self.data.as_ptr().wrapping_add(start_byte_idx))
+                .unwrap();
+        BaseBitSlice {
+            data,
+            bits: self.columns,
+            _lifetime: PhantomData,
+            _mutability: PhantomData,
+            _edge_aliasing: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over this view as maximal contiguous bit slices,
+    /// with the (row, col) of each slice's first bit relative to this view.
+    ///
+    /// Rows are `stride` bytes apart, so each slice is currently one whole row
+    /// of the view, but consumers should not rely on that. Empty slices are
+    /// not yielded, so an empty view yields nothing.
+    pub fn into_chunks(
+        self,
+    ) -> impl Iterator<Item = (usize, usize, BaseBitSlice<'a, M, A>)> {
+        let rows = if self.columns.is_empty() { 0 } else { self.rows.len() };
+        self.into_rows().take(rows).enumerate().map(|(y, row)| (y, 0, row))
+    }
+
+    pub fn chunks(
+        &self,
+    ) -> impl Iterator<Item = (usize, usize, BaseBitSlice<'_, M::Const, A>)>
+    {
+        self.reborrow().into_chunks()
+    }
+
+    /// Returns the number of `true` bits in this view.
     pub fn count_ones(&self) -> usize {
         self.chunks().map(|(_y, _x, slice)| slice.count_ones()).sum()
     }
 
-    #[cfg(any())]
-    pub fn count_ones(&self) -> usize {
-        let mut count_ones = 0;
-        'rows: for row in 0..self.height {
-            let start_byte = row * self.stride;
-            for (byte_col, byte) in
-                self.data[start_byte..][..self.stride].iter().enumerate()
-            {
-                if byte_col * 8 + 7 < self.width {
-                    count_ones += byte.count_ones() as usize;
-                } else {
-                    for bit_col in 0..8 {
-                        let col = (byte_col << 3) | bit_col;
-                        if col >= self.width {
-                            continue 'rows;
-                        }
-                        if byte & (1 << bit_col) != 0 {
-                            count_ones += 1;
-                        }
-                    }
+    /// Calls `f` with each index whose bit is `true` (row, col), relative to
+    /// this view.
+    pub fn for_each_true(&self, mut f: impl FnMut(usize, usize)) {
+        for (y, x, slice) in self.chunks() {
+            let mut col = x;
+            for (byte, bits) in slice.raw_bytes() {
+                let mut byte =
+                    unsafe { A::load_byte::<M>(byte, bits.len() == 8) }
+                        & bits.mask();
+                while byte != 0 {
+                    let bit_idx = byte.trailing_zeros() as usize;
+                    f(y, col + bit_idx - usize::from(bits.start));
+                    byte &= byte - 1;
                 }
+                col += bits.len();
             }
         }
-        count_ones
     }
 }
 
+/// An iterator over the rows of a [`BitMapView`], as bit slices.
+pub struct Rows<'a, M: Mutability, A: Aliasing> {
+    /// The rows that have not been yielded yet.
+    view: BitMapView<'a, M, A>,
+}
+
+impl<'a, M: Mutability, A: Aliasing> Iterator for Rows<'a, M, A> {
+    type Item = BaseBitSlice<'a, M, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.view.rows.is_empty() {
+            return None;
+        }
+        let row = self.view.rows.start;
+        self.view.rows.start += 1;
+        Some(self.view.row_unchecked(row))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.len();
+        (count, Some(count))
+    }
+}
+
+impl<'a, M: Mutability, A: Aliasing> ExactSizeIterator for Rows<'a, M, A> {
+    fn len(&self) -> usize {
+        self.view.rows.len()
+    }
+}
+
+impl<'a, M: Mutability, A: Aliasing> DoubleEndedIterator for Rows<'a, M, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.view.rows.is_empty() {
+            return None;
+        }
+        self.view.rows.end -= 1;
+        Some(self.view.row_unchecked(self.view.rows.end))
+    }
+}
+
+impl<'a, M: Mutability, A: Aliasing> FusedIterator for Rows<'a, M, A> {}
+
 pub struct Bits<'a, M: Mutability, A: Aliasing> {
     /// If this is `Left`, it contains the next bits to be returned by `next`;
     /// this is usually used when `A::SEMANTICALLY_ALIASED` is `false`.
@@ -1058,15 +1991,44 @@ fn byte_to_bits(byte: u8) -> std::array::IntoIter<bool, 8> {
 fn byte_to_bits_with_range(
     byte: u8,
     bitrange: ByteBitRange,
-) -> std::array::IntoIter<bool, 8> {
-    let mut bits = byte_to_bits(byte);
-    for _ in 0..bitrange.start {
-        bits.next();
-    }
-    for _ in bitrange.end..8 {
-        bits.next_back();
+) -> impl DoubleEndedIterator<Item = bool> {
+    bitrange.iter().map(move |bit| (byte & (1 << bit)) != 0)
+}
+
+/// The order of the bits within each byte, for [`pack_bits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// The first bit is the least significant, as in [`Bits`] and [`BitMap`].
+    Lsb0,
+    /// The first bit is the most significant, as in e.g. PBM images.
+    Msb0,
+}
+
+/// Packs `bits` into bytes in `order`, zero-padding the last byte. This is
+/// the inverse of [`Bits`] (with [`BitOrder::Lsb0`]).
+///
+/// ```rust
+/// # use bitmap::{pack_bits, BitOrder};
+/// let bits = [true, false, true, true, false, false, false, false, true];
+/// assert_eq!(pack_bits(bits.into_iter(), BitOrder::Lsb0), [0b1101, 0b1]);
+/// assert_eq!(
+///     pack_bits(bits.into_iter(), BitOrder::Msb0),
+///     [0b1011_0000, 0b1000_0000]
+/// );
+/// ```
+pub fn pack_bits(bits: impl Iterator<Item = bool>, order: BitOrder) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(div_ceil_8(bits.size_hint().0));
+    for (idx, bit) in bits.enumerate() {
+        let shift = match order {
+            BitOrder::Lsb0 => idx % 8,
+            BitOrder::Msb0 => 7 - idx % 8,
+        };
+        if idx % 8 == 0 {
+            bytes.push(0);
+        }
+        *bytes.last_mut().unwrap() |= (bit as u8) << shift;
     }
-    bits
+    bytes
 }
 
 impl<'a, M: Mutability, A: Aliasing> Iterator for Bits<'a, M, A> {
@@ -1259,10 +2221,346 @@ pub type AliasedBitSlice<'a, M> = BaseBitSlice<'a, M, Aliased>;
 #[cfg(test)]
 mod tests {
     use crate::{
-        mutability::{ConstSync, MutableSync},
-        BaseBitSlice, BitSlice, ByteBitRange, Unaliased,
+        mutability::{ConstSync, ConstUnsync, MutableSync, MutableUnsync},
+        pack_bits, AliasedBitSlice, BaseBitSlice, BitMap, BitOrder, BitSlice,
+        ByteBitRange, CopyRange, CopyRangeExt, ParseError, Unaliased,
+        UnaliasedBitSlice,
     };
 
+    /// A bitmap with an irregular pattern, and a width that is not a multiple
+    /// of 8.
+    fn pattern(height: usize, width: usize) -> BitMap {
+        let mut bitmap = BitMap::new(height, width).unwrap();
+        for row in 0..height {
+            for col in 0..width {
+                bitmap.set((row, col), (row * 7 + col * 3) % 5 < 2);
+            }
+        }
+        bitmap
+    }
+
+    #[test]
+    fn count_ones_per_row() {
+        for (height, width) in [(0, 5), (3, 0), (4, 8), (5, 13), (7, 30)] {
+            let mut bitmap = pattern(height, width);
+            // Fill the unused bits at the end of each row, which must not be
+            // counted.
+            if width % 8 != 0 {
+                for row in bitmap.data.chunks_mut(bitmap.stride) {
+                    row[width / 8] |= !0 << (width % 8);
+                }
+            }
+
+            let per_row = bitmap.count_ones_per_row();
+            assert_eq!(per_row.len(), height);
+            assert_eq!(per_row.iter().sum::<usize>(), bitmap.count_ones());
+            for (row, &count) in per_row.iter().enumerate() {
+                let expected =
+                    (0..width).filter(|&col| bitmap.get((row, col))).count();
+                assert_eq!(count, expected, "row {row} of {height}x{width}");
+            }
+        }
+    }
+
+    #[test]
+    fn downscale() {
+        for (height, width) in [(0, 5), (3, 0), (8, 8), (7, 13), (9, 30)] {
+            let bitmap = pattern(height, width);
+            for factor in 1..=5 {
+                for threshold in [0.0, 0.25, 0.5, 1.0] {
+                    let downscaled = bitmap.downscale(factor, threshold);
+                    let size =
+                        (height.div_ceil(factor), width.div_ceil(factor));
+                    assert_eq!(downscaled.size(), size);
+                    for (block_row, block_col) in (0..size.0)
+                        .flat_map(|r| (0..size.1).map(move |c| (r, c)))
+                    {
+                        let block = |start: usize, len: usize| {
+                            start * factor..len.min((start + 1) * factor)
+                        };
+                        let rows = block(block_row, height);
+                        let cols = block(block_col, width);
+                        let area = rows.len() * cols.len();
+                        let count = rows
+                            .map(|row| {
+                                cols.clone()
+                                    .filter(|&col| bitmap.get((row, col)))
+                                    .count()
+                            })
+                            .sum::<usize>();
+                        let expected = if threshold == 0.0 {
+                            count > 0
+                        } else {
+                            count as f32 / area as f32 >= threshold
+                        };
+                        assert_eq!(
+                            downscaled.get((block_row, block_col)),
+                            expected,
+                            "{height}x{width} / {factor} at {threshold}: \
+                             ({block_row}, {block_col})"
+                        );
+                    }
+                }
+            }
+        }
+
+        // The bottom right block is only 2x2, so one bit is a quarter of it.
+        let mut bitmap = BitMap::new(5, 5).unwrap();
+        bitmap.set((4, 4), true);
+        assert!(bitmap.downscale(3, 0.25).get((1, 1)));
+        assert!(!bitmap.downscale(3, 0.3).get((1, 1)));
+        assert_eq!(bitmap.downscale(3, 0.0).count_ones(), 1);
+    }
+
+    #[test]
+    fn upscale() {
+        for (height, width) in [(0, 5), (3, 0), (8, 8), (7, 13), (9, 30)] {
+            let bitmap = pattern(height, width);
+            for factor in 1..=9 {
+                let upscaled = bitmap.upscale(factor).unwrap();
+                assert_eq!(upscaled.size(), (height * factor, width * factor));
+                for row in 0..height * factor {
+                    for col in 0..width * factor {
+                        assert_eq!(
+                            upscaled.get((row, col)),
+                            bitmap.get((row / factor, col / factor)),
+                            "{height}x{width} * {factor}: ({row}, {col})"
+                        );
+                    }
+                }
+                let round_trip = upscaled.downscale(factor, 1.0);
+                assert_eq!(round_trip.size(), bitmap.size());
+                assert_eq!(round_trip.data, bitmap.data);
+            }
+        }
+
+        // Unused bits after the end of a row are not upscaled.
+        let mut bitmap = BitMap::new(2, 3).unwrap();
+        bitmap.data.fill(0b1111_1010);
+        let upscaled = bitmap.upscale(2).unwrap();
+        assert_eq!(upscaled.count_ones(), 2 * 2 * 2);
+        assert_eq!(upscaled.data, [0b0000_1100; 4]);
+
+        assert!(BitMap::new(1, 2).unwrap().upscale(usize::MAX).is_none());
+        assert!(BitMap::new(2, 1).unwrap().upscale(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn apply_mask() {
+        for (height, width) in [(0, 5), (3, 0), (8, 8), (7, 13), (9, 30)] {
+            let bitmap = pattern(height, width);
+            // Everything except a rectangle, which is clipped to the bitmap.
+            let rect = |(row, col): (usize, usize)| {
+                (2..5).contains(&row) && (3..11).contains(&col)
+            };
+            let mut mask = BitMap::new(height, width).unwrap();
+            for row in 0..height {
+                for col in 0..width {
+                    mask.set((row, col), !rect((row, col)));
+                }
+            }
+            let mut masked = bitmap.clone();
+            masked.apply_mask(&mask);
+            for row in 0..height {
+                for col in 0..width {
+                    let expected = bitmap.get((row, col)) && !rect((row, col));
+                    assert_eq!(masked.get((row, col)), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitwise_ops() {
+        let (height, width) = (3, 20);
+        let a = pattern(height, width);
+        let mut b = BitMap::new(height, width).unwrap();
+        for row in 0..height {
+            for col in 0..width {
+                b.set((row, col), (row + col) % 3 == 0);
+            }
+        }
+        // Set `b`'s padding, which must not leak into the results.
+        for row in 0..height {
+            b.data[row * b.stride + 2] |= 0xf0;
+        }
+        for op in ['&', '|', '^'] {
+            let mut result = a.clone();
+            let bit_op = match op {
+                '&' => {
+                    result &= &b;
+                    |x, y| x & y
+                }
+                '|' => {
+                    result |= &b;
+                    |x, y| x | y
+                }
+                _ => {
+                    result ^= &b;
+                    |x: bool, y: bool| x ^ y
+                }
+            };
+            for row in 0..height {
+                for col in 0..width {
+                    let expected = bit_op(a.get((row, col)), b.get((row, col)));
+                    assert_eq!(result.get((row, col)), expected);
+                }
+                assert_eq!(result.data[row * result.stride + 2] & 0xf0, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let (height, width) = (6, 13);
+        let a = pattern(height, width);
+        assert_eq!(a.diff(&a).count_ones(), 0);
+        let mut b = a.clone();
+        for (row, col) in [(0, 0), (2, 7), (5, 12), (3, 8)] {
+            b.set((row, col), !b.get((row, col)));
+        }
+        let diff = a.diff(&b);
+        assert_eq!(diff.count_ones(), 4);
+        assert_eq!(a.diff_count(&b), 4);
+        assert_eq!(b.diff_count(&a), 4);
+        assert!(diff.get((2, 7)) && !diff.get((2, 6)));
+    }
+
+    #[test]
+    fn fill() {
+        for (height, width) in [(3, 10), (2, 16), (0, 4), (4, 0)] {
+            let mut bitmap = pattern(height, width);
+            bitmap.fill(true);
+            assert_eq!(bitmap.count_ones_per_row(), vec![width; height]);
+            // The padding stays clear.
+            for row in bitmap.data.chunks(bitmap.stride.max(1)) {
+                let bits = row.iter().map(|byte| byte.count_ones() as usize);
+                assert_eq!(bits.sum::<usize>(), width);
+            }
+            bitmap.clear();
+            assert_eq!(bitmap.count_ones(), 0);
+            assert!(bitmap == BitMap::new(height, width).unwrap());
+        }
+    }
+
+    #[test]
+    fn eq_ignores_padding() {
+        for (height, width) in [(0, 5), (3, 0), (4, 8), (5, 13)] {
+            let fresh = pattern(height, width);
+            let mut reused = BitMap::new(height, width).unwrap();
+            reused.as_view_mut::<MutableUnsync>().fill(true);
+            reused.as_view_mut::<MutableUnsync>().fill(false);
+            for row in 0..height {
+                for col in 0..width {
+                    reused.set((row, col), fresh.get((row, col)));
+                }
+                // Garbage in the padding, if there is any.
+                if width % 8 != 0 {
+                    reused.data[(row + 1) * reused.stride - 1] |=
+                        !((1u8 << (width % 8)) - 1);
+                }
+            }
+            assert!(fresh == reused, "{height}x{width}");
+            if height > 0 && width > 0 {
+                let (row, col) = (height - 1, width - 1);
+                reused.set((row, col), !fresh.get((row, col)));
+                assert!(fresh != reused, "{height}x{width}");
+            }
+        }
+        assert!(BitMap::new(2, 3) != BitMap::new(3, 2));
+    }
+
+    #[test]
+    #[should_panic = "bitmap sizes differ: (3, 4) and (4, 3)"]
+    fn diff_size_mismatch() {
+        BitMap::new(3, 4).unwrap().diff(&BitMap::new(4, 3).unwrap());
+    }
+
+    #[test]
+    #[should_panic = "bitmap sizes differ: (3, 4) and (4, 3)"]
+    fn apply_mask_size_mismatch() {
+        let mut bitmap = BitMap::new(3, 4).unwrap();
+        bitmap.apply_mask(&BitMap::new(4, 3).unwrap());
+    }
+
+    #[test]
+    fn byte_bit_range_iter() {
+        for start in 0..=8 {
+            for end in start..=8 {
+                let range = ByteBitRange::from(start..end);
+                let expected = (start..end).collect::<Vec<u8>>();
+                assert_eq!(range.iter().collect::<Vec<_>>(), expected);
+                assert_eq!(range.iter().len(), range.len());
+                let reversed = range.iter().rev().collect::<Vec<_>>();
+                assert!(reversed.iter().eq(expected.iter().rev()));
+            }
+        }
+        // An `end` past the byte is cut short, as in `mask`.
+        let range = ByteBitRange::from(5..12);
+        assert_eq!(range.iter().collect::<Vec<_>>(), [5, 6, 7]);
+    }
+
+    #[test]
+    fn byte_bit_range_intersection() {
+        let ranges = (0..=8)
+            .flat_map(|start| (0..=9).map(move |end| (start, end)))
+            .map(|(start, end)| ByteBitRange::from(start..end));
+        for a in ranges.clone() {
+            for idx in 0..10 {
+                assert_eq!(a.contains(idx), a.iter().any(|i| i == idx));
+            }
+            for b in ranges.clone() {
+                let both = a.intersection(b);
+                assert_eq!(both.mask(), a.mask() & b.mask(), "{a:?} {b:?}");
+                for idx in 0..8 {
+                    let expected = a.contains(idx) && b.contains(idx);
+                    assert_eq!(both.contains(idx), expected);
+                }
+                if both.is_empty() {
+                    assert_eq!((both.start, both.end), (0, 0));
+                } else {
+                    assert!(both.start < both.end && both.end <= 8);
+                }
+            }
+        }
+        let both = ByteBitRange::from(1..5).intersection((3..8).into());
+        assert_eq!((both.start, both.end), (3, 5));
+        let disjoint = ByteBitRange::from(0..3).intersection((3..8).into());
+        assert_eq!((disjoint.start, disjoint.end), (0, 0));
+    }
+
+    #[test]
+    fn copy_range_intersect() {
+        let a = CopyRange::from(2..10usize);
+        let overlapping = a.intersect(CopyRange::from(6..14));
+        assert_eq!(overlapping.into_range(), 6..10);
+        assert_eq!(overlapping.len(), 4);
+        assert_eq!(a.intersect(CopyRange::from(4..5)).into_range(), 4..5);
+        assert_eq!(a.intersect(a).into_range(), 2..10);
+        // Disjoint ranges give an empty range, whichever comes first.
+        let c = CopyRange::from(12..20);
+        for (x, y) in [(a, c), (c, a)] {
+            let empty = x.intersect(y);
+            assert!(empty.is_empty());
+            assert_eq!(empty.into_range(), 12..12);
+        }
+        // Ranges that only touch do not overlap.
+        assert!(a.intersect(CopyRange::from(10..12)).is_empty());
+    }
+
+    #[test]
+    fn copy_range_contains() {
+        let a = CopyRange::from(2..10usize);
+        let b = CopyRange::from(6..14usize);
+        let both = a.intersect(b);
+        for i in 0..16 {
+            assert_eq!(a.contains(&i), (2..10).contains(&i));
+            assert_eq!(both.contains(&i), a.contains(&i) && b.contains(&i));
+        }
+        let disjoint = a.intersect(CopyRange::from(12..20));
+        assert!((0..24).all(|i| !disjoint.contains(&i)));
+    }
+
     #[test]
     fn from_value() {
         #[cfg(not(miri))]
@@ -1299,4 +2597,577 @@ mod tests {
             .collect::<Vec<bool>>();
         assert_eq!(slice.bits().collect::<Vec<_>>(), bits);
     }
+
+    #[test]
+    fn rows_as_bits() {
+        let bitmap = pattern(5, 13);
+        let rows = bitmap
+            .rows_as_bits()
+            .map(|row| row.collect::<Vec<bool>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 5);
+        for (row, bits) in rows.iter().enumerate() {
+            assert_eq!(bits.len(), 13);
+            for (col, &bit) in bits.iter().enumerate() {
+                assert_eq!(bit, bitmap.get((row, col)), "({row}, {col})");
+            }
+        }
+    }
+
+    #[test]
+    fn row_slices() {
+        let bitmap = pattern(5, 13);
+        assert_eq!(bitmap.rows().len(), 5);
+        for (row, slice) in bitmap.rows().enumerate() {
+            assert_eq!(slice.len(), 13);
+            let bits = bitmap.row(row).bits().collect::<Vec<bool>>();
+            assert_eq!(slice.into_bits().collect::<Vec<bool>>(), bits);
+            for (col, &bit) in bits.iter().enumerate() {
+                assert_eq!(bit, bitmap.get((row, col)), "({row}, {col})");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic = "index out of range"]
+    fn row_out_of_range() {
+        pattern(5, 13).row(5);
+    }
+
+    #[test]
+    fn iter_true_in_row() {
+        for (height, width) in [(3, 1), (4, 8), (5, 13), (7, 30)] {
+            let bitmap = pattern(height, width);
+            for row in 0..height {
+                let expected = (0..width)
+                    .filter(|&col| bitmap.get((row, col)))
+                    .collect::<Vec<_>>();
+                let cols = bitmap.iter_true_in_row(row).collect::<Vec<_>>();
+                assert_eq!(cols, expected, "{height}x{width} row {row}");
+            }
+        }
+        let mut bitmap = BitMap::new(2, 20).unwrap();
+        assert_eq!(bitmap.iter_true_in_row(1).next(), None);
+        bitmap.set((1, 19), true);
+        assert!(bitmap.iter_true_in_row(1).eq([19]));
+    }
+
+    #[test]
+    #[should_panic = "index out of range"]
+    fn iter_true_in_row_out_of_range() {
+        let _ = pattern(5, 13).iter_true_in_row(5);
+    }
+
+    #[test]
+    fn next_set_bit() {
+        let mut bitmap = BitMap::new(5, 13).unwrap();
+        assert_eq!(bitmap.find_first_set(), None);
+        bitmap.set((4, 11), true);
+        assert_eq!(bitmap.find_first_set(), Some((4, 11)));
+        assert_eq!(bitmap.next_set_bit((4, 11)), Some((4, 11)));
+        assert_eq!(bitmap.next_set_bit((4, 12)), None);
+        assert_eq!(bitmap.next_set_bit((4, 13)), None);
+        assert_eq!(bitmap.next_set_bit((5, 0)), None);
+
+        // Padding bits are never returned, even if they are somehow set.
+        bitmap.data[1] |= 0x80;
+        bitmap.set((1, 3), true);
+        assert_eq!(bitmap.find_first_set(), Some((1, 3)));
+        assert_eq!(bitmap.next_set_bit((1, 4)), Some((4, 11)));
+        assert_eq!(bitmap.next_set_bit((0, 9)), Some((1, 3)));
+    }
+
+    #[test]
+    fn split_at_checked() {
+        let bytes = vec![0b1010_0110, 0b0011_1100];
+        let slice = BitSlice::<ConstSync>::from_vec(&bytes, 3..14);
+        assert!(slice.split_at_checked(12).is_none());
+        for at in 0..=11 {
+            let (head, tail) = slice.split_at_checked(at).unwrap();
+            let (expected_head, expected_tail) = slice.split_at(at);
+            assert_eq!(head.len(), at);
+            assert_eq!(tail.len(), 11 - at);
+            assert!(head.bits().eq(expected_head.bits()));
+            assert!(tail.bits().eq(expected_tail.bits()));
+        }
+    }
+
+    #[test]
+    #[should_panic = "split index 12 out of range for length 11"]
+    fn split_at_out_of_range() {
+        let bytes = vec![0b1010_0110, 0b0011_1100];
+        BitSlice::<ConstSync>::from_vec(&bytes, 3..14).split_at(12);
+    }
+
+    #[test]
+    fn packed_bytes() {
+        // A small xorshift, so the bitmaps vary without extra dependencies.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for height in 0..6 {
+            for width in 0..20 {
+                let mut bitmap = BitMap::new(height, width).unwrap();
+                for row in 0..height {
+                    for col in 0..width {
+                        bitmap.set((row, col), next() % 3 == 0);
+                    }
+                }
+                let packed = bitmap.to_packed_bytes();
+                let expected_len = 16 + (height * width).div_ceil(8);
+                assert_eq!(packed.len(), expected_len, "{height}x{width}");
+                let unpacked = BitMap::from_packed_bytes(&packed).unwrap();
+                assert!(unpacked == bitmap, "{height}x{width}");
+                assert_eq!(unpacked.to_packed_bytes(), packed);
+
+                // Padding bits are not written, and not read back.
+                if width % 8 != 0 && height > 0 {
+                    let mut padded = bitmap.clone();
+                    padded.data[padded.stride - 1] |= 0x80;
+                    assert_eq!(padded.to_packed_bytes(), packed);
+                }
+                let mut trailing = packed.clone();
+                if (height * width) % 8 != 0 {
+                    *trailing.last_mut().unwrap() |= 0x80;
+                    let unpacked = BitMap::from_packed_bytes(&trailing);
+                    assert_eq!(unpacked.unwrap().data, bitmap.data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_bytes_errors() {
+        let packed = pattern(3, 5).to_packed_bytes();
+        assert_eq!(
+            BitMap::from_packed_bytes(&packed[..15]).err(),
+            Some(ParseError::MissingHeader)
+        );
+        assert_eq!(
+            BitMap::from_packed_bytes(&packed[..17]).err(),
+            Some(ParseError::WrongLength { expected: 18, actual: 17 })
+        );
+        let mut huge = vec![0xff; 16];
+        huge.push(0);
+        let err = BitMap::from_packed_bytes(&huge).err().unwrap();
+        assert_eq!(
+            err,
+            ParseError::TooLarge { height: u64::MAX, width: u64::MAX }
+        );
+        // A size that would fit, but without the data for it, is rejected
+        // before allocating.
+        let mut large = (1u64 << 20).to_le_bytes().to_vec();
+        large.extend((1u64 << 36).to_le_bytes());
+        large.push(0);
+        let err = BitMap::from_packed_bytes(&large).err().unwrap();
+        assert_eq!(
+            err,
+            ParseError::WrongLength { expected: 16 + (1 << 53), actual: 17 }
+        );
+    }
+
+    #[test]
+    fn view_rect() {
+        let bitmap = pattern(7, 19);
+        for (rows, cols) in [(0..7, 0..19), (2..5, 3..11), (6..7, 8..16)] {
+            let view = bitmap.view_rect(rows.clone(), cols.clone());
+            let mut count = 0;
+            for row in 0..rows.len() {
+                for col in 0..cols.len() {
+                    let index = (rows.start + row, cols.start + col);
+                    let expected = bitmap.get(index);
+                    assert_eq!(view.get((row, col)), expected);
+                    count += usize::from(expected);
+                }
+            }
+            assert_eq!(view.count_ones(), count, "{rows:?} {cols:?}");
+        }
+        for (rows, cols) in [(3..3, 0..19), (0..7, 19..19)] {
+            let view = bitmap.view_rect(rows, cols);
+            assert_eq!(view.chunks().count(), 0);
+            assert_eq!(view.count_ones(), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic = "columns 4..20 out of range for width 19"]
+    fn view_rect_out_of_range() {
+        pattern(7, 19).view_rect(0..7, 4..20);
+    }
+
+    #[test]
+    fn slice_count_ones() {
+        let bytes = [0b01001001, 0b11111111, 0b00100100, 0b10000001];
+        let bit = |i: usize| bytes[i / 8] >> (i % 8) & 1 == 1;
+        // Byte-aligned, partial bytes at either or both ends, and within a
+        // single byte.
+        for bits in [8..24, 0..32, 3..29, 3..32, 0..13, 2..6, 5..5] {
+            let expected = bits.clone().filter(|&i| bit(i)).count();
+            let mut bytes = bytes;
+            let slice =
+                BitSlice::<MutableSync>::from_bytes_mut(&mut bytes, bits);
+            assert_eq!(slice.count_ones(), expected);
+            assert_eq!(slice.bits().filter(|&b| b).count(), expected);
+        }
+    }
+
+    #[test]
+    fn copy_from() {
+        let src_bytes = [0b01001001, 0b10010110, 0b00100100, 0b11010011];
+        let bit = |i: usize| src_bytes[i / 8] >> (i % 8) & 1 == 1;
+        // Same and different offsets within bytes, including whole bytes.
+        for (src_bits, dst_start) in
+            [(3..29, 5), (3..29, 11), (0..32, 0), (8..24, 3), (2..6, 13)]
+        {
+            let len = src_bits.len();
+            let dst_bits = dst_start..dst_start + len;
+            for fill in [0x00, 0xff] {
+                let mut src = src_bytes;
+                let src = BitSlice::<MutableSync>::from_bytes_mut(
+                    &mut src,
+                    src_bits.clone(),
+                );
+                let mut dst_bytes = [fill; 5];
+                BitSlice::<MutableSync>::from_bytes_mut(
+                    &mut dst_bytes,
+                    dst_bits.clone(),
+                )
+                .copy_from(&src);
+                for i in 0..40 {
+                    let expected = match dst_bits.contains(&i) {
+                        true => bit(i - dst_start + src_bits.start),
+                        false => fill == 0xff,
+                    };
+                    let actual = dst_bytes[i / 8] >> (i % 8) & 1 == 1;
+                    assert_eq!(actual, expected, "{src_bits:?} -> {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitwise_assign() {
+        let a_bytes = [0b01001001, 0b10010110, 0b00100100, 0b11010011];
+        let b_bytes = [0b11100101, 0b00111010, 0b10011001, 0b01101100];
+        let bit = |bytes: &[u8], i: usize| bytes[i / 8] >> (i % 8) & 1 == 1;
+        // Same and different offsets within bytes, with partial edge bytes.
+        for (b_bits, a_start) in [(3..29, 5), (3..27, 7), (2..6, 13)] {
+            let len = b_bits.len();
+            let a_bits = a_start..a_start + len;
+            for op in ["and", "or", "xor"] {
+                let mut b = b_bytes;
+                let b = BitSlice::<MutableSync>::from_bytes_mut(
+                    &mut b,
+                    b_bits.clone(),
+                );
+                let mut result = a_bytes;
+                let mut a = BitSlice::<MutableSync>::from_bytes_mut(
+                    &mut result,
+                    a_bits.clone(),
+                );
+                let combine: fn(bool, bool) -> bool = match op {
+                    "and" => {
+                        a.bitand_assign(&b);
+                        |a, b| a & b
+                    }
+                    "or" => {
+                        a.bitor_assign(&b);
+                        |a, b| a | b
+                    }
+                    _ => {
+                        a.bitxor_assign(&b);
+                        |a, b| a ^ b
+                    }
+                };
+                for i in 0..32 {
+                    let expected = match a_bits.contains(&i) {
+                        true => combine(
+                            bit(&a_bytes, i),
+                            bit(&b_bytes, i - a_start + b_bits.start),
+                        ),
+                        false => bit(&a_bytes, i),
+                    };
+                    let case = format!("{op} {b_bits:?} -> {i}");
+                    assert_eq!(bit(&result, i), expected, "{case}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic = "bit slice lengths differ: 4 and 5"]
+    fn copy_from_wrong_length() {
+        let (mut a, mut b) = ([0u8], [0u8]);
+        BitSlice::<MutableSync>::from_bytes_mut(&mut a, 0..4)
+            .copy_from(&BitSlice::<MutableSync>::from_bytes_mut(&mut b, 0..5));
+    }
+
+    #[test]
+    fn take_skip() {
+        fn slice(bytes: &mut [u8; 3]) -> BitSlice<'_, MutableSync> {
+            BitSlice::<MutableSync>::from_bytes_mut(bytes, 2..21)
+        }
+        let bytes = [0b01001001, 0b10010010, 0b00100100];
+        let all = slice(&mut { bytes }).bits().collect::<Vec<bool>>();
+
+        let head = slice(&mut { bytes }).take(3).bits().collect::<Vec<_>>();
+        let tail = slice(&mut { bytes }).skip(3).bits().collect::<Vec<_>>();
+        assert_eq!(head, all[..3]);
+        assert_eq!(tail, all[3..]);
+
+        for n in [19, 20, usize::MAX] {
+            let head = slice(&mut { bytes }).take(n).bits().collect::<Vec<_>>();
+            assert_eq!(head, all);
+            assert_eq!(slice(&mut { bytes }).skip(n).len(), 0);
+        }
+    }
+
+    #[test]
+    fn view_get_set() {
+        use copy_range::CopyRange;
+
+        use crate::BitMapView;
+
+        let original = pattern(9, 21);
+        let mut bitmap = pattern(9, 21);
+        let (rows, columns) = (2..7, 3..19);
+        let mut view = BitMapView::<MutableSync, Unaliased> {
+            rows: CopyRange::from(rows.clone()),
+            columns: CopyRange::from(columns.clone()),
+            ..bitmap.as_view_mut()
+        };
+        for r in 0..rows.len() {
+            for c in 0..columns.len() {
+                let (row, col) = (rows.start + r, columns.start + c);
+                let expected = original.get((row, col));
+                assert_eq!(view.get((r, c)), expected);
+                view.set((r, c), !expected);
+                assert_eq!(view.get((r, c)), !expected);
+            }
+        }
+        // Only the bits in the view were changed.
+        for row in 0..9 {
+            for col in 0..21 {
+                let in_view = rows.contains(&row) && columns.contains(&col);
+                assert_eq!(
+                    bitmap.get((row, col)),
+                    original.get((row, col)) != in_view
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn is_semantically_aliased() {
+        let mut bytes = vec![0u8; 3];
+        let aliased = AliasedBitSlice::<ConstSync>::from_vec(&bytes, 2..20);
+        assert!(aliased.is_semantically_aliased());
+        let slice = BitSlice::<ConstSync>::from_vec(&bytes, 2..20);
+        assert!(!slice.is_semantically_aliased());
+        let unaliased =
+            UnaliasedBitSlice::<MutableSync>::from_bytes_mut(&mut bytes, 2..20);
+        assert!(!unaliased.is_semantically_aliased());
+    }
+
+    #[test]
+    fn count_differences_and_ones_where() {
+        // xorshift, so that the test does not need a rand dependency.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        let a = (0..16).map(|_| next() as u8).collect::<Vec<u8>>();
+        let b = (0..16).map(|_| next() as u8).collect::<Vec<u8>>();
+        for _ in 0..500 {
+            let len = next() % 100;
+            let (a_start, b_start) = (next() % 28, next() % 28);
+            // Exercise the aligned fast path as well.
+            let b_start = if next() % 2 == 0 { a_start } else { b_start };
+            let a = BitSlice::<ConstSync>::from_vec(&a, a_start..a_start + len);
+            let b = UnaliasedBitSlice::<ConstUnsync>::from_vec(
+                &b,
+                b_start..b_start + len,
+            );
+            let pairs = a.bits().zip(b.bits()).collect::<Vec<_>>();
+            let differences = pairs.iter().filter(|(a, b)| a != b).count();
+            let both = pairs.iter().filter(|&&(a, b)| a && b).count();
+            let case = format!("{a_start}..+{len} and {b_start}..+{len}");
+            assert_eq!(a.count_differences(&b), differences, "{case}");
+            assert_eq!(b.count_differences(&a), differences, "{case}");
+            assert_eq!(a.count_ones_where(&b), both, "{case}");
+            assert_eq!(b.count_ones_where(&a), both, "{case}");
+        }
+    }
+
+    #[test]
+    #[should_panic = "bit slice lengths differ: 5 and 6"]
+    fn count_differences_length_mismatch() {
+        let bytes = vec![0b1010_1010];
+        let a = BitSlice::<ConstSync>::from_vec(&bytes, 0..5);
+        let b = BitSlice::<ConstSync>::from_vec(&bytes, 1..7);
+        a.count_differences(&b);
+    }
+
+    #[test]
+    fn view_chunks() {
+        use std::marker::PhantomData;
+
+        use copy_range::CopyRange;
+
+        use crate::{AliasedEdgesOnly, BitMapView};
+
+        let original = pattern(9, 21);
+        // Partial-byte column ranges (including within a single byte), a
+        // byte-aligned range, and empty views.
+        for (rows, columns) in [
+            (2..7, 3..19),
+            (0..9, 0..21),
+            (1..4, 5..7),
+            (3..8, 8..16),
+            (4..4, 3..19),
+            (2..7, 6..6),
+        ] {
+            let mut bitmap = original.clone();
+            let whole = bitmap.as_view_mut::<MutableSync>();
+            // `Unaliased` may overwrite bits outside of the view.
+            let mut view = BitMapView::<MutableSync, AliasedEdgesOnly> {
+                data: whole.data,
+                stride: whole.stride,
+                rows: CopyRange::from(rows.clone()),
+                columns: CopyRange::from(columns.clone()),
+                _lifetime: PhantomData,
+                _mutability: PhantomData,
+                _edge_aliasing: PhantomData,
+            };
+            let expected = rows
+                .clone()
+                .flat_map(|row| columns.clone().map(move |col| (row, col)))
+                .filter(|&idx| original.get(idx))
+                .map(|(row, col)| (row - rows.start, col - columns.start))
+                .collect::<Vec<_>>();
+
+            let mut chunk_bits = vec![];
+            for (y, x, slice) in view.chunks() {
+                assert_ne!(slice.len(), 0);
+                for (i, bit) in slice.bits().enumerate() {
+                    assert_eq!(bit, view.get((y, x + i)), "({y}, {})", x + i);
+                    chunk_bits.push((y, x + i));
+                }
+            }
+            assert_eq!(chunk_bits.len(), rows.len() * columns.len());
+
+            let mut for_each_true = vec![];
+            view.for_each_true(|row, col| for_each_true.push((row, col)));
+            assert_eq!(for_each_true, expected, "{rows:?} x {columns:?}");
+            assert_eq!(view.count_ones(), expected.len());
+
+            view.fill(true);
+            assert_eq!(view.count_ones(), rows.len() * columns.len());
+            for row in 0..9 {
+                for col in 0..21 {
+                    let in_view = rows.contains(&row) && columns.contains(&col);
+                    assert_eq!(
+                        bitmap.get((row, col)),
+                        in_view || original.get((row, col)),
+                        "({row}, {col}) after filling {rows:?} x {columns:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn view_rows() {
+        let bitmap = pattern(5, 13);
+        let view = bitmap.as_view_ref::<ConstSync>();
+        let forward = view
+            .rows()
+            .map(|row| row.bits().collect::<Vec<bool>>())
+            .collect::<Vec<_>>();
+        let mut backward = view
+            .rows()
+            .rev()
+            .map(|row| row.bits().collect::<Vec<bool>>())
+            .collect::<Vec<_>>();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        for (row, bits) in forward.iter().enumerate() {
+            for (col, &bit) in bits.iter().enumerate() {
+                assert_eq!(bit, bitmap.get((row, col)), "({row}, {col})");
+            }
+        }
+
+        let mut rows = view.rows();
+        assert_eq!(rows.len(), 5);
+        rows.next();
+        rows.next_back();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows.size_hint(), (3, Some(3)));
+        assert_eq!(rows.by_ref().count(), 3);
+        assert!(rows.next().is_none() && rows.next_back().is_none());
+    }
+
+    #[test]
+    #[should_panic = "index out of range"]
+    fn view_get_out_of_range() {
+        let bitmap = pattern(4, 4);
+        bitmap.as_view_ref::<ConstSync>().get((0, 4));
+    }
+
+    #[test]
+    fn normalize_edges() {
+        let mut bytes = [0b1010_1010, 0b1100_1100, 0b1111_0000];
+        let mut slice =
+            UnaliasedBitSlice::<MutableSync>::from_bytes_mut(&mut bytes, 3..20);
+        let before = slice.bits().collect::<Vec<bool>>();
+        slice.normalize_edges(false);
+        assert_eq!(slice.bits().collect::<Vec<bool>>(), before);
+        assert_eq!(bytes, [0b1010_1000, 0b1100_1100, 0b0000_0000]);
+
+        let mut slice =
+            UnaliasedBitSlice::<MutableSync>::from_bytes_mut(&mut bytes, 3..20);
+        slice.normalize_edges(true);
+        assert_eq!(slice.bits().collect::<Vec<bool>>(), before);
+        assert_eq!(bytes, [0b1010_1111, 0b1100_1100, 0b1111_0000]);
+
+        // Both edges in one byte.
+        let mut byte = [0b0101_0101];
+        let mut slice =
+            UnaliasedBitSlice::<MutableUnsync>::from_bytes_mut(&mut byte, 2..5);
+        slice.normalize_edges(false);
+        assert_eq!(byte, [0b0001_0100]);
+
+        // Byte-aligned slices have no edges to normalize.
+        let mut bytes = [0b0101_0101, 0b1010_1010];
+        let mut slice =
+            UnaliasedBitSlice::<MutableSync>::from_bytes_mut(&mut bytes, 8..16);
+        slice.normalize_edges(true);
+        assert_eq!(bytes, [0b0101_0101, 0b1010_1010]);
+    }
+
+    #[test]
+    fn pack_bits_round_trip() {
+        for len in [0usize, 1, 7, 8, 9, 16, 30] {
+            let bits: Vec<bool> = (0..len).map(|i| (i * 5) % 7 < 3).collect();
+            let bytes = pack_bits(bits.iter().copied(), BitOrder::Lsb0);
+            assert_eq!(bytes.len(), len.div_ceil(8));
+            let slice = BitSlice::<ConstSync>::from_vec(&bytes, ..);
+            let unpacked = slice.bits().collect::<Vec<bool>>();
+            assert_eq!(unpacked[..len], bits[..], "{len} bits");
+            // The padding is zeroed.
+            assert!(unpacked[len..].iter().all(|&bit| !bit));
+
+            let msb = pack_bits(bits.iter().copied(), BitOrder::Msb0);
+            let reversed: Vec<u8> =
+                bytes.iter().map(|byte| byte.reverse_bits()).collect();
+            assert_eq!(msb, reversed);
+        }
+    }
 }
@@ -1,4 +1,6 @@
-use std::{borrow::Cow, collections::VecDeque, iter::Peekable};
+use std::{
+    borrow::Cow, collections::VecDeque, iter::Peekable, sync::OnceLock,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HasArgument {
@@ -39,7 +41,9 @@ pub struct Opt {
 pub enum InvalidOptError {
     #[error("short options must not be `\0`, `=`, or `-`")]
     InvalidShortOption(char),
-    #[error("long options must not contain `\0`, `=`, or `-`")]
+    #[error(
+        "long options must not contain `\0` or `=`, and must not start with `-`"
+    )]
     InvalidLongOption(Cow<'static, str>),
     #[error("long options must not be empty")]
     EmptyLongOption,
@@ -84,8 +88,9 @@ impl Opt {
                 Err(InvalidOptError::EmptyLongOption)
             }
             (_, Some(long))
-                if memchr::memchr3(b'\0', b'-', b'=', long.as_bytes())
-                    .is_some() =>
+                if long.starts_with('-')
+                    || memchr::memchr2(b'\0', b'=', long.as_bytes())
+                        .is_some() =>
             {
                 Err(InvalidOptError::InvalidLongOption(long.clone()))
             }
@@ -107,11 +112,16 @@ impl Opt {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Getopt {
     options: Vec<Opt>,
 }
 
 impl Getopt {
+    pub fn builder() -> GetoptBuilder {
+        GetoptBuilder { options: Vec::new() }
+    }
+
     /// Assumes the program name is NOT in the iterator.
     pub fn parse<'a, I: IntoIterator<Item = &'a str>>(
         &'a self,
@@ -125,6 +135,23 @@ impl Getopt {
         }
     }
 
+    /// Parses `args`, and separates the options from the non-option
+    /// arguments (see [`GetoptIter::partitioned`]).
+    pub fn parse_partitioned<'a, I: IntoIterator<Item = &'a str>>(
+        &'a self,
+        args: I,
+    ) -> Result<(Vec<GetoptItem<'a>>, Vec<&'a str>), GetoptError<'a>> {
+        self.parse(args).partitioned()
+    }
+
+    /// Parses this process's arguments (see [`env_args`]).
+    pub fn parse_env<'a>(
+        &'a self,
+    ) -> GetoptIter<'a, impl Iterator<Item = &'a str>> {
+        let args: &'a [&'a str] = env_args();
+        self.parse(args.iter().copied())
+    }
+
     pub fn add_option(&mut self, opt: Opt) -> Result<(), InvalidOptError> {
         opt.validate()?;
         if let Some(existing_opt) = self.options.iter().find(|e_opt| {
@@ -158,6 +185,64 @@ impl Getopt {
     }
 }
 
+/// Builds a [`Getopt`] one option at a time. Invalid or duplicate options
+/// are reported by [`GetoptBuilder::build`].
+#[derive(Debug, Clone)]
+pub struct GetoptBuilder {
+    options: Vec<Opt>,
+}
+
+impl GetoptBuilder {
+    pub fn opt(mut self, opt: Opt) -> Self {
+        self.options.push(opt);
+        self
+    }
+
+    pub fn flag(self, short: char, long: &'static str) -> Self {
+        self.opt(Opt::short_long(short, long, HasArgument::No))
+    }
+
+    pub fn arg(self, short: char, long: &'static str) -> Self {
+        self.opt(Opt::short_long(short, long, HasArgument::Yes))
+    }
+
+    pub fn optional_arg(self, short: char, long: &'static str) -> Self {
+        self.opt(Opt::short_long(short, long, HasArgument::Optional))
+    }
+
+    pub fn long_flag(self, long: &'static str) -> Self {
+        self.opt(Opt::long(long, HasArgument::No))
+    }
+
+    pub fn long_arg(self, long: &'static str) -> Self {
+        self.opt(Opt::long(long, HasArgument::Yes))
+    }
+
+    pub fn long_optional_arg(self, long: &'static str) -> Self {
+        self.opt(Opt::long(long, HasArgument::Optional))
+    }
+
+    /// Returns the first error [`Getopt::add_option`] would, if any.
+    pub fn build(self) -> Result<Getopt, InvalidOptError> {
+        Getopt::from_iter(self.options)
+    }
+}
+
+/// This process's arguments, without the program name.
+///
+/// The arguments are collected (and leaked) on the first call, so that parsed
+/// items can borrow from them for the rest of the program.
+///
+/// # Panics
+///
+/// Panics if any argument is not valid Unicode, like [`std::env::args`].
+pub fn env_args() -> &'static [&'static str] {
+    static ARGS: OnceLock<Box<[&'static str]>> = OnceLock::new();
+    ARGS.get_or_init(|| {
+        std::env::args().skip(1).map(|arg| &*arg.leak()).collect()
+    })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum GetoptItem<'a> {
     Opt { opt: &'a Opt, arg: Option<&'a str> },
@@ -182,6 +267,25 @@ pub struct GetoptIter<'a, I: Iterator<Item = &'a str>> {
     found_dash_dash: bool,
 }
 
+impl<'a, I: Iterator<Item = &'a str>> GetoptIter<'a, I> {
+    /// Collects the options (which are all `GetoptItem::Opt`) and the
+    /// non-option arguments (including any after `--`) separately, each in
+    /// order. Returns the first error, if any.
+    pub fn partitioned(
+        self,
+    ) -> Result<(Vec<GetoptItem<'a>>, Vec<&'a str>), GetoptError<'a>> {
+        let mut opts = vec![];
+        let mut non_opts = vec![];
+        for item in self {
+            match item? {
+                GetoptItem::NonOpt(arg) => non_opts.push(arg),
+                opt @ GetoptItem::Opt { .. } => opts.push(opt),
+            }
+        }
+        Ok((opts, non_opts))
+    }
+}
+
 impl<'a, I: Iterator<Item = &'a str>> Iterator for GetoptIter<'a, I> {
     type Item = Result<GetoptItem<'a>, GetoptError<'a>>;
 
@@ -383,7 +487,9 @@ impl<'a, I: Iterator<Item = &'a str>> Iterator for GetoptIter<'a, I> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Getopt, GetoptError, GetoptItem, HasArgument, Opt};
+    use crate::{
+        Getopt, GetoptError, GetoptItem, HasArgument, InvalidOptError, Opt,
+    };
 
     #[test]
     fn basic_short() {
@@ -577,4 +683,92 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn long_with_dash() {
+        let a = Opt::long("no-a", HasArgument::No);
+        let b = Opt::long("b-arg", HasArgument::Yes);
+        let getopt = Getopt::from_iter([a.clone(), b.clone()]).unwrap();
+
+        assert_eq!(
+            getopt
+                .parse(["--no-a", "--b-arg=x", "--b-arg", "y"])
+                .collect::<Vec<_>>(),
+            vec![
+                Ok(GetoptItem::Opt { opt: &a, arg: None }),
+                Ok(GetoptItem::Opt { opt: &b, arg: Some("x") }),
+                Ok(GetoptItem::Opt { opt: &b, arg: Some("y") }),
+            ]
+        );
+
+        assert_eq!(
+            Opt::long("-a", HasArgument::No).validate(),
+            Err(InvalidOptError::InvalidLongOption("-a".into()))
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let getopt = Getopt::builder()
+            .flag('N', "normal")
+            .long_flag("hues")
+            .arg('v', "vector")
+            .long_arg("jitter")
+            .optional_arg('c', "chance")
+            .long_optional_arg("seed")
+            .opt(Opt::short('x', HasArgument::No))
+            .build()
+            .unwrap();
+        let expected = Getopt::from_iter([
+            Opt::short_long('N', "normal", HasArgument::No),
+            Opt::long("hues", HasArgument::No),
+            Opt::short_long('v', "vector", HasArgument::Yes),
+            Opt::long("jitter", HasArgument::Yes),
+            Opt::short_long('c', "chance", HasArgument::Optional),
+            Opt::long("seed", HasArgument::Optional),
+            Opt::short('x', HasArgument::No),
+        ])
+        .unwrap();
+        assert_eq!(getopt, expected);
+
+        assert_eq!(
+            Getopt::builder().flag('a', "all").arg('a', "add").build(),
+            Err(InvalidOptError::DuplicateShortOption('a'))
+        );
+        assert_eq!(
+            Getopt::builder().flag('a', "all").long_arg("all").build(),
+            Err(InvalidOptError::DuplicateLongOption("all".into()))
+        );
+        assert_eq!(
+            Getopt::builder().long_flag("").build(),
+            Err(InvalidOptError::EmptyLongOption)
+        );
+    }
+
+    #[test]
+    fn partitioned() {
+        let a = Opt::short('a', HasArgument::No);
+        let b = Opt::short_long('b', "bee", HasArgument::Yes);
+        let getopt = Getopt::from_iter([a.clone(), b.clone()]).unwrap();
+
+        assert_eq!(
+            getopt.parse_partitioned([
+                "x", "-a", "y", "--bee", "z", "w", "-bv", "--", "-a", "--bee"
+            ]),
+            Ok((
+                vec![
+                    GetoptItem::Opt { opt: &a, arg: None },
+                    GetoptItem::Opt { opt: &b, arg: Some("z") },
+                    GetoptItem::Opt { opt: &b, arg: Some("v") },
+                ],
+                vec!["x", "y", "w", "-a", "--bee"],
+            ))
+        );
+
+        assert_eq!(getopt.parse_partitioned([]), Ok((vec![], vec![])));
+        assert_eq!(
+            getopt.parse_partitioned(["x", "-c", "y"]),
+            Err(GetoptError::UnrecognizedShortOpt { opt: 'c', arg: None })
+        );
+    }
 }
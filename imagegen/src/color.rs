@@ -1,6 +1,13 @@
 use getopt::{GetoptItem, Opt};
 use rand::{Rng, RngCore};
-use std::{borrow::Cow, num::NonZeroUsize, simd::Simd};
+use std::{
+    borrow::Cow,
+    num::NonZeroUsize,
+    simd::{num::SimdFloat, Simd},
+    sync::Once,
+};
+
+use crate::{error::ConfigError, pnmdata::PnmData};
 
 #[cfg(feature = "f32")]
 pub type Channel = f32;
@@ -32,17 +39,47 @@ impl Default for VectorSetKind {
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct VectorSet {
     start: Color,
+    /// The maximum distance each new color's start is randomly moved from
+    /// `start`.
+    jitter: Channel,
     vectors: Cow<'static, [Color]>,
     chance: NonZeroUsize,
     kind: VectorSetKind,
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Clone, PartialEq, PartialOrd)]
 pub struct VectorSetGroup {
     // Must never be empty
     vectorsets: Cow<'static, [VectorSet]>,
     // Must equal self.vectorsets.map(.chance).sum()
     total_chance: NonZeroUsize,
+    order: VectorSetOrder,
+    /// For `VectorSetOrder::Cycle`, the index of each vectorset to use, in
+    /// order. Each appears as many times as its chance. Empty otherwise.
+    schedule: Vec<usize>,
+}
+
+/// How a `VectorSetGroup` chooses a vectorset for each color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VectorSetOrder {
+    /// Randomly, weighted by each vectorset's chance.
+    #[default]
+    Random,
+    /// Round-robin, using each vectorset as many times as its chance in
+    /// every `total_chance` colors, spread out as evenly as possible. The
+    /// position in the cycle is the index the caller passes to `new_color`.
+    Cycle,
+}
+
+// Without the schedule, which follows from the rest.
+impl std::fmt::Debug for VectorSetGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VectorSetGroup")
+            .field("vectorsets", &self.vectorsets)
+            .field("total_chance", &self.total_chance)
+            .field("order", &self.order)
+            .finish_non_exhaustive()
+    }
 }
 
 impl VectorSetGroup {
@@ -51,16 +88,76 @@ impl VectorSetGroup {
     ) -> Result<Self, Cow<'static, [VectorSet]>> {
         let total_chance = vectorsets.iter().map(|vs| vs.chance.get()).sum();
         if let Some(total_chance) = NonZeroUsize::new(total_chance) {
-            Ok(Self { vectorsets, total_chance })
+            Ok(Self {
+                vectorsets,
+                total_chance,
+                order: VectorSetOrder::Random,
+                schedule: vec![],
+            })
         } else {
             Err(vectorsets)
         }
     }
+
+    pub fn with_order(mut self, order: VectorSetOrder) -> Self {
+        self.order = order;
+        self.schedule = match order {
+            VectorSetOrder::Random => vec![],
+            VectorSetOrder::Cycle => self.cycle_schedule(),
+        };
+        self
+    }
+
+    /// Spreads out each vectorset's uses by smooth weighted round-robin:
+    /// each step, every vectorset gains its chance in credit, and the one
+    /// with the most (the first, on ties) is used and pays `total_chance`.
+    fn cycle_schedule(&self) -> Vec<usize> {
+        let total = self.total_chance.get() as i128;
+        let mut credits = vec![0i128; self.vectorsets.len()];
+        (0..total)
+            .map(|_| {
+                for (credit, vs) in credits.iter_mut().zip(&*self.vectorsets) {
+                    *credit += vs.chance.get() as i128;
+                }
+                let (index, _) = credits
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .max_by_key(|&(_, credit)| credit)
+                    .expect("vectorsets is not empty");
+                credits[index] -= total;
+                index
+            })
+            .collect()
+    }
 }
 
-pub trait ColorGenerator: std::fmt::Debug {
-    /// Using rng, generate a new color in this colorspace.
-    fn new_color(&self, rng: &mut dyn RngCore) -> Color;
+pub trait ColorGenerator: std::fmt::Debug + Sync {
+    /// Using rng, generate a new color in this colorspace. `index` is how
+    /// many colors the caller has generated before this one, which
+    /// generators that go through their options in order use as their
+    /// position, so that they keep no state of their own.
+    fn new_color(&self, rng: &mut dyn RngCore, index: usize) -> Color;
+
+    /// Using rng, fill `colors` with new colors, as if by calling `new_color`
+    /// for each in order, with indices counting up from `first`. Generators
+    /// can override this to generate a batch without the per-call overhead.
+    fn new_colors(
+        &self,
+        rng: &mut dyn RngCore,
+        first: usize,
+        colors: &mut [Color],
+    ) {
+        for (i, color) in colors.iter_mut().enumerate() {
+            *color = self.new_color(rng, first + i);
+        }
+    }
+
+    /// At most how many distinct colors this generates, if it only generates
+    /// a finite palette.
+    fn palette_size(&self) -> Option<usize> {
+        None
+    }
 
     #[doc(hidden)]
     #[cfg(test)]
@@ -76,8 +173,21 @@ pub trait ColorGenerator: std::fmt::Debug {
 }
 
 impl<'a, G: ColorGenerator + ?Sized> ColorGenerator for &'a G {
-    fn new_color(&self, rng: &mut dyn RngCore) -> Color {
-        (**self).new_color(rng)
+    fn new_color(&self, rng: &mut dyn RngCore, index: usize) -> Color {
+        (**self).new_color(rng, index)
+    }
+
+    fn new_colors(
+        &self,
+        rng: &mut dyn RngCore,
+        first: usize,
+        colors: &mut [Color],
+    ) {
+        (**self).new_colors(rng, first, colors)
+    }
+
+    fn palette_size(&self) -> Option<usize> {
+        (**self).palette_size()
     }
 
     #[doc(hidden)]
@@ -95,6 +205,7 @@ impl<'a, G: ColorGenerator + ?Sized> ColorGenerator for &'a G {
 
 static BASIC_COLOR: VectorSet = VectorSet {
     start: from_3(0.0, 0.0, 0.0),
+    jitter: 0.0,
     vectors: Cow::Borrowed(&[
         from_3(1.0, 0.0, 0.0),
         from_3(0.0, 1.0, 0.0),
@@ -107,45 +218,70 @@ static BASIC_COLOR: VectorSet = VectorSet {
 static FULL_INTENSITY_HUES: &'static [VectorSet] = &[
     VectorSet {
         start: from_3(1.0, 0.0, 0.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(0.0, 1.0, 0.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
     VectorSet {
         start: from_3(0.0, 1.0, 0.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(1.0, 0.0, 0.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
     VectorSet {
         start: from_3(0.0, 1.0, 0.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(0.0, 0.0, 1.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
     VectorSet {
         start: from_3(0.0, 0.0, 1.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(0.0, 1.0, 0.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
     VectorSet {
         start: from_3(0.0, 0.0, 1.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(1.0, 0.0, 0.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
     VectorSet {
         start: from_3(1.0, 0.0, 0.0),
+        jitter: 0.0,
         vectors: Cow::Borrowed(&[from_3(0.0, 0.0, 1.0)]),
         chance: ONE,
         kind: VectorSetKind::Full,
     },
 ];
 
+/// Generates a random color (with alpha 0) uniformly within the unit ball.
+fn random_in_ball(rng: &mut dyn RngCore) -> Color {
+    loop {
+        let v = from_3(
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+        );
+        if (v * v).reduce_sum() <= 1.0 {
+            return v;
+        }
+    }
+}
+
 impl ColorGenerator for VectorSet {
-    fn new_color(&self, rng: &mut dyn RngCore) -> Color {
+    fn new_color(&self, rng: &mut dyn RngCore, _index: usize) -> Color {
         let mut c = self.start;
+        // Skip this entirely when there is no jitter, so that the colors
+        // generated from a given seed do not change.
+        if self.jitter > 0.0 {
+            c += random_in_ball(rng) * Color::splat(self.jitter);
+        }
         match self.kind {
             // Each vector multiplier is generated independently.
             VectorSetKind::Full => {
@@ -163,6 +299,11 @@ impl ColorGenerator for VectorSet {
         }
     }
 
+    fn palette_size(&self) -> Option<usize> {
+        // Without vectors or jitter, this always generates `start`.
+        (self.vectors.is_empty() && self.jitter == 0.0).then_some(1)
+    }
+
     #[doc(hidden)]
     #[cfg(test)]
     fn as_vectorset(&self) -> Option<&VectorSet> {
@@ -171,23 +312,50 @@ impl ColorGenerator for VectorSet {
 }
 
 impl ColorGenerator for VectorSetGroup {
-    fn new_color(&self, rng: &mut dyn RngCore) -> Color {
+    fn new_color(&self, rng: &mut dyn RngCore, index: usize) -> Color {
         if self.vectorsets.len() == 0 {
             return Color::default();
         }
         if self.vectorsets.len() == 1 {
-            return self.vectorsets[0].new_color(rng);
+            return self.vectorsets[0].new_color(rng, index);
+        }
+        if self.order == VectorSetOrder::Cycle {
+            let vectorset = self.schedule[index % self.schedule.len()];
+            return self.vectorsets[vectorset].new_color(rng, index);
         }
         let mut chance = rng.gen_range(0..self.total_chance.get());
         for vectorset in &*self.vectorsets {
             if chance < vectorset.chance.get() {
-                return vectorset.new_color(rng);
+                return vectorset.new_color(rng, index);
             }
             chance -= vectorset.chance.get();
         }
         unreachable!("total_chance should be the sum of all chances")
     }
 
+    fn new_colors(
+        &self,
+        rng: &mut dyn RngCore,
+        first: usize,
+        colors: &mut [Color],
+    ) {
+        // Choosing a vectorset uses the rng, so this can only skip the choice
+        // if there is at most one.
+        match &*self.vectorsets {
+            [] => colors.fill(Color::default()),
+            [vectorset] => vectorset.new_colors(rng, first, colors),
+            _ => {
+                for (i, color) in colors.iter_mut().enumerate() {
+                    *color = self.new_color(rng, first + i);
+                }
+            }
+        }
+    }
+
+    fn palette_size(&self) -> Option<usize> {
+        self.vectorsets.iter().map(VectorSet::palette_size).sum()
+    }
+
     #[doc(hidden)]
     #[cfg(test)]
     fn as_vectorsetgroup(&self) -> Option<&VectorSetGroup> {
@@ -195,6 +363,144 @@ impl ColorGenerator for VectorSetGroup {
     }
 }
 
+/// Chooses uniformly from a fixed palette, e.g. one extracted from an image by
+/// `--palette-extract`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    // Must never be empty
+    colors: Vec<Color>,
+}
+
+impl ColorGenerator for Palette {
+    fn new_color(&self, rng: &mut dyn RngCore, _index: usize) -> Color {
+        self.colors[rng.gen_range(0..self.colors.len())]
+    }
+
+    fn palette_size(&self) -> Option<usize> {
+        Some(self.colors.len())
+    }
+}
+
+/// Wraps another generator, applying `--gamma` to the red, green, and blue
+/// channels of its colors, i.e. raising each to the power of `1 / gamma`.
+/// Alpha is left alone.
+#[derive(Debug)]
+pub struct GammaGenerator {
+    pub inner: Box<dyn ColorGenerator + Send>,
+    pub gamma: Channel,
+}
+
+impl GammaGenerator {
+    fn correct(&self, color: Color) -> Color {
+        let exponent = 1.0 / self.gamma;
+        let [r, g, b, a] = color.to_array();
+        // Negative channels keep their sign rather than becoming NaN.
+        let correct = |channel: Channel| {
+            channel.signum() * channel.abs().powf(exponent)
+        };
+        Color::from_array([correct(r), correct(g), correct(b), a])
+    }
+}
+
+impl ColorGenerator for GammaGenerator {
+    fn new_color(&self, rng: &mut dyn RngCore, index: usize) -> Color {
+        self.correct(self.inner.new_color(rng, index))
+    }
+
+    fn new_colors(
+        &self,
+        rng: &mut dyn RngCore,
+        first: usize,
+        colors: &mut [Color],
+    ) {
+        self.inner.new_colors(rng, first, colors);
+        colors.iter_mut().for_each(|color| *color = self.correct(*color));
+    }
+
+    // Correction is one-to-one, so it makes no more or fewer colors.
+    fn palette_size(&self) -> Option<usize> {
+        self.inner.palette_size()
+    }
+}
+
+/// Reduces `pixels` to at most `max` colors: the distinct colors if there are
+/// few enough, or otherwise the averages of the boxes found by median cut,
+/// which repeatedly splits the box of colors with the widest range in any
+/// channel at its median in that channel.
+fn extract_palette(pixels: &[Color], max: NonZeroUsize) -> Vec<Color> {
+    let mut distinct =
+        pixels.iter().map(|color| color.to_bits()).collect::<Vec<_>>();
+    distinct.sort_unstable_by_key(|bits| bits.to_array());
+    distinct.dedup();
+    if distinct.len() <= max.get() {
+        return distinct.into_iter().map(Color::from_bits).collect();
+    }
+
+    // The channel (ignoring alpha) with the widest range in `colors`, and
+    // that range.
+    let widest = |colors: &[Color]| {
+        let min = colors.iter().copied().reduce(Color::simd_min).unwrap();
+        let max = colors.iter().copied().reduce(Color::simd_max).unwrap();
+        let range = (max - min).to_array();
+        (0..3).map(|channel| (channel, range[channel])).fold(
+            (0, 0.0),
+            |widest, next| if next.1 > widest.1 { next } else { widest },
+        )
+    };
+    let mut boxes = vec![pixels.to_vec()];
+    while boxes.len() < max.get() {
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| (index, widest(colors)))
+            .filter(|(_, (_, range))| *range > 0.0)
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))
+            .map(|(index, (channel, _))| (index, channel))
+        else {
+            break;
+        };
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_by(|a, b| a[channel].total_cmp(&b[channel]));
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.extend([lower, upper]);
+    }
+    boxes
+        .iter()
+        .map(|colors| {
+            let total = colors.iter().sum::<Color>();
+            total / Color::splat(colors.len() as Channel)
+        })
+        .collect()
+}
+
+/// Reads the image at `path` for `--palette-extract`, and extracts a palette
+/// of at most `max` colors from it.
+fn read_palette(path: &str, max: NonZeroUsize) -> Result<Palette, ConfigError> {
+    let file = std::fs::File::open(path).map_err(|source| {
+        ConfigError::OpenFile {
+            option: "palette-extract",
+            path: path.to_string(),
+            source,
+        }
+    })?;
+    let image = PnmData::read_from(std::io::BufReader::new(file)).map_err(
+        |source| ConfigError::ReadFile {
+            option: "palette-extract",
+            path: path.to_string(),
+            source,
+        },
+    )?;
+    let colors = extract_palette(&image.rawdata, max);
+    if colors.is_empty() {
+        return Err(ConfigError::InvalidValue {
+            option: "palette-extract",
+            value: path.to_string(),
+        });
+    }
+    log::info!("extracted {} colors from {path:?}", colors.len());
+    Ok(Palette { colors })
+}
+
 // pub fn options(cmd: clap::Command) -> clap::Command {
 //     // let normal_color = arg!([normal_color] -N --normal "Default color
 // generation.");     // let vector_color = ArgGroup::new("vector_color")
@@ -220,31 +526,61 @@ pub fn opts() -> impl IntoIterator<Item = Opt> {
         Opt::short_long('v', "vector", getopt::HasArgument::Yes),
         Opt::short_long('b', "base", getopt::HasArgument::Yes),
         Opt::short_long('t', "type", getopt::HasArgument::Yes),
+        Opt::long("jitter", getopt::HasArgument::Yes),
+        Opt::long("color255", getopt::HasArgument::No),
+        Opt::long("vectorsetorder", getopt::HasArgument::Yes),
+        Opt::long("palette-extract", getopt::HasArgument::Yes),
+        Opt::long("palette-colors", getopt::HasArgument::Yes),
+        Opt::long("gamma", getopt::HasArgument::Yes),
     ]
 }
 
-fn parse_color(s: &str) -> Result<Color, String> {
+/// Whether `--color255` was given, so that every color option (`--vector`,
+/// `--base`, and `--background`) is in 0-255 units instead of 0-1.
+///
+/// This applies regardless of where `--color255` appears among the options.
+pub fn color255(opts: &[GetoptItem<'_>]) -> bool {
+    opts.iter().any(|item| {
+        matches!(item, GetoptItem::Opt { opt, arg: None }
+            if opt.is_long("color255"))
+    })
+}
+
+/// Parses a comma-separated color, e.g. `1,0.5,0`, or `255,128,0` if
+/// `color255` is set. Missing channels are 0.
+pub fn parse_color(s: &str, color255: bool) -> Result<Color, String> {
     let mut color = [0.0; 4];
     for (s, channel) in s.split(',').zip(color.iter_mut()) {
         *channel = s
             .parse()
             .map_err(|_| format!("incorrect color string: {:?}", s))?;
     }
-    Ok(Color::from_array(color))
+    let color = Color::from_array(color);
+    Ok(if color255 { color / Color::splat(255.0) } else { color })
 }
 
 pub fn handle_opts(
     opts: &[GetoptItem<'_>],
-) -> Box<dyn ColorGenerator + Send + 'static> {
+) -> Result<Box<dyn ColorGenerator + Send + 'static>, ConfigError> {
     let mut normal = false;
+    let color255 = color255(opts);
     // Invariant: This is either None, or a NON-EMPTY vec/slice
     let mut vectorsets = None;
+    // The first option that made a vectorset, for error messages.
+    let mut vectorset_option = None;
+    // Requires more than one vectorset.
+    let mut order = None;
+    let mut palette_path = None;
+    // Requires --palette-extract.
+    let mut palette_colors = None;
+    let mut gamma = None;
     for opt in opts {
         match opt {
             GetoptItem::Opt { opt, arg: None } if opt.is_long("normal") => {
                 normal = true
             }
             GetoptItem::Opt { opt, arg: None } if opt.is_long("hues") => {
+                vectorset_option.get_or_insert("hues");
                 match vectorsets {
                     None => {
                         vectorsets = Some(Cow::Borrowed(FULL_INTENSITY_HUES))
@@ -257,11 +593,13 @@ pub fn handle_opts(
             GetoptItem::Opt { opt, arg: None }
                 if opt.is_long("newvectorset") =>
             {
+                vectorset_option.get_or_insert("newvectorset");
                 match vectorsets {
                     None => {
                         vectorsets = Some(
                             vec![VectorSet {
                                 start: Color::default(),
+                                jitter: 0.0,
                                 vectors: vec![].into(),
                                 chance: ONE,
                                 kind: VectorSetKind::Full,
@@ -271,6 +609,7 @@ pub fn handle_opts(
                     }
                     Some(ref mut cow) => cow.to_mut().push(VectorSet {
                         start: Color::default(),
+                        jitter: 0.0,
                         vectors: vec![].into(),
                         chance: ONE,
                         kind: VectorSetKind::Full,
@@ -280,12 +619,19 @@ pub fn handle_opts(
             GetoptItem::Opt { opt, arg: Some(vector) }
                 if opt.is_long("vector") =>
             {
-                let vector = parse_color(vector).expect("TODO: error handling");
+                vectorset_option.get_or_insert("vector");
+                let vector = parse_color(vector, color255).map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "vector",
+                        value: vector.to_string(),
+                    }
+                })?;
                 match vectorsets {
                     None => {
                         vectorsets = Some(
                             vec![VectorSet {
                                 start: Color::default(),
+                                jitter: 0.0,
                                 vectors: vec![vector].into(),
                                 chance: ONE,
                                 kind: VectorSetKind::Full,
@@ -303,12 +649,19 @@ pub fn handle_opts(
                 }
             }
             GetoptItem::Opt { opt, arg: Some(base) } if opt.is_long("base") => {
-                let start = parse_color(base).expect("TODO: error handling");
+                vectorset_option.get_or_insert("base");
+                let start = parse_color(base, color255).map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "base",
+                        value: base.to_string(),
+                    }
+                })?;
                 match vectorsets {
                     None => {
                         vectorsets = Some(
                             vec![VectorSet {
                                 start,
+                                jitter: 0.0,
                                 vectors: Cow::Borrowed(&[]),
                                 chance: ONE,
                                 kind: VectorSetKind::Full,
@@ -328,17 +681,25 @@ pub fn handle_opts(
             GetoptItem::Opt { opt, arg: Some(r#type) }
                 if opt.is_long("type") =>
             {
+                vectorset_option.get_or_insert("type");
                 let kind = match *r#type {
                     "full" | "f" => VectorSetKind::Full,
                     "sum_one" | "sumone" | "one" | "o" => VectorSetKind::SumOne,
                     "triangular" | "tri" | "t" => VectorSetKind::Triangular,
-                    _ => panic!("TODO: error handling"),
+                    _ => {
+                        return Err(ConfigError::UnknownValue {
+                            option: "type",
+                            value: r#type.to_string(),
+                            expected: "full, triangular, sum_one",
+                        })
+                    }
                 };
                 match vectorsets {
                     None => {
                         vectorsets = Some(
                             vec![VectorSet {
                                 start: Color::default(),
+                                jitter: 0.0,
                                 vectors: Cow::Borrowed(&[]),
                                 chance: ONE,
                                 kind,
@@ -355,27 +716,192 @@ pub fn handle_opts(
                     }
                 }
             }
+            GetoptItem::Opt { opt, arg: Some(jitter_str) }
+                if opt.is_long("jitter") =>
+            {
+                vectorset_option.get_or_insert("jitter");
+                let jitter = match jitter_str.parse::<Channel>() {
+                    Ok(jitter) if jitter >= 0.0 && jitter.is_finite() => jitter,
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            option: "jitter",
+                            value: jitter_str.to_string(),
+                        })
+                    }
+                };
+                match vectorsets {
+                    None => {
+                        vectorsets = Some(
+                            vec![VectorSet {
+                                start: Color::default(),
+                                jitter,
+                                vectors: Cow::Borrowed(&[]),
+                                chance: ONE,
+                                kind: VectorSetKind::Full,
+                            }]
+                            .into(),
+                        )
+                    }
+                    Some(ref mut cow) => {
+                        let vectorset = cow
+                            .to_mut()
+                            .last_mut()
+                            .expect("vectorsets should never be an empty vec");
+                        vectorset.jitter = jitter;
+                    }
+                }
+            }
+            GetoptItem::Opt { opt, arg: Some(order_str) }
+                if opt.is_long("vectorsetorder") =>
+            {
+                if order.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "vectorsetorder",
+                        value: order_str.to_string(),
+                    });
+                }
+                order = Some(match *order_str {
+                    "random" => VectorSetOrder::Random,
+                    "cycle" => VectorSetOrder::Cycle,
+                    _ => {
+                        return Err(ConfigError::UnknownValue {
+                            option: "vectorsetorder",
+                            value: order_str.to_string(),
+                            expected: "random, cycle",
+                        })
+                    }
+                });
+            }
+            GetoptItem::Opt { opt, arg: Some(path) }
+                if opt.is_long("palette-extract") =>
+            {
+                if palette_path.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "palette-extract",
+                        value: path.to_string(),
+                    });
+                }
+                palette_path = Some(*path);
+            }
+            GetoptItem::Opt { opt, arg: Some(colors) }
+                if opt.is_long("palette-colors") =>
+            {
+                if palette_colors.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "palette-colors",
+                        value: colors.to_string(),
+                    });
+                }
+                palette_colors = Some(colors.parse().map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "palette-colors",
+                        value: colors.to_string(),
+                    }
+                })?);
+            }
+            GetoptItem::Opt { opt, arg: Some(gamma_str) }
+                if opt.is_long("gamma") =>
+            {
+                if gamma.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "gamma",
+                        value: gamma_str.to_string(),
+                    });
+                }
+                gamma = match gamma_str.parse::<Channel>() {
+                    Ok(gamma) if gamma > 0.0 && gamma.is_finite() => {
+                        Some(gamma)
+                    }
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            option: "gamma",
+                            value: gamma_str.to_string(),
+                        })
+                    }
+                };
+            }
             _ => {}
         }
     }
-    match (normal, vectorsets) {
-        (true | false, None) => Box::new(&BASIC_COLOR), /* Default to basic */
-        // if no colorspace
-        // is given
-        (false, Some(vectorsets)) => Box::new(
-            VectorSetGroup::new(vectorsets).expect("vectorsets is not empty"),
-        ),
-        (true, Some(_)) => panic!("Must provide only one colorspace"),
+    if palette_colors.is_some() && palette_path.is_none() {
+        return Err(ConfigError::Requires {
+            option: "palette-colors",
+            requirement: "--palette-extract",
+        });
+    }
+    let vectorset_count = vectorsets.as_ref().map_or(0, |sets| sets.len());
+    if order.is_some() && vectorset_count < 2 {
+        return Err(ConfigError::Requires {
+            option: "vectorsetorder",
+            requirement: "more than one vectorset",
+        });
+    }
+    let generator: Box<dyn ColorGenerator + Send> = match palette_path {
+        Some(path) => {
+            let other = match (normal, vectorset_option) {
+                (true, _) => Some("normal"),
+                (false, other) => other,
+            };
+            if let Some(other) = other {
+                return Err(ConfigError::Conflict {
+                    option: "palette-extract",
+                    other,
+                });
+            }
+            let max = palette_colors.unwrap_or(NonZeroUsize::new(256).unwrap());
+            Box::new(read_palette(path, max)?)
+        }
+        None => match (normal, vectorsets) {
+            // Default to basic if no colorspace is given
+            (true | false, None) => Box::new(&BASIC_COLOR),
+            (false, Some(vectorsets)) => Box::new(
+                VectorSetGroup::new(vectorsets)
+                    .expect("vectorsets is not empty")
+                    .with_order(order.unwrap_or_default()),
+            ),
+            (true, Some(_)) => {
+                return Err(ConfigError::Conflict {
+                    option: "normal",
+                    other: vectorset_option.expect("vectorsets is not empty"),
+                })
+            }
+        },
+    };
+    let generator: Box<dyn ColorGenerator + Send> = match gamma {
+        Some(gamma) => Box::new(GammaGenerator { inner: generator, gamma }),
+        None => generator,
+    };
+    if let Some(err) = solid_color(&*generator) {
+        let strict = opts.iter().any(|item| {
+            matches!(item, GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("strict"))
+        });
+        if strict {
+            return Err(err);
+        }
+        static WARNING: Once = Once::new();
+        WARNING.call_once(|| log::warn!("{err}"));
     }
+    Ok(generator)
+}
+
+/// An error (or warning, without `--strict`) if `generator` only ever
+/// generates one color, e.g. a vectorset with no vectors or jitter.
+fn solid_color(generator: &dyn ColorGenerator) -> Option<ConfigError> {
+    matches!(generator.palette_size(), Some(0 | 1))
+        .then_some(ConfigError::SolidColor)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{borrow::Cow, num::NonZeroUsize, simd::num::SimdFloat};
+
     use getopt::Getopt;
+    use rand::{Rng, RngCore, SeedableRng};
 
     use super::{
-        from_3, Color, VectorSet, VectorSetGroup, VectorSetKind, BASIC_COLOR,
-        FULL_INTENSITY_HUES, ONE,
+        from_3, Channel, Color, ColorGenerator, VectorSet, VectorSetGroup,
+        VectorSetKind, BASIC_COLOR, FULL_INTENSITY_HUES, ONE,
     };
 
     #[test]
@@ -390,7 +916,7 @@ mod tests {
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap();
 
-            let should_be_normal = super::handle_opts(&opts);
+            let should_be_normal = super::handle_opts(&opts).unwrap();
             let should_be_normal = should_be_normal.as_vectorset().unwrap();
             assert_eq!(should_be_normal, &BASIC_COLOR);
         }
@@ -409,7 +935,7 @@ mod tests {
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap();
 
-            let should_be_hues = super::handle_opts(&opts);
+            let should_be_hues = super::handle_opts(&opts).unwrap();
             let should_be_hues = should_be_hues.as_vectorsetgroup().unwrap();
             assert_eq!(should_be_hues, &expected);
         }
@@ -420,6 +946,7 @@ mod tests {
         let empty = VectorSetGroup::new(
             vec![VectorSet {
                 start: Color::default(),
+                jitter: 0.0,
                 vectors: vec![].into(),
                 chance: ONE,
                 kind: VectorSetKind::Full,
@@ -430,6 +957,7 @@ mod tests {
         let redgreen = VectorSetGroup::new(
             vec![VectorSet {
                 start: from_3(0.0, 0.0, 0.0),
+                jitter: 0.0,
                 vectors: vec![from_3(1.0, 0.0, 0.0), from_3(0.0, 1.0, 0.0)]
                     .into(),
                 chance: super::ONE,
@@ -458,10 +986,388 @@ mod tests {
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap();
 
-            let should_be_expected = super::handle_opts(&opts);
+            let should_be_expected = super::handle_opts(&opts).unwrap();
             let should_be_expected =
                 should_be_expected.as_vectorsetgroup().unwrap();
             assert_eq!(should_be_expected, expected);
         }
     }
+
+    #[test]
+    fn color255() {
+        let close = |color: Color, expected: Color| {
+            (color - expected).abs().reduce_max() < 1e-3
+        };
+        let orange = super::parse_color("255,128,0", true).unwrap();
+        assert!(close(orange, from_3(1.0, 0.502, 0.0)), "{orange:?}");
+        // Without --color255, the same components are taken as they are.
+        assert_eq!(
+            super::parse_color("255,128,0", false).unwrap(),
+            from_3(255.0, 128.0, 0.0)
+        );
+        assert_eq!(
+            super::parse_color("1,0.5,0", false).unwrap(),
+            from_3(1.0, 0.5, 0.0)
+        );
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        // --color255 applies to every color, even those before it.
+        let args_iter: [&[&str]; 2] = [
+            &["-b", "0,0,51", "-v255,0,0", "--color255"],
+            &["--color255", "-b", "0,0,51", "-v255,0,0"],
+        ];
+        for args in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let group = super::handle_opts(&opts).unwrap();
+            let vectorset = &group.as_vectorsetgroup().unwrap().vectorsets[0];
+            assert!(close(vectorset.start, from_3(0.0, 0.0, 0.2)));
+            assert!(close(vectorset.vectors[0], from_3(1.0, 0.0, 0.0)));
+        }
+    }
+
+    #[test]
+    fn invalid_opts() {
+        #[rustfmt::skip]
+        let args_iter: [(&[&str], &str); 10] = [
+            (&["-v1,x,0"], "invalid value for --vector: \"1,x,0\""),
+            (&["--vectorsetorder=sorted"], "unknown value for --vectorsetorder: \"sorted\" (expected one of: random, cycle)"),
+            (&["-n", "--jitter=-0.1"], "invalid value for --jitter: \"-0.1\""),
+            (&["--type=square"], "unknown value for --type: \"square\" (expected one of: full, triangular, sum_one)"),
+            (&["--hues", "-v1,0,0", "-N"], "--normal cannot be used together with --hues"),
+            (&["--gamma=0"], "invalid value for --gamma: \"0\""),
+            (&["--gamma=2", "--gamma=2.2"], "multiple values specified for --gamma (extra value: \"2.2\")"),
+            (&["--hues", "--palette-colors=4"], "--palette-colors requires --palette-extract"),
+            (&["--vectorsetorder=cycle"], "--vectorsetorder requires more than one vectorset"),
+            (&["-v1,0,0", "--vectorsetorder=random"], "--vectorsetorder requires more than one vectorset"),
+        ];
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+
+        for (args, expected) in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let err = super::handle_opts(&opts).err().unwrap();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn jitter() {
+        let vectorset = |jitter| VectorSet {
+            start: from_3(0.5, 0.5, 0.5),
+            jitter,
+            vectors: vec![from_3(0.25, 0.0, 0.0)].into(),
+            chance: ONE,
+            kind: VectorSetKind::Full,
+        };
+
+        // No jitter uses the RNG exactly as before.
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let mut reference = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        for _ in 0..100 {
+            let multiplier: super::Channel = reference.gen_range(0.0..=1.0);
+            assert_eq!(
+                vectorset(0.0).new_color(&mut rng, 0),
+                from_3(0.5 + 0.25 * multiplier, 0.5, 0.5)
+            );
+        }
+
+        let jittered = vectorset(0.1);
+        let mut distinct_starts = vec![];
+        for _ in 0..1000 {
+            let color = jittered.new_color(&mut rng, 0);
+            // Without the red vector, which only adds at most 0.25.
+            let offset = color - from_3(0.5, 0.5, 0.5);
+            let (red, rest) = (offset[0], offset * from_3(0.0, 1.0, 1.0));
+            assert!((-0.1..=0.35).contains(&red), "{color:?}");
+            assert!((rest * rest).reduce_sum() <= 0.1 * 0.1, "{color:?}");
+            if !distinct_starts.contains(&rest) {
+                distinct_starts.push(rest);
+            }
+        }
+        assert!(distinct_starts.len() > 900);
+    }
+
+    #[test]
+    fn new_colors() {
+        /// Deterministic in the rng, without any batch method of its own.
+        #[derive(Debug)]
+        struct Stub;
+        impl ColorGenerator for Stub {
+            fn new_color(&self, rng: &mut dyn RngCore, _: usize) -> Color {
+                Color::splat(rng.next_u32() as super::Channel)
+            }
+        }
+
+        let group = |vectorsets| VectorSetGroup::new(vectorsets).unwrap();
+        let single = group(Cow::Borrowed(std::slice::from_ref(&BASIC_COLOR)));
+        let several = group(Cow::Borrowed(FULL_INTENSITY_HUES));
+        let cycle = several.clone().with_order(super::VectorSetOrder::Cycle);
+        let generators: [&dyn ColorGenerator; 5] =
+            [&Stub, &BASIC_COLOR, &single, &several, &cycle];
+        for generator in generators {
+            for n in [0, 1, 7] {
+                let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(3);
+                let mut reference = rand_chacha::ChaCha12Rng::seed_from_u64(3);
+                let mut colors = vec![Color::splat(-1.0); n];
+                generator.new_colors(&mut rng, 4, &mut colors);
+                let expected = (4..4 + n)
+                    .map(|i| generator.new_color(&mut reference, i))
+                    .collect::<Vec<_>>();
+                assert_eq!(colors, expected, "{generator:?}");
+                // Both used the rng the same amount.
+                assert_eq!(rng.next_u64(), reference.next_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn gamma() {
+        use super::GammaGenerator;
+
+        #[derive(Debug)]
+        struct Fixed(Color);
+        impl ColorGenerator for Fixed {
+            fn new_color(&self, _rng: &mut dyn RngCore, _: usize) -> Color {
+                self.0
+            }
+        }
+
+        let color = Color::from_array([0.5, 0.0, 1.0, 0.5]);
+        let corrected = |gamma| {
+            let generator =
+                GammaGenerator { inner: Box::new(Fixed(color)), gamma };
+            let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(0);
+            generator.new_color(&mut rng, 0)
+        };
+        assert_eq!(corrected(1.0), color);
+        let [r, g, b, a] = corrected(2.2).to_array();
+        assert!((r - 0.73).abs() < 0.001, "{r}");
+        assert_eq!([g, b, a], [0.0, 1.0, 0.5]);
+
+        // `--gamma` wraps whatever colorspace was chosen.
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let opts = getopt
+            .parse(["-b0.5,0.5,0.5", "--gamma=2.2"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let generator = super::handle_opts(&opts).unwrap();
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(0);
+        let new_color = generator.new_color(&mut rng, 0);
+        assert_eq!(new_color, from_3(r, r, r));
+        assert_eq!(generator.palette_size(), Some(1));
+    }
+
+    #[test]
+    fn palette_size() {
+        let args_iter: [(&[&str], Option<usize>); 6] = [
+            (&[], None),
+            (&["--hues"], None),
+            (&["-b1,0,0"], Some(1)),
+            (&["-b1,0,0", "-n", "-b0,1,0", "-n"], Some(3)),
+            (&["-b1,0,0", "-n", "-b0,1,0", "-v0,0,1"], None),
+            (&["-b1,0,0", "--jitter=0.1"], None),
+        ];
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+
+        for (args, expected) in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let generator = super::handle_opts(&opts).unwrap();
+            assert_eq!(generator.palette_size(), expected, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn solid_color() {
+        use getopt::{HasArgument, Opt};
+
+        let empty = VectorSet {
+            start: Color::default(),
+            jitter: 0.0,
+            vectors: Cow::Borrowed(&[]),
+            chance: ONE,
+            kind: VectorSetKind::Full,
+        };
+        assert!(super::solid_color(&empty).is_some());
+        assert!(super::solid_color(&BASIC_COLOR).is_none());
+        let jittered = VectorSet { jitter: 0.1, ..empty.clone() };
+        assert!(super::solid_color(&jittered).is_none());
+
+        let getopt = Getopt::from_iter(
+            super::opts()
+                .into_iter()
+                .chain([Opt::long("strict", HasArgument::No)]),
+        )
+        .unwrap();
+        let parse = |args: &[&str]| {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            super::handle_opts(&opts)
+        };
+        // Without --strict this is only a warning.
+        assert!(parse(&["-n"]).is_ok());
+        assert!(parse(&["-b1,0,0", "-n", "-b0,1,0", "-n"]).is_ok());
+        let err = parse(&["-n", "--strict"]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the color options only ever generate one color, so the image will be solid"
+        );
+        assert!(parse(&["--hues", "--strict"]).is_ok());
+    }
+
+    #[test]
+    fn vectorset_order() {
+        use super::VectorSetOrder;
+
+        let starts = [
+            from_3(1.0, 0.0, 0.0),
+            from_3(0.0, 1.0, 0.0),
+            from_3(0.0, 0.0, 1.0),
+        ];
+        let vectorsets = (1..)
+            .zip(starts)
+            .map(|(chance, start)| VectorSet {
+                start,
+                jitter: 0.0,
+                vectors: Cow::Borrowed(&[]),
+                chance: NonZeroUsize::new(chance).unwrap(),
+                kind: VectorSetKind::Full,
+            })
+            .collect::<Vec<_>>();
+        let group = VectorSetGroup::new(vectorsets.into()).unwrap();
+        let counts = |generator: &dyn ColorGenerator, seed| {
+            let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(seed);
+            let mut counts = [0; 3];
+            for i in 0..6 * 50 {
+                let color = generator.new_color(&mut rng, i);
+                let index = starts.iter().position(|&start| start == color);
+                counts[index.unwrap()] += 1;
+            }
+            counts
+        };
+
+        let cycle = group.clone().with_order(VectorSetOrder::Cycle);
+        assert_eq!(cycle.schedule, [2, 1, 0, 2, 1, 2]);
+        assert_eq!(counts(&cycle, 1), [50, 100, 150]);
+        assert_eq!(counts(&cycle, 2), [50, 100, 150]);
+        // The position comes from the index alone, so it is the same for
+        // clones, in any order, from any rng.
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let copy = cycle.clone();
+        for i in (0..12).rev() {
+            let expected = starts[cycle.schedule[i % 6]];
+            assert_eq!(copy.new_color(&mut rng, i), expected);
+            assert_eq!(cycle.new_color(&mut rng, i), expected);
+        }
+        assert!(copy == cycle);
+
+        let random = group.with_order(VectorSetOrder::Random);
+        for seed in 1..4 {
+            let counts = counts(&random, seed);
+            assert_ne!(counts, [50, 100, 150]);
+            assert!(counts[0] < counts[2], "{counts:?}");
+        }
+
+        // From the command line, every vectorset has the same chance.
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let args = ["-b1,0,0", "-n", "-b0,1,0", "--vectorsetorder=cycle"];
+        let opts = getopt
+            .parse(args)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let generator = super::handle_opts(&opts).unwrap();
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        for i in 0..10 {
+            assert_eq!(generator.new_color(&mut rng, i), starts[i % 2]);
+        }
+    }
+
+    #[test]
+    fn palette_extract() {
+        use crate::pnmdata::{OutputConfig, PnmData};
+
+        let (red, blue) = (from_3(1.0, 0.0, 0.0), from_3(0.0, 0.0, 1.0));
+        let image = PnmData {
+            dimx: 3,
+            dimy: 2,
+            output: OutputConfig::default(),
+            comments: vec![],
+            background: Color::default(),
+            rawdata: vec![red, blue, red, red, blue, blue],
+        };
+        let path = std::env::temp_dir().join(format!(
+            "imagegen-palette-test-{}.ppm",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        image.write_to(&mut file, &image.output, None).unwrap();
+        drop(file);
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let parse = |args: &[&str]| {
+            let extract = format!("--palette-extract={}", path.display());
+            let opts = getopt
+                .parse([&*extract].into_iter().chain(args.iter().copied()))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            super::handle_opts(&opts)
+        };
+        let generator = parse(&[]).unwrap();
+        let conflict = parse(&["--hues"]).err().unwrap().to_string();
+        let averaged = parse(&["--palette-colors=1"]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(generator.palette_size(), Some(2));
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(3);
+        let mut seen = [false; 2];
+        for _ in 0..32 {
+            let color = generator.new_color(&mut rng, 0);
+            assert!(color == red || color == blue, "{color:?}");
+            seen[(color == blue) as usize] = true;
+        }
+        assert_eq!(seen, [true, true]);
+        assert_eq!(
+            conflict,
+            "--palette-extract cannot be used together with --hues"
+        );
+        assert_eq!(averaged.palette_size(), Some(1));
+        assert_eq!(averaged.new_color(&mut rng, 0), from_3(0.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn median_cut() {
+        let max = NonZeroUsize::new(8).unwrap();
+        let gradient = (0..1000)
+            .map(|i| {
+                let t = i as Channel / 999.0;
+                from_3(t, 0.5, 1.0 - t)
+            })
+            .collect::<Vec<_>>();
+        let palette = super::extract_palette(&gradient, max);
+        assert_eq!(palette.len(), 8);
+        for color in &palette {
+            let [r, g, b, _] = color.to_array();
+            assert!((0.0..=1.0).contains(&r) && g == 0.5, "{color:?}");
+            assert!((r + b - 1.0).abs() < 1e-4, "{color:?}");
+        }
+
+        // Few enough distinct colors are kept exactly.
+        let few = [gradient[3], gradient[1], gradient[3], gradient[2]];
+        let palette = super::extract_palette(&few, max);
+        assert_eq!(palette.len(), 3);
+        assert!(few.iter().all(|color| palette.contains(color)));
+    }
 }
@@ -0,0 +1,409 @@
+//! Config file support.
+//!
+//! A config file is a TOML file with one table per option module, e.g.
+//!
+//! ```toml
+//! [setup]
+//! size = "1024x1024"
+//!
+//! [generate]
+//! seeds = 4
+//! offsets = ["n", "3,3"]
+//!
+//! [color]
+//! hues = true
+//!
+//! [progress]
+//! text = true
+//! ```
+//!
+//! Each key is the long name of an option in that module, optionally without
+//! the table name as a prefix (so `text` in `[progress]` is `--progresstext`).
+//! The config is translated into long-option arguments and parsed by the same
+//! `Getopt` as the command line, so every option works in a config file.
+//!
+//! Since options are grouped by key, the relative order of different options
+//! is not preserved; configurations where that matters (e.g. several color
+//! vector sets) should be given on the command line instead.
+
+use getopt::{GetoptItem, HasArgument, Opt};
+use toml::{Table, Value};
+
+use crate::error::ConfigError;
+
+pub fn opts() -> impl IntoIterator<Item = Opt> {
+    [
+        Opt::long("config", HasArgument::Yes),
+        Opt::long("dump-config", HasArgument::No),
+    ]
+}
+
+pub struct ConfigSettings {
+    /// Arguments translated from the config file, to be parsed with the same
+    /// `Getopt` as the command line and then passed to `merge`.
+    pub args: Vec<String>,
+    /// Whether to print the effective configuration instead of generating an
+    /// image.
+    pub dump: bool,
+}
+
+/// The option modules that can be configured, by table name.
+fn modules() -> [(&'static str, Vec<Opt>); 6] {
+    [
+        ("output", crate::output::opts().into_iter().collect()),
+        ("setup", crate::setup::opts().into_iter().collect()),
+        ("geometry", crate::geometry::opts().into_iter().collect()),
+        ("generate", crate::generate::opts().into_iter().collect()),
+        ("color", crate::color::opts().into_iter().collect()),
+        ("progress", crate::progress::opts().into_iter().collect()),
+    ]
+}
+
+/// Finds the option for `key` in the config table `table`.
+fn find_opt<'a>(opts: &'a [Opt], table: &str, key: &str) -> Option<&'a Opt> {
+    opts.iter().find(|opt| opt.is_long(key)).or_else(|| {
+        opts.iter().find(|opt| opt.is_long(&format!("{table}{key}")))
+    })
+}
+
+pub fn handle_opts(
+    opts: &[GetoptItem<'_>],
+) -> Result<ConfigSettings, ConfigError> {
+    let mut path = None;
+    let mut dump = false;
+    for opt in opts {
+        match opt {
+            GetoptItem::Opt { opt, arg: Some(arg) }
+                if opt.is_long("config") =>
+            {
+                if path.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "config",
+                        value: arg.to_string(),
+                    });
+                }
+                path = Some(*arg);
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("dump-config") =>
+            {
+                dump = true;
+            }
+            _ => {}
+        }
+    }
+
+    let args = match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|source| {
+                ConfigError::OpenFile {
+                    option: "config",
+                    path: path.to_string(),
+                    source,
+                }
+            })?;
+            to_args(&text)?
+        }
+        None => vec![],
+    };
+    Ok(ConfigSettings { args, dump })
+}
+
+/// Translates the contents of a config file into long-option arguments.
+fn to_args(text: &str) -> Result<Vec<String>, ConfigError> {
+    let root: Table = text.parse().map_err(|err: toml::de::Error| {
+        ConfigError::ConfigSyntax(err.to_string())
+    })?;
+    let modules = modules();
+
+    let mut args = vec![];
+    for (table_name, table) in &root {
+        let (opts, table) = match (
+            modules.iter().find(|(name, _)| name == table_name),
+            table,
+        ) {
+            (Some((_, opts)), Value::Table(table)) => (opts, table),
+            _ => {
+                return Err(ConfigError::UnknownConfigTable(table_name.clone()));
+            }
+        };
+        for (key, value) in table {
+            let invalid = |expected| ConfigError::InvalidConfigValue {
+                table: table_name.clone(),
+                key: key.clone(),
+                expected,
+            };
+            let opt = find_opt(opts, table_name, key).ok_or_else(|| {
+                ConfigError::UnknownConfigKey {
+                    table: table_name.clone(),
+                    key: key.clone(),
+                }
+            })?;
+            let long = opt.long.as_deref().expect("found by long name");
+            // Repeatable options are given as arrays.
+            let values = match value {
+                Value::Array(values) => &values[..],
+                value => std::slice::from_ref(value),
+            };
+            for value in values {
+                let arg = match (opt.has_argument, value) {
+                    (_, Value::Boolean(false)) => continue,
+                    (
+                        HasArgument::No | HasArgument::Optional,
+                        Value::Boolean(true),
+                    ) => {
+                        format!("--{long}")
+                    }
+                    (HasArgument::No, _) => return Err(invalid("a boolean")),
+                    (_, Value::String(value)) => format!("--{long}={value}"),
+                    (_, Value::Integer(value)) => format!("--{long}={value}"),
+                    (_, Value::Float(value)) => format!("--{long}={value}"),
+                    (HasArgument::Yes, _) => {
+                        return Err(invalid("a string or number"));
+                    }
+                    (HasArgument::Optional, _) => {
+                        return Err(invalid("a boolean, string, or number"));
+                    }
+                };
+                args.push(arg);
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Combines options from the config file with options from the command line.
+///
+/// Command-line options take precedence: an option given on the command line
+/// replaces every occurrence of that option from the config file. The sizing
+/// options (`-x`, `-y`, `--size`, and `--square`) are replaced together, since
+/// most of them cannot be mixed.
+pub fn merge<'a>(
+    config: Vec<GetoptItem<'a>>,
+    cli: Vec<GetoptItem<'a>>,
+) -> Vec<GetoptItem<'a>> {
+    let sizing = |opt: &Opt| {
+        ["x", "y", "size", "square"].into_iter().any(|long| opt.is_long(long))
+    };
+    let cli_opts = || {
+        cli.iter().filter_map(|item| match item {
+            GetoptItem::Opt { opt, .. } => Some(*opt),
+            GetoptItem::NonOpt(_) => None,
+        })
+    };
+    let cli_sizing = cli_opts().any(sizing);
+    let overridden = |config_opt: &Opt| {
+        (cli_sizing && sizing(config_opt))
+            || cli_opts().any(|opt| opt == config_opt)
+    };
+    let mut merged: Vec<_> = config
+        .into_iter()
+        .filter(|item| match item {
+            GetoptItem::Opt { opt, .. } => !overridden(opt),
+            GetoptItem::NonOpt(_) => true,
+        })
+        .collect();
+    merged.extend_from_slice(&cli);
+    merged
+}
+
+/// Formats `opts` as a config file that translates back into the same
+/// options.
+///
+/// If no seed was specified, `seed` (the one that was actually used) is
+/// included so that the dumped config reproduces the same image.
+pub fn dump(opts: &[GetoptItem<'_>], seed: u64) -> String {
+    let mut root = Table::new();
+    for (table_name, module_opts) in modules() {
+        let mut table = Table::new();
+        for item in opts {
+            let GetoptItem::Opt { opt, arg } = *item else { continue };
+            if !module_opts.contains(opt) {
+                continue;
+            }
+            let long = opt.long.as_deref().expect("all options are long");
+            // Use the shorter key if it refers to the same option.
+            let key = long
+                .strip_prefix(table_name)
+                .filter(|key| {
+                    // e.g. not `255` for `color255`.
+                    key.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && find_opt(&module_opts, table_name, key) == Some(opt)
+                })
+                .unwrap_or(long);
+            let value = match arg {
+                None => Value::Boolean(true),
+                Some(arg) => match arg.parse::<i64>() {
+                    Ok(value) if value.to_string() == arg => {
+                        Value::Integer(value)
+                    }
+                    _ => Value::String(arg.to_string()),
+                },
+            };
+            match table.get_mut(key) {
+                None => {
+                    table.insert(key.to_string(), value);
+                }
+                Some(Value::Array(values)) => values.push(value),
+                Some(prev) => {
+                    *prev = Value::Array(vec![prev.clone(), value]);
+                }
+            }
+        }
+        if table_name == "setup" && !table.contains_key("seed") {
+            let seed = match i64::try_from(seed) {
+                Ok(seed) => Value::Integer(seed),
+                Err(_) => Value::String(seed.to_string()),
+            };
+            table.insert("seed".to_string(), seed);
+        }
+        if !table.is_empty() {
+            root.insert(table_name.to_string(), Value::Table(table));
+        }
+    }
+    root.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use getopt::{Getopt, GetoptItem};
+
+    use crate::setup;
+
+    fn getopt() -> Getopt {
+        let module_opts =
+            super::modules().into_iter().flat_map(|(_, opts)| opts);
+        Getopt::from_iter(module_opts.chain(super::opts())).unwrap()
+    }
+
+    fn parse<'a>(
+        getopt: &'a Getopt,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<GetoptItem<'a>> {
+        getopt.parse(args).collect::<Result<Vec<_>, _>>().unwrap()
+    }
+
+    #[test]
+    fn example() {
+        let args = super::to_args(
+            r#"
+            [setup]
+            size = "1024x1024"
+
+            [generate]
+            seeds = 4
+            offsets = ["n", "3,3"]
+
+            [color]
+            hues = true
+
+            [progress]
+            text = true
+            interval = 512
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "--size=1024x1024",
+                "--seeds=4",
+                "--offsets=n",
+                "--offsets=3,3",
+                "--hues",
+                "--progresstext",
+                "--progressinterval=512",
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let getopt = getopt();
+        let args_iter: [&[&str]; 5] = [
+            &[],
+            &["-s64x32", "-S7", "-e3", "-On", "-O3,3", "--no-reseed"],
+            &["-x", "16", "-y8", "--maxval=65535", "--hues", "-T", "-I100"],
+            &["-S18446744073709551615", "-v1,0,0", "-v0,1,0", "-b0,0,0.5"],
+            &["--color255", "-v255,0,0", "--background=0,0,128"],
+        ];
+
+        for args in args_iter {
+            let opts = parse(&getopt, args.iter().copied());
+            let setup = setup::handle_opts(&opts, None).unwrap();
+            let dumped = super::dump(&opts, setup.seed);
+
+            let config_args = super::to_args(&dumped).unwrap();
+            let loaded = parse(&getopt, config_args.iter().map(String::as_str));
+            let loaded_setup = setup::handle_opts(&loaded, None).unwrap();
+            assert_eq!(loaded_setup.seed, setup.seed);
+            assert_eq!(
+                (loaded_setup.dimx, loaded_setup.dimy),
+                (setup.dimx, setup.dimy)
+            );
+            assert_eq!(
+                crate::generate::handle_opts(
+                    &loaded,
+                    loaded_setup.dimx,
+                    loaded_setup.dimy
+                )
+                .unwrap()
+                .offsets(),
+                crate::generate::handle_opts(&opts, setup.dimx, setup.dimy)
+                    .unwrap()
+                    .offsets(),
+            );
+            assert_eq!(
+                format!("{:?}", crate::color::handle_opts(&loaded).unwrap()),
+                format!("{:?}", crate::color::handle_opts(&opts).unwrap()),
+            );
+            assert_eq!(super::dump(&loaded, loaded_setup.seed), dumped);
+        }
+    }
+
+    #[test]
+    fn cli_overrides_config() {
+        let getopt = getopt();
+        let config_args =
+            super::to_args("[setup]\nsize = \"8x8\"\nseed = 1\n").unwrap();
+        let config = parse(&getopt, config_args.iter().map(String::as_str));
+        let cli = parse(&getopt, ["-S2"]);
+        let setup =
+            setup::handle_opts(&super::merge(config, cli), None).unwrap();
+        assert_eq!(setup.seed, 2);
+        assert_eq!(setup.size.get(), 64);
+
+        // Sizing options replace each other, instead of conflicting.
+        let cases: [(&str, &[&str], _); 3] = [
+            ("size = \"8x8\"", &["-x16", "-y4"], (16, 4)),
+            ("size = \"8x8\"", &["--square=5"], (5, 5)),
+            ("x = 16\ny = 4", &["-s8x8"], (8, 8)),
+        ];
+        for (sizing, cli, expected) in cases {
+            let config_args =
+                super::to_args(&format!("[setup]\n{sizing}\n")).unwrap();
+            let config = parse(&getopt, config_args.iter().map(String::as_str));
+            let cli = parse(&getopt, cli.iter().copied());
+            let setup =
+                setup::handle_opts(&super::merge(config, cli), None).unwrap();
+            let size = (setup.dimx.get(), setup.dimy.get());
+            assert_eq!(size, expected, "{sizing:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_config() {
+        #[rustfmt::skip]
+        let cases = [
+            ("[size]\n", "unknown config table [size] (expected one of: output, setup, geometry, generate, color, progress)"),
+            ("size = \"8x8\"\n", "unknown config table [size] (expected one of: output, setup, geometry, generate, color, progress)"),
+            ("[setup]\nwidth = 8\n", "unknown key `width` in config table [setup]"),
+            ("[color]\nhues = \"yes\"\n", "invalid value for `hues` in config table [color]: expected a boolean"),
+            ("[generate]\nseeds = true\n", "invalid value for `seeds` in config table [generate]: expected a string or number"),
+        ];
+        for (text, expected) in cases {
+            let err = super::to_args(text).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+}
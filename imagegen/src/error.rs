@@ -0,0 +1,123 @@
+use getopt::GetoptError;
+
+/// An error in the command-line configuration.
+///
+/// `option` fields are long option names, without the leading `--`.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("unrecognized option or missing argument: {0}")]
+    UnrecognizedOption(String),
+    #[error("unexpected argument {0:?} (did you mean `-o {0}`?)")]
+    UnexpectedArgument(String),
+    #[error("multiple values specified for --{option} (extra value: {value:?})")]
+    Duplicate { option: &'static str, value: String },
+    #[error("--{option} specifies {value:?} more than once")]
+    RepeatedValue { option: &'static str, value: String },
+    #[error("invalid value for --{option}: {value:?}")]
+    InvalidValue { option: &'static str, value: String },
+    #[error("invalid value for ${var}: {value:?}")]
+    InvalidEnvValue { var: &'static str, value: String },
+    #[error(
+        "unknown value for --{option}: {value:?} (expected one of: {expected})"
+    )]
+    UnknownValue { option: &'static str, value: String, expected: &'static str },
+    #[error("--{option} cannot be used together with --{other}")]
+    Conflict { option: &'static str, other: &'static str },
+    #[error("--{option} requires {requirement}")]
+    Requires { option: &'static str, requirement: &'static str },
+    #[error("--{option} is not supported yet")]
+    Unsupported { option: &'static str },
+    #[error("failed to open {path:?} for --{option}: {source}")]
+    OpenFile {
+        option: &'static str,
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to read {path:?} for --{option}: {source}")]
+    ReadFile {
+        option: &'static str,
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(
+        "--mask image {path:?} is {width}x{height}, but the image is {dimx}x{dimy}"
+    )]
+    MaskSize {
+        path: String,
+        width: u32,
+        height: u32,
+        dimx: usize,
+        dimy: usize,
+    },
+    #[error(
+        "--maxval {maxval} may lose precision with 32-bit float channels (build without the `f32` feature for deep color)"
+    )]
+    PrecisionLoss { maxval: u32 },
+    #[error(
+        "the color options only ever generate one color, so the image will be solid"
+    )]
+    SolidColor,
+    #[error(
+        "requested {width}x{height} is too large (each dimension must be at most {max})"
+    )]
+    DimensionTooLarge { width: usize, height: usize, max: usize },
+    #[error(
+        "requested {width}x{height} needs ~{}, which is more than --max-pixels-allowed ({limit} pixels)",
+        crate::setup::format_bytes(*.bytes)
+    )]
+    TooManyPixels { width: usize, height: usize, bytes: u128, limit: usize },
+    #[error("requested {width}x{height} is too large to allocate")]
+    ImageTooLarge { width: usize, height: usize },
+    #[error(
+        "refusing to write binary image data to a terminal (redirect stdout, e.g. `imagegen > out.ppm`, use -o <path>, or pass --force-stdout)"
+    )]
+    StdoutIsTerminal,
+    #[error("invalid config file: {0}")]
+    ConfigSyntax(String),
+    #[error(
+        "unknown config table [{0}] (expected one of: output, setup, geometry, generate, color, progress)"
+    )]
+    UnknownConfigTable(String),
+    #[error("unknown key `{key}` in config table [{table}]")]
+    UnknownConfigKey { table: String, key: String },
+    #[error(
+        "invalid value for `{key}` in config table [{table}]: expected {expected}"
+    )]
+    InvalidConfigValue { table: String, key: String, expected: &'static str },
+}
+
+impl From<GetoptError<'_>> for ConfigError {
+    fn from(err: GetoptError<'_>) -> Self {
+        let option = match err {
+            GetoptError::UnrecognizedShortOpt { opt, .. } => format!("-{opt}"),
+            GetoptError::UnrecognizedLongOpt { opt, .. } => format!("--{opt}"),
+        };
+        ConfigError::UnrecognizedOption(option)
+    }
+}
+
+/// An error that stops generating images.
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to write output image to {destination}: {source}")]
+    Output {
+        destination: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A progressor failed (see `ProgressSupervisorData::fail`). The image is
+    /// still written before this is returned.
+    #[error("failed to write progress frame to {destination}: {source}")]
+    Progress {
+        destination: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The partial image is still written before this is returned.
+    #[error("the {thread} thread panicked: {message}")]
+    Panicked { thread: &'static str, message: String },
+}
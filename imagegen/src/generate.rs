@@ -1,16 +1,21 @@
 use std::{
-    collections::VecDeque,
+    collections::{BTreeSet, VecDeque},
     num::NonZeroUsize,
     ops::Range,
-    sync::{atomic::Ordering, Arc},
+    simd::num::SimdFloat,
+    str::FromStr,
+    sync::{atomic::Ordering, mpsc, Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
 use bitmap::BitMap;
 use getopt::{GetoptItem, Opt};
-use rand::{seq::SliceRandom, Rng, RngCore};
+use rand::{seq::SliceRandom, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
 
 use crate::{
-    color::{Channel, Color, ColorGenerator},
+    color::{color255, parse_color, Channel, Color, ColorGenerator},
+    error::ConfigError,
     pnmdata::PnmData,
     CommonData, CommonLockedData,
 };
@@ -37,8 +42,6 @@ impl std::ops::Add<Offset> for Pixel {
     }
 }
 
-// TODO: somehow make fitness function configurable
-
 #[derive(Clone)]
 pub struct GeneratorData {}
 pub trait Generator: std::fmt::Debug {
@@ -56,6 +59,38 @@ pub trait Generator: std::fmt::Debug {
     fn offsets(&self) -> &[Offset];
 }
 
+/// Places the `--seedat` seeds, in order, with their fixed colors or new
+/// colors from `color_generator`. They were checked to be in bounds and
+/// distinct when parsed. `generated` counts the new colors (see
+/// `ColorGenerator::new_color`).
+fn place_fixed_seeds(
+    seeds: &[(Pixel, Option<Color>)],
+    data: &mut CommonLockedData,
+    color_generator: &dyn ColorGenerator,
+    rng: &mut dyn RngCore,
+    generated: &mut usize,
+) -> Vec<Pixel> {
+    seeds
+        .iter()
+        .filter_map(|&(pixel @ Pixel { x, y }, color)| {
+            // Only `--mask` can have placed it already.
+            if data.placed_pixels.get((y as usize, x as usize)) {
+                log::warn!(
+                    "not placing seed at ({x},{y}), which is masked out"
+                );
+                return None;
+            }
+            log::trace!("placing fixed seed at ({x},{y})");
+            let color = color.unwrap_or_else(|| {
+                *generated += 1;
+                color_generator.new_color(rng, *generated - 1)
+            });
+            data.place((y as usize, x as usize), color);
+            Some(pixel)
+        })
+        .collect()
+}
+
 fn place_seeds_common(
     count: usize,
     dimx: NonZeroUsize,
@@ -63,6 +98,7 @@ fn place_seeds_common(
     data: &mut CommonLockedData,
     color_generator: &dyn ColorGenerator,
     rng: &mut dyn RngCore,
+    generated: &mut usize,
 ) -> Vec<Pixel> {
     log::trace!("placing {count} seeds");
     let mut placed = Vec::with_capacity(count);
@@ -83,8 +119,8 @@ fn place_seeds_common(
 
             log::trace!("placing seed at ({x},{y})");
 
-            data.image[(y, x)] = color_generator.new_color(rng);
-            data.placed_pixels.set((y, x), true);
+            data.place((y, x), color_generator.new_color(rng, *generated));
+            *generated += 1;
             placed.push(Pixel { x: x as _, y: y as _ });
 
             successes += 1;
@@ -95,20 +131,17 @@ fn place_seeds_common(
         log::trace!(
             "naive seeding failed {failures} times (it placed {successes} / {count}); using slower always-successful version"
         );
-        let mut all_empty = Vec::with_capacity(dimx.get());
         log::trace!(
             "{} placed pixels according to bitmap",
             data.placed_pixels.count_ones()
         );
-        data.placed_pixels.for_each_false(|row, col| {
-            debug_assert!(!data.placed_pixels.get((row, col)));
-            all_empty.push((row, col));
-        });
-        for &(y, x) in all_empty.choose_multiple(rng, count - successes) {
+        let chosen =
+            choose_empty_pixels(&data.placed_pixels, count - successes, rng);
+        for (y, x) in chosen {
             log::trace!("placing seed at ({x},{y})");
 
-            data.image[(y, x)] = color_generator.new_color(rng);
-            data.placed_pixels.set((y, x), true);
+            data.place((y, x), color_generator.new_color(rng, *generated));
+            *generated += 1;
             placed.push(Pixel { x: x as _, y: y as _ });
 
             successes += 1;
@@ -117,83 +150,719 @@ fn place_seeds_common(
     placed
 }
 
+/// A `--seedpattern` value: where the initial seeds go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SeedPattern {
+    /// Random empty pixels (see `place_seeds_common`).
+    #[default]
+    Random,
+    /// The center of the image.
+    Center,
+    /// The four corners, clockwise from the top left.
+    Corners,
+    /// The center of each cell of a `cols`x`rows` grid, in row-major order.
+    Grid { cols: NonZeroUsize, rows: NonZeroUsize },
+    /// Evenly spaced around the border, clockwise from the top left.
+    Border,
+}
+
+impl FromStr for SeedPattern {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SeedPattern::Random),
+            "center" => Ok(SeedPattern::Center),
+            "corners" => Ok(SeedPattern::Corners),
+            "border" => Ok(SeedPattern::Border),
+            _ => {
+                let (cols, rows) = s
+                    .strip_prefix("grid:")
+                    .and_then(|grid| grid.split_once('x'))
+                    .ok_or(())?;
+                Ok(SeedPattern::Grid {
+                    cols: cols.parse().map_err(|_| ())?,
+                    rows: rows.parse().map_err(|_| ())?,
+                })
+            }
+        }
+    }
+}
+
+impl SeedPattern {
+    /// How many seeds to place when `--seeds` is not given.
+    fn default_count(self) -> usize {
+        match self {
+            SeedPattern::Random | SeedPattern::Center => 1,
+            SeedPattern::Corners | SeedPattern::Border => 4,
+            SeedPattern::Grid { cols, rows } => cols.get() * rows.get(),
+        }
+    }
+
+    /// Up to `count` distinct locations from the pattern, in order. There
+    /// may be fewer if the pattern does not have room for `count` seeds in a
+    /// `dimx`x`dimy` image; the rest should be placed randomly.
+    fn locations(
+        self,
+        count: usize,
+        dimx: NonZeroUsize,
+        dimy: NonZeroUsize,
+    ) -> Vec<Pixel> {
+        let (w, h) = (dimx.get(), dimy.get());
+        let pixel = |x: usize, y: usize| Pixel { x: x as _, y: y as _ };
+        let mut locations = match self {
+            SeedPattern::Random => vec![],
+            SeedPattern::Center => vec![pixel(w / 2, h / 2)],
+            SeedPattern::Corners => vec![
+                pixel(0, 0),
+                pixel(w - 1, 0),
+                pixel(w - 1, h - 1),
+                pixel(0, h - 1),
+            ],
+            SeedPattern::Grid { cols, rows } => {
+                let (cols, rows) = (cols.get(), rows.get());
+                (0..rows)
+                    .flat_map(|row| {
+                        (0..cols).map(move |col| {
+                            let x = (2 * col + 1) * w / (2 * cols);
+                            let y = (2 * row + 1) * h / (2 * rows);
+                            pixel(x, y)
+                        })
+                    })
+                    .collect()
+            }
+            SeedPattern::Border => {
+                let border: Vec<Pixel> = if w == 1 || h == 1 {
+                    (0..h)
+                        .flat_map(|y| (0..w).map(move |x| pixel(x, y)))
+                        .collect()
+                } else {
+                    let top = (0..w - 1).map(|x| pixel(x, 0));
+                    let right = (0..h - 1).map(|y| pixel(w - 1, y));
+                    let bottom = (1..w).rev().map(|x| pixel(x, h - 1));
+                    let left = (1..h).rev().map(|y| pixel(0, y));
+                    top.chain(right).chain(bottom).chain(left).collect()
+                };
+                let count = count.min(border.len());
+                (0..count).map(|i| border[i * border.len() / count]).collect()
+            }
+        };
+        // Small images can put several pattern locations on the same pixel.
+        let mut seen = BitMap::new(h, w).expect("setup checked the size");
+        locations.retain(|&Pixel { x, y }| {
+            let (y, x) = (y as usize, x as usize);
+            let new = !seen.get((y, x));
+            seen.set((y, x), true);
+            new
+        });
+        locations.truncate(count);
+        locations
+    }
+}
+
+/// Chooses `count` distinct unplaced pixels uniformly at random (or all of
+/// them, if there are fewer than `count`), as `(row, col)` pairs.
+///
+/// This uses reservoir sampling, so it only needs `O(count)` memory instead
+/// of collecting every unplaced pixel.
+fn choose_empty_pixels(
+    placed_pixels: &BitMap,
+    count: usize,
+    rng: &mut dyn RngCore,
+) -> Vec<(usize, usize)> {
+    let mut reservoir = Vec::with_capacity(count);
+    let mut seen = 0usize;
+    placed_pixels.for_each_false(|row, col| {
+        if reservoir.len() < count {
+            reservoir.push((row, col));
+        } else {
+            let idx = rng.gen_range(0..=seen);
+            if idx < count {
+                reservoir[idx] = (row, col);
+            }
+        }
+        seen += 1;
+    });
+    reservoir
+}
+
 /// For inner generation, only one neighbor is considered for fitness.
 /// Edges for inner generators are the actual placed pixels; when an edge is
 /// found to be the "best" for a color, that color is placed adjacent to the
 /// edge (and becomes an edge itself)
 #[derive(Debug, Clone)]
 struct InnerGenerator {
-    seeds: NonZeroUsize,
+    /// How many seeds to place randomly, after `seedat`.
+    seeds: usize,
+    /// Seeds at fixed locations, with fixed colors or new ones.
+    seedat: Vec<(Pixel, Option<Color>)>,
     offsets: Vec<Offset>,
     workers: NonZeroUsize,
     colorcount: NonZeroUsize,
     maxfitness: Option<Channel>,
+    /// Once this many pixels are placed, each color goes to the place it fits
+    /// worst instead of best (see `maximizing`).
+    invert_fitness_after: Option<usize>,
+    /// If `false`, generation finishes when there are no edges left instead
+    /// of placing a new seed.
+    reseed: bool,
+    /// Whether to place each color at its edge's best-fitting open neighbor,
+    /// instead of the first open one (see `place_pixel_inner`).
+    smartplace: bool,
+    /// At most how many edges each iteration considers, chosen by
+    /// `edgeselect` (see `EdgeSelect::select`). `None` considers
+    /// every edge.
+    edgelimit: Option<NonZeroUsize>,
+    edgeselect: EdgeSelect,
+    /// How to compare an edge's color to a new color.
+    fitness: Fitness,
+    /// Whether to compare new colors to the average of the placed pixels
+    /// around each open neighbor of an edge, and place them there, instead
+    /// of comparing them to the edge's color (see `neighborhood_candidates`).
+    neighborhood: bool,
+    /// Whether each worker generates its own share of the colors, from its
+    /// own RNG seeded by the main one, and considers every edge for them,
+    /// instead of the supervisor generating all of them. Only used with more
+    /// than one worker.
+    worker_colors: bool,
+    /// With `--mutate`, each iteration places one color, made by changing
+    /// each channel of a random edge's color by up to this much, next to that
+    /// edge, instead of generating colors and finding where they fit best.
+    /// Only used with one worker.
+    mutate: Option<Channel>,
+    stop: StopConditions,
+}
+
+/// Which edges an iteration considers when there are more than `--edgelimit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EdgeSelect {
+    /// The oldest edges.
+    Fifo,
+    /// The newest edges, newest first, which makes more branching shapes.
+    Lifo,
+    /// A uniform sample of the edges, using the run's RNG.
+    Random,
+}
+
+impl EdgeSelect {
+    /// Chooses which of the `len` edges this iteration considers, by their
+    /// indices in `edges`, into `selected`. Returns `false` (and leaves
+    /// `selected` alone) if every edge is considered, in order.
+    fn select(
+        self,
+        limit: Option<NonZeroUsize>,
+        len: usize,
+        rng: &mut dyn RngCore,
+        selected: &mut Vec<usize>,
+    ) -> bool {
+        let limit = match limit {
+            Some(limit) if limit.get() < len => limit.get(),
+            _ => return false,
+        };
+        selected.clear();
+        match self {
+            EdgeSelect::Fifo => selected.extend(0..limit),
+            EdgeSelect::Lifo => selected.extend((len - limit..len).rev()),
+            EdgeSelect::Random => {
+                selected.extend(rand::seq::index::sample(rng, len, limit));
+                // Keep the same order as `Fifo`, for locality.
+                selected.sort_unstable();
+            }
+        }
+        true
+    }
+}
+
+/// Whether any of `pixel + offset` for `offsets` is in bounds and not placed.
+fn has_open_neighbor(
+    dimy: NonZeroUsize,
+    dimx: NonZeroUsize,
+    pixel: Pixel,
+    placed_pixels: &BitMap,
+    offsets: &[Offset],
+) -> bool {
+    // if let Some(canonical) = geometry.canonicalize(pixel + offset) {...}
+    offsets.iter().any(|&offset| {
+        in_bounds(dimy, dimx, pixel + offset)
+            .is_some_and(|location| !placed_pixels.get(location))
+    })
 }
 
-fn validate_inner_edges(
+/// Removes the edges without any open neighbors, but only checks `placed` (the
+/// pixels placed since the last validation) and the edges they could have
+/// closed off, instead of every edge. The order of the remaining edges is
+/// unchanged.
+///
+/// `closed` is scratch space the size of the image, which must be clear, and
+/// is left clear.
+fn validate_edges_near(
     dimy: NonZeroUsize,
     dimx: NonZeroUsize,
     edges: &mut VecDeque<Pixel>,
     placed_pixels: &BitMap,
     offsets: &[Offset],
+    placed: impl IntoIterator<Item = Pixel>,
+    closed: &mut BitMap,
 ) {
-    edges.retain(|pixel| {
-        placed_pixels.get((pixel.y as usize, pixel.x as usize)) && {
-            let mut any_neighbor_open = false;
-            'offsets: for offset in offsets {
-                // if let Some(canonical) = geometry.canonicalize(pixel +
-                // offset) {...}
-                let y = pixel.y + offset.dy;
-                if y < 0 || y as usize >= dimy.get() {
-                    continue 'offsets;
-                }
-                let x = pixel.x + offset.dx;
-                if x < 0 || x as usize >= dimx.get() {
-                    continue 'offsets;
+    let mut newly_closed = vec![];
+    for pixel in placed {
+        // `pixel` closes off `edge` if `edge + offset == pixel`.
+        let edges = offsets.iter().map(|&Offset { dx, dy }| {
+            pixel + Offset { dx: -dx, dy: -dy }
+        });
+        for edge in std::iter::once(pixel).chain(edges) {
+            let Some(location) = in_bounds(dimy, dimx, edge) else {
+                continue;
+            };
+            if placed_pixels.get(location)
+                && !closed.get(location)
+                && !has_open_neighbor(dimy, dimx, edge, placed_pixels, offsets)
+            {
+                closed.set(location, true);
+                newly_closed.push(location);
+            }
+        }
+    }
+    // Only a pixel that was just placed, or that was next to one, can have
+    // just been closed off, and neither had been removed yet, so everything in
+    // `newly_closed` is in `edges`. This still checks every edge, but only
+    // when there is something to remove, and only with one bitmap lookup each.
+    if newly_closed.is_empty() {
+        return;
+    }
+    edges.retain(|&Pixel { x, y }| !closed.get((y as usize, x as usize)));
+    for location in newly_closed {
+        closed.set(location, false);
+    }
+}
+
+/// Returns `location` as `(row, col)` if it is in bounds.
+fn in_bounds(
+    dimy: NonZeroUsize,
+    dimx: NonZeroUsize,
+    Pixel { x, y }: Pixel,
+) -> Option<(usize, usize)> {
+    let y = usize::try_from(y).ok().filter(|&y| y < dimy.get())?;
+    let x = usize::try_from(x).ok().filter(|&x| x < dimx.get())?;
+    Some((y, x))
+}
+
+/// The squared difference between two colors. Lower is a better fit.
+fn color_distance(a: Color, b: Color) -> Channel {
+    let diff = a - b;
+    let sq_diff = diff * diff;
+    sq_diff.as_array().iter().sum()
+}
+
+/// A `--fitness` value: how far apart two colors are. Lower is a better fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Fitness {
+    /// The sum of the squared channel differences (see `color_distance`).
+    #[default]
+    SumSq,
+    /// The sum of the absolute channel differences.
+    Abs,
+    /// The largest absolute channel difference.
+    MaxChannel,
+    /// The squared difference in (Rec. 709) luma, ignoring hue entirely.
+    Luma,
+}
+
+impl FromStr for Fitness {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sumsq" => Ok(Fitness::SumSq),
+            "abs" => Ok(Fitness::Abs),
+            "maxchannel" => Ok(Fitness::MaxChannel),
+            "luma" => Ok(Fitness::Luma),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How well `b` fits next to `a` by `kind`. Lower is better.
+fn fitness(kind: Fitness, a: Color, b: Color) -> Channel {
+    let diff = a - b;
+    match kind {
+        Fitness::SumSq => color_distance(a, b),
+        Fitness::Abs => diff.abs().reduce_sum(),
+        Fitness::MaxChannel => diff.abs().reduce_max(),
+        Fitness::Luma => {
+            let weights = Color::from_array([0.2126, 0.7152, 0.0722, 0.0]);
+            let luma = (diff * weights).reduce_sum();
+            luma * luma
+        }
+    }
+}
+
+/// The largest palette (see `ColorGenerator::palette_size`) that
+/// `DistanceCache` remembers distances for. Rows are found by a linear
+/// search, so this should stay small.
+const MAX_CACHED_PALETTE: usize = 16;
+
+/// Remembers the distances from each edge color to an iteration's new colors
+/// if the color generator only has a small palette, since then most edges
+/// share a color and would compute the same distances over and over.
+#[derive(Debug)]
+struct DistanceCache {
+    enabled: bool,
+    fitness: Fitness,
+    /// The bits of an edge color, and its distance to each new color.
+    rows: Vec<(ColorBits, Vec<Channel>)>,
+    /// How many of `rows` are for this iteration. The rest are only kept to
+    /// reuse their allocations.
+    used: usize,
+}
+
+type ColorBits = <Color as SimdFloat>::Bits;
+
+impl DistanceCache {
+    fn new(palette_size: Option<usize>, fitness: Fitness) -> Self {
+        let enabled = palette_size.is_some_and(|n| n <= MAX_CACHED_PALETTE);
+        DistanceCache { enabled, fitness, rows: vec![], used: 0 }
+    }
+
+    /// Forgets every row, at the start of an iteration.
+    fn clear(&mut self) {
+        self.used = 0;
+    }
+
+    /// The fitness of each of `colors` next to `edge`, which must be the same
+    /// for the whole iteration. `None` if the cache is disabled (or full, if
+    /// `edge` is somehow not in the palette), so the caller should compute
+    /// the distances itself.
+    fn row(&mut self, edge: Color, colors: &[Color]) -> Option<&[Channel]> {
+        if !self.enabled {
+            return None;
+        }
+        let bits = edge.to_bits();
+        let rows = &self.rows[..self.used];
+        let index = match rows.iter().position(|(row, _)| *row == bits) {
+            Some(index) => index,
+            None if self.used == MAX_CACHED_PALETTE => return None,
+            None => {
+                if self.used == self.rows.len() {
+                    self.rows.push((bits, vec![]));
                 }
-                if !placed_pixels.get((y as usize, x as usize)) {
-                    any_neighbor_open = true;
-                    break 'offsets;
+                let (row_bits, row) = &mut self.rows[self.used];
+                *row_bits = bits;
+                row.clear();
+                let kind = self.fitness;
+                row.extend(
+                    colors.iter().map(|&color| fitness(kind, edge, color)),
+                );
+                self.used += 1;
+                self.used - 1
+            }
+        };
+        Some(&self.rows[index].1)
+    }
+}
+
+/// How well `color` would fit at `location`: the mean `placement.fitness`
+/// distance between it and the placed neighbors of `location` (by
+/// `placement.offsets`). Lower is better, and a location with no placed
+/// neighbors fits worst.
+fn placement_fitness(
+    placement: Placement<'_>,
+    location: Pixel,
+    color: Color,
+    image: &PnmData,
+    placed_pixels: &BitMap,
+) -> Channel {
+    let Placement { dimy, dimx, offsets, fitness: kind, .. } = placement;
+    let mut total = 0.0;
+    let mut count = 0;
+    for &offset in offsets {
+        let Some(neighbor) = in_bounds(dimy, dimx, location + offset) else {
+            continue;
+        };
+        if placed_pixels.get(neighbor) {
+            total += fitness(kind, color, image[neighbor]);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        Channel::INFINITY
+    } else {
+        total / count as Channel
+    }
+}
+
+/// Finds each open neighbor of `edge` (by `offsets`) and the average color of
+/// the placed pixels that it is a neighbor of, for `--neighborhood`. Those
+/// always include `edge` itself.
+fn neighborhood_candidates(
+    dimy: NonZeroUsize,
+    dimx: NonZeroUsize,
+    edge: Pixel,
+    image: &PnmData,
+    placed_pixels: &BitMap,
+    offsets: &[Offset],
+    candidates: &mut Vec<(Pixel, Color)>,
+) {
+    candidates.clear();
+    for &offset in offsets {
+        let location = edge + offset;
+        match in_bounds(dimy, dimx, location) {
+            Some(location) if !placed_pixels.get(location) => {}
+            _ => continue,
+        }
+        let mut total = Color::default();
+        let mut count = 0;
+        for &Offset { dx, dy } in offsets {
+            let neighbor = location + Offset { dx: -dx, dy: -dy };
+            match in_bounds(dimy, dimx, neighbor) {
+                Some(neighbor) if placed_pixels.get(neighbor) => {
+                    total += image[neighbor];
+                    count += 1;
                 }
+                _ => {}
             }
-            any_neighbor_open
         }
-    });
+        candidates.push((location, total / Color::splat(count as Channel)));
+    }
 }
 
-/// Chooses a neighbor to `pixel`, places `color` in the data at that location,
-/// sets it as placed in the bitmap, and adds it as an edge.
-fn place_pixel_inner(
+/// `--maxpixels` and `--maxseconds`: when to stop before the image is full.
+#[derive(Debug, Clone, Copy, Default)]
+struct StopConditions {
+    max_pixels: Option<usize>,
+    max_duration: Option<Duration>,
+}
+
+impl StopConditions {
+    /// Whether generation should stop, with `placed` pixels placed in a run
+    /// that began at `start`.
+    fn reached(&self, start: Instant, placed: usize) -> bool {
+        self.max_pixels.is_some_and(|max| placed >= max)
+            || self.max_duration.is_some_and(|max| start.elapsed() >= max)
+    }
+}
+
+/// For `--mutate`: `color` with each of red, green, and blue moved by up to
+/// `amount` either way, clamped to [0, 1].
+fn mutate(color: Color, amount: Channel, rng: &mut dyn RngCore) -> Color {
+    let mut channels = color.to_array();
+    for channel in &mut channels[..3] {
+        let offset = rng.gen_range(-amount..=amount);
+        *channel = (*channel + offset).clamp(0.0, 1.0);
+    }
+    Color::from_array(channels)
+}
+
+/// Whether colors go to the place with the highest fitness (the worst fit)
+/// instead of the lowest, once `placed` pixels are placed.
+fn maximizing(invert_fitness_after: Option<usize>, placed: usize) -> bool {
+    invert_fitness_after.is_some_and(|after| placed >= after)
+}
+
+/// At most how many places are kept for each color (see `BestPlaces`).
+const MAX_PLACE_CANDIDATES: usize = 16;
+
+/// The best places found for one color, best first.
+///
+/// With more than one color per iteration, several colors often fit best at
+/// the same edge, so each keeps up to as many places as there are colors (but
+/// at most `MAX_PLACE_CANDIDATES`), and goes to the best one that no earlier
+/// color used (see `InnerGenerator::place_colors`).
+#[derive(Debug, Clone, Default)]
+struct BestPlaces(Vec<(Pixel, Channel)>);
+
+impl BestPlaces {
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn best(&self) -> Option<(Pixel, Channel)> {
+        self.0.first().copied()
+    }
+
+    /// Inserts `place` in order, keeping at most `limit` places. It goes
+    /// before places that fit equally well if `ties_win`, and after them
+    /// otherwise.
+    fn insert(
+        &mut self,
+        place: (Pixel, Channel),
+        limit: usize,
+        maximize: bool,
+        ties_win: bool,
+    ) {
+        let (_, fitness) = place;
+        let index = self
+            .0
+            .iter()
+            .position(|&(_, other)| match (maximize, ties_win) {
+                (false, true) => fitness <= other,
+                (false, false) => fitness < other,
+                (true, true) => fitness >= other,
+                (true, false) => fitness > other,
+            })
+            .unwrap_or(self.0.len());
+        if index < limit {
+            self.0.truncate(limit - 1);
+            self.0.insert(index, place);
+        }
+    }
+
+    /// Adds the places from `other` (e.g. another worker's), which lose ties.
+    fn merge(&mut self, other: &BestPlaces, limit: usize, maximize: bool) {
+        for &place in &other.0 {
+            self.insert(place, limit, maximize, false);
+        }
+    }
+}
+
+/// Adds `place` to `best` if it fits at least as well (or, if `maximize`, at
+/// least as badly) as any of the `limit` places kept, unless its fitness is
+/// not below `maxfitness`.
+fn update_best_place(
+    best: &mut BestPlaces,
+    place: (Pixel, Channel),
+    maxfitness: Option<Channel>,
+    maximize: bool,
+    limit: usize,
+) {
+    let (_, fitness) = place;
+    if maxfitness.is_some_and(|max| fitness >= max) {
+        return;
+    }
+    best.insert(place, limit, maximize, true);
+}
+
+/// How `place_pixel_inner` chooses the neighbor of an edge to place a color
+/// in.
+#[derive(Debug, Clone, Copy)]
+struct Placement<'a> {
     dimy: NonZeroUsize,
     dimx: NonZeroUsize,
+    /// The neighbors to consider, in order.
+    offsets: &'a [Offset],
+    /// Whether to choose the open neighbor where the color fits best (see
+    /// `placement_fitness`), instead of the first.
+    smart: bool,
+    /// How `placement_fitness` compares colors, for `--fitness`.
+    fitness: Fitness,
+}
+
+/// Chooses a neighbor to `pixel` (see `Placement`), places `color` in the
+/// data at that location, sets it as placed in the bitmap, and adds it as an
+/// edge.
+fn place_pixel_inner(
+    placement: Placement<'_>,
     pixel: Pixel,
     color: Color,
     image: &mut PnmData,
     edges: &mut VecDeque<Pixel>,
     placed_pixels: &mut BitMap,
-    offsets: &[Offset],
 ) -> Result<Pixel, ()> {
-    for offset in offsets {
-        let y = pixel.y + offset.dy;
-        if y < 0 || y as usize >= dimy.get() {
-            continue;
+    let Placement { dimy, dimx, offsets, smart, .. } = placement;
+    let mut open =
+        offsets.iter().map(|&offset| pixel + offset).filter(|&location| {
+            match in_bounds(dimy, dimx, location) {
+                Some(location) => !placed_pixels.get(location),
+                None => false,
+            }
+        });
+    let location = if smart {
+        // Ties go to the first in `offsets`.
+        open.map(|location| {
+            let fitness = placement_fitness(
+                placement,
+                location,
+                color,
+                image,
+                placed_pixels,
+            );
+            (location, fitness)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(location, _)| location)
+    } else {
+        open.next()
+    };
+    let location = location.ok_or(())?;
+    let (y, x) = (location.y as usize, location.x as usize);
+    placed_pixels.set((y, x), true);
+    image[(y, x)] = color;
+    edges.push_back(location);
+    Ok(location)
+}
+
+impl InnerGenerator {
+    /// Places each color next to the best of its `best_places` that no
+    /// earlier color in this batch used, so that the colors spread out
+    /// instead of all crowding around one edge. If that fails (e.g. because
+    /// every such place was used, or its open neighbors were taken), the
+    /// color goes next to its best place anyway, if there is still room.
+    fn place_colors(
+        &self,
+        common_data: &CommonData,
+        locked: &mut CommonLockedData,
+        colors: &[Color],
+        best_places: &[BestPlaces],
+    ) {
+        let mut used = BTreeSet::new();
+        for (&color, best) in colors.iter().zip(best_places) {
+            let Some((best_pixel, _)) = best.best() else { continue };
+            let unused = best
+                .0
+                .iter()
+                .map(|&(pixel, _)| pixel)
+                .filter(|&pixel| used.insert(pixel));
+            let placed = unused.chain([best_pixel]).any(|pixel| {
+                self.place_color(common_data, locked, pixel, color)
+            });
+            if !placed && !self.neighborhood {
+                log::warn!("failed to place pixel at {best_pixel:?}");
+            }
         }
-        let x = pixel.x + offset.dx;
-        if x < 0 || x as usize >= dimx.get() {
-            continue;
+    }
+
+    /// Places `color` for the place `pixel` (see `place_pixel_inner`), and
+    /// returns whether there was room.
+    fn place_color(
+        &self,
+        common_data: &CommonData,
+        locked: &mut CommonLockedData,
+        pixel: Pixel,
+        color: Color,
+    ) -> bool {
+        if self.neighborhood {
+            // `pixel` is the open neighbor itself, which another color may
+            // have taken this iteration.
+            let (y, x) = (pixel.y as usize, pixel.x as usize);
+            if locked.placed_pixels.get((y, x)) {
+                return false;
+            }
+            locked.place((y, x), color);
+            locked.edges.push_back(pixel);
+            return true;
         }
-        let location = Pixel { y, x };
-        let y = y as usize;
-        let x = x as usize;
-        if placed_pixels.get((y, x)) {
-            continue;
+        let placement = Placement {
+            dimy: common_data.dimy,
+            dimx: common_data.dimx,
+            offsets: &self.offsets,
+            smart: self.smartplace,
+            fitness: self.fitness,
+        };
+        match place_pixel_inner(
+            placement,
+            pixel,
+            color,
+            &mut locked.image,
+            &mut locked.edges,
+            &mut locked.placed_pixels,
+        ) {
+            Ok(Pixel { x, y }) => {
+                locked.recently_placed.push((y as usize, x as usize));
+                true
+            }
+            Err(()) => false,
         }
-        placed_pixels.set((y, x), true);
-        image[(y, x)] = color;
-        edges.push_back(location);
-        return Ok(location);
     }
-    Err(())
 }
 
 impl Generator for InnerGenerator {
@@ -204,34 +873,72 @@ impl Generator for InnerGenerator {
         color_generator: &dyn ColorGenerator,
         rng: &mut dyn RngCore,
     ) {
+        let start = Instant::now();
+        // Seeds are placed before `recently_placed` is cleared for the next
+        // iteration, so they are validated separately.
+        let mut unvalidated_seeds = vec![];
+        let mut closed =
+            BitMap::new(common_data.dimy.get(), common_data.dimx.get())
+                .expect("setup checked the size");
+        // How many colors the supervisor has generated.
+        let mut generated = 0;
+
         // Place seeds
         {
             let mut locked = common_data.locked.write().unwrap();
-            let seed_locations = place_seeds_common(
-                self.seeds.get(),
+            let mut seed_locations = place_fixed_seeds(
+                &self.seedat,
+                &mut locked,
+                color_generator,
+                rng,
+                &mut generated,
+            );
+            seed_locations.extend(place_seeds_common(
+                self.seeds,
                 common_data.dimx,
                 common_data.dimy,
                 &mut locked,
                 color_generator,
                 rng,
-            );
+                &mut generated,
+            ));
             common_data
                 .pixels_generated
-                .fetch_add(seed_locations.len(), Ordering::SeqCst);
+                .fetch_add(seed_locations.len(), Ordering::Relaxed);
             common_data
                 .pixels_placed
-                .fetch_add(seed_locations.len(), Ordering::SeqCst);
-            locked.edges.extend(seed_locations);
+                .fetch_add(seed_locations.len(), Ordering::Relaxed);
+            locked.edges.extend(&seed_locations);
+            unvalidated_seeds.extend(seed_locations);
         }
 
-        let generate_colors = |color_generator: &dyn ColorGenerator,
-                               rng: &mut dyn RngCore|
-         -> Arc<[Color]> {
-            Arc::from_iter(
-                (0..self.colorcount.get())
-                    .map(|_| color_generator.new_color(rng)),
-            )
+        // Refills `colors` in place when no worker still holds a reference to
+        // it, so the main loops don't allocate a new buffer each iteration.
+        let generate_colors = |colors: &mut Arc<[Color]>,
+                               color_generator: &dyn ColorGenerator,
+                               rng: &mut dyn RngCore,
+                               generated: &mut usize| {
+            let first = *generated;
+            *generated += colors.len();
+            match Arc::get_mut(colors) {
+                Some(colors) => color_generator.new_colors(rng, first, colors),
+                None => {
+                    let mut new_colors = vec![Color::default(); colors.len()];
+                    color_generator.new_colors(rng, first, &mut new_colors);
+                    *colors = Arc::from(new_colors);
+                }
+            }
         };
+        let mut colors: Arc<[Color]> =
+            Arc::from(vec![Color::default(); self.colorcount.get()]);
+        let mut best_places =
+            vec![BestPlaces::default(); self.colorcount.get()];
+        // How many places each color keeps (see `BestPlaces`).
+        let limit = self.colorcount.get().min(MAX_PLACE_CANDIDATES);
+        let palette_size = color_generator.palette_size();
+        if let Some(n) = palette_size.filter(|&n| n <= MAX_CACHED_PALETTE) {
+            log::info!("caching color distances (palette of <= {n} colors)");
+        }
 
         // Main loop
         if self.workers.get() == 1 {
@@ -241,13 +948,34 @@ impl Generator for InnerGenerator {
             // with '-w 2' or above."); todo!("single-thread
             // generator main loop");
 
+            let mut distances = DistanceCache::new(palette_size, self.fitness);
+            let mut selected = vec![];
+            // `self.offsets` is shuffled every iteration, but the workers
+            // consider candidates in the configured order.
+            let neighborhood_offsets = self.offsets.clone();
+            let mut candidates = vec![];
             loop {
-                let mut best_places = vec![None; self.colorcount.get()];
+                best_places.iter_mut().for_each(BestPlaces::clear);
                 {
                     let mut locked = common_data.locked.write().unwrap();
 
-                    // If there are no edges left, seed again
-                    if locked.edges.len() == 0 {
+                    // Stop if interrupted (see `CommonData::interrupted`
+                    // for why this is propagated here). Otherwise, if there
+                    // are no edges left, seed again (or finish, if
+                    // re-seeding is disabled).
+                    if common_data.stop_early() {
+                        log::trace!("generator interrupted");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if self.stop.reached(
+                        start,
+                        common_data.pixels_placed.load(Ordering::Relaxed),
+                    ) {
+                        log::info!("stop condition reached; stopping");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if locked.edges.is_empty() && !self.reseed {
+                        log::trace!("no edges left; generator finished");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if locked.edges.is_empty() {
                         log::trace!("re-seeding");
                         let seed_locations = place_seeds_common(
                             1,
@@ -256,369 +984,880 @@ impl Generator for InnerGenerator {
                             &mut locked,
                             color_generator,
                             rng,
+                            &mut generated,
                         );
                         common_data
                             .pixels_generated
-                            .fetch_add(seed_locations.len(), Ordering::SeqCst);
+                            .fetch_add(seed_locations.len(), Ordering::Relaxed);
                         common_data
                             .pixels_placed
-                            .fetch_add(seed_locations.len(), Ordering::SeqCst);
-                        locked.edges.extend(seed_locations);
+                            .fetch_add(seed_locations.len(), Ordering::Relaxed);
+                        locked.edges.extend(&seed_locations);
+                        unvalidated_seeds.extend(seed_locations);
                     }
                 }
 
                 log::trace!(target: "barriers", "before progress barrier a");
-                common_data.progress_barrier.wait();
+                common_data.generator_barrier_wait();
                 log::trace!(target: "barriers", "after progress barrier a");
-                if common_data.finished.load(Ordering::SeqCst) {
+                if common_data.finished.load(Ordering::Acquire) {
                     break;
                 }
 
-                let colors = generate_colors(color_generator, rng);
-                common_data
-                    .pixels_generated
-                    .fetch_add(colors.len(), Ordering::SeqCst);
-                {
-                    let CommonLockedData { image, edges, .. } =
-                        &*common_data.locked.read().unwrap();
-
-                    for edge in 0..edges.len() {
-                        let pixel @ Pixel { x, y } = edges[edge];
-                        // TODO: geometry
-                        let x = x as usize;
-                        let y = y as usize;
-
-                        let color = image[(y, x)];
-                        for (current_best, new_color) in
-                            best_places.iter_mut().zip(&*colors)
-                        {
-                            // let fitness = fitness(*color, &image)
-                            // TODO: configurable fitness function
-                            let diff = color - new_color;
-                            let sq_diff = diff * diff;
-                            let fitness: Channel =
-                                sq_diff.as_array().iter().sum();
-                            match current_best {
-                                Some((_, current_fitness))
-                                    if *current_fitness < fitness => {}
-                                _ => *current_best = Some((pixel, fitness)),
+                if let Some(amount) = self.mutate {
+                    // Instead of generating colors and finding where they
+                    // fit best, derive one from a random edge's color.
+                    let locked = common_data.locked.read().unwrap();
+                    if !locked.edges.is_empty() {
+                        let edge =
+                            locked.edges[rng.gen_range(0..locked.edges.len())];
+                        let color =
+                            locked.image[(edge.y as usize, edge.x as usize)];
+                        Arc::make_mut(&mut colors)[0] =
+                            mutate(color, amount, rng);
+                        best_places[0].insert((edge, 0.0), 1, false, true);
+                        common_data
+                            .pixels_generated
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                } else {
+                    generate_colors(
+                        &mut colors,
+                        color_generator,
+                        rng,
+                        &mut generated,
+                    );
+                    common_data
+                        .pixels_generated
+                        .fetch_add(colors.len(), Ordering::Relaxed);
+                    let maximize = maximizing(
+                        self.invert_fitness_after,
+                        common_data.pixels_placed.load(Ordering::Relaxed),
+                    );
+                    {
+                        let CommonLockedData {
+                            image, edges, placed_pixels, ..
+                        } = &*common_data.locked.read().unwrap();
+
+                        distances.clear();
+                        let limited = self.edgeselect.select(
+                            self.edgelimit,
+                            edges.len(),
+                            rng,
+                            &mut selected,
+                        );
+                        let considered =
+                            if limited { selected.len() } else { edges.len() };
+                        for i in 0..considered {
+                            let pixel @ Pixel { x, y } =
+                                edges[if limited { selected[i] } else { i }];
+                            // TODO: geometry
+                            let x = x as usize;
+                            let y = y as usize;
+
+                            if self.neighborhood {
+                                neighborhood_candidates(
+                                    common_data.dimy,
+                                    common_data.dimx,
+                                    pixel,
+                                    image,
+                                    placed_pixels,
+                                    &neighborhood_offsets,
+                                    &mut candidates,
+                                );
+                                for &(location, average) in &candidates {
+                                    for (current_best, &new_color) in
+                                        best_places.iter_mut().zip(&*colors)
+                                    {
+                                        let fitness = fitness(
+                                            self.fitness,
+                                            average,
+                                            new_color,
+                                        );
+                                        update_best_place(
+                                            current_best,
+                                            (location, fitness),
+                                            self.maxfitness,
+                                            maximize,
+                                            limit,
+                                        );
+                                    }
+                                }
+                                continue;
+                            }
+
+                            let color = image[(y, x)];
+                            let cached = distances.row(color, &colors);
+                            for (i, (current_best, new_color)) in
+                                best_places.iter_mut().zip(&*colors).enumerate()
+                            {
+                                let fitness = match cached {
+                                    Some(row) => row[i],
+                                    None => {
+                                        fitness(self.fitness, color, *new_color)
+                                    }
+                                };
+                                update_best_place(
+                                    current_best,
+                                    (pixel, fitness),
+                                    self.maxfitness,
+                                    maximize,
+                                    limit,
+                                );
                             }
                         }
                     }
                 }
 
                 log::trace!(target: "barriers", "before progress barrier b");
-                common_data.progress_barrier.wait();
+                common_data.generator_barrier_wait();
                 log::trace!(target: "barriers", "afterprogress barrier b");
 
                 // Apply best_places
                 let mut locked = common_data.locked.write().unwrap();
                 let locked = &mut *locked;
+                locked.recently_placed.clear();
                 self.offsets.shuffle(rng);
-                for (color, (pixel, _)) in colors
-                    .iter()
-                    .zip(best_places)
-                    .filter_map(|(color, best)| Some((color, best?)))
-                {
-                    // let Pixel { x, y } = pixel;
-                    // // TODO: geometry
-                    // let x = x as usize;
-                    // let y = y as usize;
-
-                    // locked.image[(y, x)] = *color;
-                    // locked.placed_pixels.set((y, x), true);
-                    if let Ok(_) = place_pixel_inner(
-                        common_data.dimy,
-                        common_data.dimx,
-                        pixel,
-                        *color,
-                        &mut locked.image,
-                        &mut locked.edges,
-                        &mut locked.placed_pixels,
-                        &self.offsets,
-                    ) {
-                        common_data
-                            .pixels_placed
-                            .fetch_add(1, Ordering::SeqCst);
-                    } else {
-                        log::warn!("failed to place pixel at {pixel:?}");
-                    }
-                }
-                if common_data.pixels_placed.load(Ordering::SeqCst)
-                    == common_data.size.get()
-                {
-                    common_data.finished.store(true, Ordering::SeqCst);
+                self.place_colors(&common_data, locked, &colors, &best_places);
+                // Only this thread changes `pixels_placed`, so the count
+                // cannot be stale.
+                let newly_placed = locked.recently_placed.len();
+                let pixels_placed = newly_placed
+                    + common_data
+                        .pixels_placed
+                        .fetch_add(newly_placed, Ordering::Relaxed);
+                if pixels_placed == common_data.size.get() {
+                    common_data.finished.store(true, Ordering::Release);
                     log::trace!("generator finished");
                 } else {
-                    validate_inner_edges(
+                    let placed = locked.recently_placed.iter().map(|&(y, x)| {
+                        Pixel { x: x as _, y: y as _ }
+                    });
+                    validate_edges_near(
                         common_data.dimy,
                         common_data.dimx,
                         &mut locked.edges,
-                        &mut locked.placed_pixels,
+                        &locked.placed_pixels,
                         &self.offsets,
+                        placed.chain(unvalidated_seeds.drain(..)),
+                        &mut closed,
                     );
                 }
             }
         } else {
             // Supervisor sends the colors to the worker, the worker calculates
-            // the best places, the worker sends back the best
-            // places this worker saw with their fitness.
+            // the best places, the worker sends back each color with the best
+            // place this worker saw for it and its fitness. With
+            // `--worker-colors`, the supervisor sends no colors, and each
+            // worker generates its own instead.
+            type WorkerResults = Vec<(Color, BestPlaces)>;
             struct WorkerData {
                 colors_rx: tokio::sync::broadcast::Receiver<Arc<[Color]>>,
-                edges_rx: tokio::sync::mpsc::Receiver<Range<usize>>,
-                best_places_tx:
-                    tokio::sync::mpsc::Sender<Vec<Option<(Pixel, Channel)>>>,
+                /// The range of edges to consider, and whether it indexes
+                /// `selected` instead of the edges themselves.
+                edges_rx: tokio::sync::mpsc::Receiver<(Range<usize>, bool)>,
+                selected: Arc<RwLock<Vec<usize>>>,
+                /// This worker's results, reused across iterations.
+                results: Arc<Mutex<WorkerResults>>,
+                /// With `--worker-colors`, how many colors this worker
+                /// generates each iteration, and the RNG it uses.
+                own_colors: Option<(usize, ChaCha12Rng)>,
+                done_tx: tokio::sync::mpsc::Sender<()>,
+                distances: DistanceCache,
                 #[allow(unused)]
                 data: GeneratorData,
                 common_data: Arc<CommonData>,
-                generator: InnerGenerator, /* TODO: something better than
-                                            * cloning self */
-            }
-            let mut handles = Vec::with_capacity(self.workers.get());
-            let mut edges_txs = Vec::with_capacity(self.workers.get());
-
-            let (colors_tx, _) = tokio::sync::broadcast::channel(1);
-            let (best_places_tx, mut best_places_rx) =
-                tokio::sync::mpsc::channel(self.workers.get());
-
-            for _ in 0..self.workers.get() {
-                let (edges_tx, edges_rx) = tokio::sync::mpsc::channel(1);
-                edges_txs.push(edges_tx);
-                let data = WorkerData {
-                    edges_rx,
-                    colors_rx: colors_tx.subscribe(),
-                    best_places_tx: best_places_tx.clone(),
-                    data: data.clone(),
-                    common_data: common_data.clone(),
-                    generator: self.clone(),
-                };
-                handles.push(std::thread::spawn(move || {
-                    let mut data = data;
-                    let rt = tokio::runtime::Builder::new_current_thread()
-                        .build()
-                        .unwrap();
-                    rt.block_on(async move {
-                        while !data.common_data.finished.load(Ordering::SeqCst) {
-                            log::warn!(
-                                "TODO: handle RecvError::Closed as supervisor thread exiting"
-                            );
-                            let colors = match data.colors_rx.recv().await {
-                                Ok(colors) => colors,
-                                Err(tokio::sync::broadcast::error::RecvError::Closed) => {
-                                    log::warn!(
-                                        "Worker exiting because supervisor closed colors channel"
+            }
+            // Scoped, so the workers can borrow the color generator.
+            std::thread::scope(|scope| {
+                let mut handles = Vec::with_capacity(self.workers.get());
+                let mut edges_txs = Vec::with_capacity(self.workers.get());
+
+                let (colors_tx, _) = tokio::sync::broadcast::channel(1);
+                let (done_tx, mut done_rx) =
+                    tokio::sync::mpsc::channel(self.workers.get());
+                let worker_results: Vec<_> = (0..self.workers.get())
+                    .map(|_| {
+                        Arc::new(Mutex::new(Vec::with_capacity(
+                            self.colorcount.get(),
+                        )))
+                    })
+                    .collect();
+
+                let selected = Arc::new(RwLock::new(vec![]));
+                let (edgelimit, edgeselect) = (self.edgelimit, self.edgeselect);
+                let (kind, maxfitness) = (self.fitness, self.maxfitness);
+                let invert_fitness_after = self.invert_fitness_after;
+                let neighborhood = self.neighborhood;
+                let neighborhood_offsets: Arc<[Offset]> =
+                    Arc::from(&self.offsets[..]);
+                let worker_colors = self.worker_colors;
+                // Sent instead of the colors with `--worker-colors`, so that
+                // the supervisor's buffer is never shared.
+                let no_colors: Arc<[Color]> = Arc::from([]);
+
+                for (w, results) in worker_results.iter().enumerate() {
+                    let (edges_tx, edges_rx) = tokio::sync::mpsc::channel(1);
+                    edges_txs.push(edges_tx);
+                    // Split the colors as evenly as possible, and seed each
+                    // worker in order, so the output only depends on the seed.
+                    let (colorcount, workers) =
+                        (self.colorcount.get(), self.workers.get());
+                    let own_colors = worker_colors.then(|| {
+                        let count = colorcount / workers
+                            + usize::from(w < colorcount % workers);
+                        (count, ChaCha12Rng::seed_from_u64(rng.next_u64()))
+                    });
+                    let data = WorkerData {
+                        edges_rx,
+                        colors_rx: colors_tx.subscribe(),
+                        results: results.clone(),
+                        own_colors,
+                        selected: selected.clone(),
+                        done_tx: done_tx.clone(),
+                        distances: DistanceCache::new(palette_size, kind),
+                        data: data.clone(),
+                        common_data: common_data.clone(),
+                    };
+                    let neighborhood_offsets = neighborhood_offsets.clone();
+                    handles.push(scope.spawn(move || {
+                        let mut data = data;
+                        let mut candidates = vec![];
+                        let mut own_colors = vec![];
+                        // How many colors this worker has generated.
+                        let mut generated = 0;
+                        let rt = tokio::runtime::Builder::new_current_thread()
+                            .build()
+                            .unwrap();
+                        rt.block_on(async move {
+                            while !data.common_data.finished.load(Ordering::Acquire) {
+                                log::warn!(
+                                    "TODO: handle RecvError::Closed as supervisor thread exiting"
+                                );
+                                let received = match data.colors_rx.recv().await {
+                                    Ok(colors) => colors,
+                                    Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                                        log::warn!(
+                                            "Worker exiting because supervisor closed colors channel"
+                                        );
+                                        break;
+                                    }
+                                    Err(tokio::sync::broadcast::error::RecvError::Lagged(..)) => {
+                                        log::error!("Worker error: colors channel lagged");
+                                        unreachable!("colors channel lagged");
+                                    }
+                                };
+                                // Calculate best places for each color in this worker's edge chunk
+                                {
+                                    let locked = data.common_data.locked.read().unwrap();
+                                    let CommonLockedData {
+                                        image,
+                                        edges,
+                                        placed_pixels,
+                                        ..
+                                    } = &*locked;
+
+                                    log::trace!("recv'ing edge range");
+                                    let (my_edges, limited) = data
+                                        .edges_rx
+                                        .recv()
+                                        .await
+                                        .expect("supervisor thread exited?");
+                                    log::trace!("recv'd edge range: {my_edges:?}");
+
+                                    let colors = match &mut data.own_colors {
+                                        Some((count, rng)) => {
+                                            own_colors.resize(*count, Color::default());
+                                            color_generator.new_colors(rng, generated, &mut own_colors);
+                                            generated += *count;
+                                            &own_colors[..]
+                                        }
+                                        None => &received[..],
+                                    };
+                                    let mut results = data.results.lock().unwrap();
+                                    // Reuses each color's `BestPlaces` buffer.
+                                    results.resize_with(colors.len(), Default::default);
+                                    for ((color, best), &new_color) in results.iter_mut().zip(colors) {
+                                        *color = new_color;
+                                        best.clear();
+                                    }
+                                    data.distances.clear();
+                                    let selected = data.selected.read().unwrap();
+                                    // Only the supervisor changes this, after
+                                    // the workers are done.
+                                    let maximize = maximizing(
+                                        invert_fitness_after,
+                                        data.common_data.pixels_placed.load(Ordering::Relaxed),
                                     );
-                                    break;
-                                }
-                                Err(tokio::sync::broadcast::error::RecvError::Lagged(..)) => {
-                                    log::error!("Worker error: colors channel lagged");
-                                    unreachable!("colors channel lagged");
+                                    for i in my_edges {
+                                        let pixel @ Pixel { x, y } =
+                                            edges[if limited { selected[i] } else { i }];
+                                        // TODO: geometry
+                                        let x = x as usize;
+                                        let y = y as usize;
+
+                                        if neighborhood {
+                                            neighborhood_candidates(
+                                                data.common_data.dimy,
+                                                data.common_data.dimx,
+                                                pixel,
+                                                image,
+                                                placed_pixels,
+                                                &neighborhood_offsets,
+                                                &mut candidates,
+                                            );
+                                            for &(location, average) in &candidates {
+                                                for &mut (new_color, ref mut current_best) in
+                                                    results.iter_mut()
+                                                {
+                                                    let fitness = fitness(kind, average, new_color);
+                                                    update_best_place(current_best, (location, fitness), maxfitness, maximize, limit);
+                                                }
+                                            }
+                                            continue;
+                                        }
+
+                                        let color = image[(y, x)];
+                                        let cached = data.distances.row(color, colors);
+                                        for (i, &mut (new_color, ref mut current_best)) in
+                                            results.iter_mut().enumerate()
+                                        {
+                                            let fitness = match cached {
+                                                Some(row) => row[i],
+                                                None => fitness(kind, color, new_color),
+                                            };
+                                            update_best_place(current_best, (pixel, fitness), maxfitness, maximize, limit);
+                                        }
+                                    }
                                 }
-                            };
-                            // Calculate best places for each color in this worker's edge chunk
-                            let mut best_places = vec![None; data.generator.colorcount.get()];
-                            {
-                                let locked = data.common_data.locked.read().unwrap();
-                                let CommonLockedData {
-                                    image,
-                                    edges,
-                                    ..
-                                } = &*locked;
-
-                                log::trace!("recv'ing edge range");
-                                let my_edges = data
-                                    .edges_rx
-                                    .recv()
+                                // Let the supervisor reuse the colors buffer.
+                                drop(received);
+                                data.done_tx
+                                    .send(())
                                     .await
                                     .expect("supervisor thread exited?");
-                                log::trace!("recv'd edge range: {my_edges:?}");
+                            }
+                        });
+                    }));
+                }
 
-                                for edge in my_edges {
-                                    let pixel @ Pixel { x, y } = edges[edge];
-                                    // TODO: geometry
-                                    let x = x as usize;
-                                    let y = y as usize;
+                let rt =
+                    tokio::runtime::Builder::new_current_thread().build().unwrap();
 
-                                    let color = image[(y, x)];
-                                    for (current_best, new_color) in
-                                        best_places.iter_mut().zip(&*colors)
-                                    {
-                                        // let fitness = fitness(*color, &image)
-                                        // TODO: configurable fitness function
-                                        let diff = color - new_color;
-                                        let sq_diff = diff * diff;
-                                        let fitness: Channel = sq_diff.as_array().iter().sum();
-                                        match current_best {
-                                            Some((_, current_fitness))
-                                                if *current_fitness < fitness => {}
-                                            _ => *current_best = Some((pixel, fitness)),
-                                        }
-                                    }
-                                }
+                rt.block_on(async {
+                    loop {
+                        best_places.iter_mut().for_each(BestPlaces::clear);
+                        {
+                            let mut locked = common_data.locked.write().unwrap();
+
+                            // Stop if interrupted (see `CommonData::interrupted`
+                            // for why this is propagated here). Otherwise, if there
+                            // are no edges left, seed again (or finish, if
+                            // re-seeding is disabled).
+                            if common_data.stop_early() {
+                                log::trace!("generator interrupted");
+                                common_data.finished.store(true, Ordering::Release);
+                            } else if self.stop.reached(start, common_data.pixels_placed.load(Ordering::Relaxed)) {
+                                log::info!("stop condition reached; stopping");
+                                common_data.finished.store(true, Ordering::Release);
+                            } else if locked.edges.is_empty() && !self.reseed {
+                                log::trace!("no edges left; generator finished");
+                                common_data.finished.store(true, Ordering::Release);
+                            } else if locked.edges.is_empty() {
+                                log::trace!("re-seeding");
+                                let seed_locations = place_seeds_common(
+                                    1,
+                                    common_data.dimx,
+                                    common_data.dimy,
+                                    &mut locked,
+                                    color_generator,
+                                    rng,
+                                    &mut generated,
+                                );
+                                common_data
+                                    .pixels_generated
+                                    .fetch_add(seed_locations.len(), Ordering::Relaxed);
+                                common_data
+                                    .pixels_placed
+                                    .fetch_add(seed_locations.len(), Ordering::Relaxed);
+                                locked.edges.extend(&seed_locations);
+                                unvalidated_seeds.extend(seed_locations);
                             }
-                            data.best_places_tx
-                                .send(best_places)
-                                .await
-                                .expect("supervisor thread exited?");
                         }
-                    });
-                }));
-            }
-
-            let rt =
-                tokio::runtime::Builder::new_current_thread().build().unwrap();
+                        {
+                            let locked = common_data.locked.read().unwrap();
 
-            rt.block_on(async {
-                loop {
-                    let mut best_places = vec![None; self.colorcount.get()];
-                    {
-                        let mut locked = common_data.locked.write().unwrap();
+                            log::trace!(target: "barriers", "before progress barrier a");
+                            common_data.generator_barrier_wait();
+                            log::trace!(target: "barriers", "afterprogress barrier a");
+                            if common_data.finished.load(Ordering::Acquire) {
+                                break;
+                            }
 
-                        // If there are no edges left, seed again
-                        if locked.edges.len() == 0 {
-                            log::trace!("re-seeding");
-                            let seed_locations = place_seeds_common(
-                                1,
-                                common_data.dimx,
-                                common_data.dimy,
-                                &mut locked,
-                                color_generator,
-                                rng,
-                            );
+                            // Generate the colors before selecting edges, so that
+                            // the rng is used in the same order as with one worker.
+                            if !worker_colors {
+                                generate_colors(&mut colors, color_generator, rng, &mut generated);
+                            }
                             common_data
                                 .pixels_generated
-                                .fetch_add(seed_locations.len(), Ordering::SeqCst);
-                            common_data
-                                .pixels_placed
-                                .fetch_add(seed_locations.len(), Ordering::SeqCst);
-                            locked.edges.extend(seed_locations);
+                                .fetch_add(colors.len(), Ordering::Relaxed);
+
+                            let (edgecount, limited) = {
+                                let mut selected = selected.write().unwrap();
+                                let limited = edgeselect.select(
+                                    edgelimit,
+                                    locked.edges.len(),
+                                    rng,
+                                    &mut selected,
+                                );
+                                match limited {
+                                    true => (selected.len(), true),
+                                    false => (locked.edges.len(), false),
+                                }
+                            };
+                            let step = edgecount / edges_txs.len();
+                            log::trace!(
+                                "sending edge ranges: {} (slices of {:?}",
+                                edges_txs.len(),
+                                0..edgecount
+                            );
+                            for (w, tx) in edges_txs.iter_mut().enumerate() {
+                                // Workers with their own colors need every edge.
+                                let range = if worker_colors {
+                                    0..edgecount
+                                } else if w == self.workers.get() - 1 {
+                                    w * step..edgecount
+                                } else {
+                                    w * step..(w + 1) * step
+                                };
+                                log::trace!("sending edge range {w}: {range:?}");
+                                tx.send((range, limited)).await.expect("worker exited?");
+                            }
                         }
-                    }
-                    {
-                        let locked = common_data.locked.read().unwrap();
-
-                        log::trace!(target: "barriers", "before progress barrier a");
-                        common_data.progress_barrier.wait();
-                        log::trace!(target: "barriers", "afterprogress barrier a");
-                        if common_data.finished.load(Ordering::SeqCst) {
-                            break;
+                        log::trace!("sending colors");
+                        let sent =
+                            if worker_colors { &no_colors } else { &colors };
+                        colors_tx
+                            .send(sent.clone())
+                            .expect("Worker threads should be running");
+
+                        // Ensure all progressors have read what they need to
+                        log::trace!(target: "barriers", "before progress barrier b");
+                        common_data.generator_barrier_wait();
+                        log::trace!(target: "barriers", "afterprogress barrier b");
+
+                        // Wait for workers (happens at done_rx.recv())
+                        for _ in 0..self.workers.get() {
+                            done_rx
+                                .recv()
+                                .await
+                                .expect("worker thread exited early?");
                         }
-
-                        let edgecount = locked.edges.len();
-                        let step = edgecount / edges_txs.len();
-                        log::trace!(
-                            "sending edge ranges: {} (slices of {:?}",
-                            edges_txs.len(),
-                            0..edgecount
+                        // Coalesce worker results into best_places. Workers
+                        // with their own colors each fill the next part of
+                        // `colors`.
+                        let mut own_colors = worker_colors.then(|| {
+                            Arc::get_mut(&mut colors).expect("colors are never sent")
+                        });
+                        let maximize = maximizing(
+                            invert_fitness_after,
+                            common_data.pixels_placed.load(Ordering::Relaxed),
                         );
-                        for (w, tx) in edges_txs.iter_mut().enumerate() {
-                            let range = if w == self.workers.get() - 1 {
-                                w * step..edgecount
-                            } else {
-                                w * step..(w + 1) * step
-                            };
-                            log::trace!("sending edge range {w}: {range:?}");
-                            tx.send(range).await.expect("worker exited?");
+                        let mut start = 0;
+                        for results in &worker_results {
+                            let results = results.lock().unwrap();
+                            debug_assert!(
+                                worker_colors || results.len() == best_places.len(),
+                                "worker returned wrong length?"
+                            );
+                            for (i, (color, worker)) in results.iter().enumerate() {
+                                let best = match &mut own_colors {
+                                    Some(own_colors) => {
+                                        own_colors[start + i] = *color;
+                                        &mut best_places[start + i]
+                                    }
+                                    None => &mut best_places[i],
+                                };
+                                // Workers only report places that satisfy
+                                // maxfitness (see `update_best_place`), and
+                                // earlier workers win ties.
+                                best.merge(worker, limit, maximize);
+                            }
+                            if worker_colors {
+                                start += results.len();
+                            }
                         }
-                    }
-                    let colors = generate_colors(color_generator, rng);
-                    common_data
-                        .pixels_generated
-                        .fetch_add(colors.len(), Ordering::SeqCst);
-                    log::trace!("sending colors");
-                    colors_tx
-                        .send(colors.clone())
-                        .expect("Worker threads should be running");
-
-                    // Ensure all progressors have read what they need to
-                    log::trace!(target: "barriers", "before progress barrier b");
-                    common_data.progress_barrier.wait();
-                    log::trace!(target: "barriers", "afterprogress barrier b");
-
-                    // Wait for workers (happens at best_places_rx.recv())
-                    // Coalesce worker results into best_places
-                    for _ in 0..self.workers.get() {
-                        let best_places_recvd = best_places_rx
-                            .recv()
-                            .await
-                            .expect("worker thread exited early?");
                         debug_assert!(
-                            best_places_recvd.len() == best_places.len(),
-                            "worker returned wrong length?"
+                            start == 0 || start == colors.len(),
+                            "workers generated the wrong number of colors?"
                         );
-                        for (best, worker) in best_places.iter_mut().zip(best_places_recvd) {
-                            match (&*best, &worker, self.maxfitness) {
-                                (_, None, _) => { /* do nothing */ }
-                                (None, Some(_), None) => *best = worker,
-                                (None, Some((_, fitness)), Some(maxfitness)) => {
-                                    if *fitness < maxfitness {
-                                        *best = worker
-                                    }
-                                }
-                                (Some((_, bfitness)), Some((_, wfitness)), _) => {
-                                    // Don't need to check maxfitness, since the best fitness already satisfies that
-                                    if wfitness < bfitness {
-                                        *best = worker;
-                                    }
-                                }
-                            }
+
+                        log::trace!("best_places = {best_places:?}");
+
+                        // Apply best_places
+                        let mut locked = common_data.locked.write().unwrap();
+                        let locked = &mut *locked;
+                        locked.recently_placed.clear();
+                        self.offsets.shuffle(rng);
+                        self.place_colors(&common_data, locked, &colors, &best_places);
+                        // Only this thread changes `pixels_placed`, so the count
+                        // cannot be stale.
+                        let newly_placed = locked.recently_placed.len();
+                        let pixels_placed = newly_placed
+                            + common_data.pixels_placed.fetch_add(newly_placed, Ordering::Relaxed);
+                        if pixels_placed == common_data.size.get() {
+                            common_data.finished.store(true, Ordering::Release);
+                            log::trace!("generator finished");
+                        } else {
+                            let placed = locked.recently_placed.iter().map(|&(y, x)| {
+                                Pixel { x: x as _, y: y as _ }
+                            });
+                            validate_edges_near(
+                                common_data.dimy,
+                                common_data.dimx,
+                                &mut locked.edges,
+                                &locked.placed_pixels,
+                                &self.offsets,
+                                placed.chain(unvalidated_seeds.drain(..)),
+                                &mut closed,
+                            );
                         }
                     }
+                });
+                drop(colors_tx);
+                for handle in handles {
+                    handle.join().unwrap_or_else(|err| {
+                        log::error!("Worker panicked: {err:?}")
+                    });
+                }
+            });
+        }
+    }
 
-                    log::trace!("best_places = {best_places:?}");
+    #[cfg(test)]
+    #[doc(hidden)]
+    fn offsets(&self) -> &[Offset] {
+        &self.offsets
+    }
+}
 
-                    // Apply best_places
-                    let mut locked = common_data.locked.write().unwrap();
-                    let locked = &mut *locked;
-                    self.offsets.shuffle(rng);
-                    for (color, (pixel, _)) in colors
-                        .iter()
-                        .zip(best_places)
-                        .filter_map(|(color, best)| Some((color, best?)))
-                    {
-                        // let Pixel { x, y } = pixel;
-                        // // TODO: geometry
-                        // let x = x as usize;
-                        // let y = y as usize;
-
-                        // locked.image[(y, x)] = *color;
-                        // locked.placed_pixels.set((y, x), true);
-                        if let Ok(_) = place_pixel_inner(
-                            common_data.dimy,
-                            common_data.dimx,
-                            pixel,
-                            *color,
-                            &mut locked.image,
-                            &mut locked.edges,
-                            &mut locked.placed_pixels,
-                            &self.offsets,
-                        ) {
-                            common_data.pixels_placed.fetch_add(1, Ordering::SeqCst);
-                        } else {
-                            log::warn!("failed to place pixel at {pixel:?}");
+/// For outer generation, edges are the unplaced pixels next to placed ones,
+/// and a color fits an edge by how close it is to the average of the edge's
+/// placed neighbors. The best-fitting edge for each color is placed directly.
+#[derive(Debug, Clone)]
+struct OuterGenerator {
+    /// How many seeds to place randomly, after `seedat`.
+    seeds: usize,
+    /// Seeds at fixed locations, with fixed colors or new ones.
+    seedat: Vec<(Pixel, Option<Color>)>,
+    offsets: Vec<Offset>,
+    workers: NonZeroUsize,
+    colorcount: NonZeroUsize,
+    maxfitness: Option<Channel>,
+    /// If `false`, generation finishes when there are no edges left instead
+    /// of placing a new seed.
+    reseed: bool,
+    /// How to compare the average of an edge's neighbors to a new color.
+    fitness: Fitness,
+    stop: StopConditions,
+}
+
+/// The average color of the placed neighbors of `location` (by `offsets`),
+/// or `None` if it has none.
+fn neighbor_average(
+    dimy: NonZeroUsize,
+    dimx: NonZeroUsize,
+    location: Pixel,
+    image: &PnmData,
+    placed_pixels: &BitMap,
+    offsets: &[Offset],
+) -> Option<Color> {
+    let mut total = Color::default();
+    let mut count = 0;
+    for &offset in offsets {
+        let Some(neighbor) = in_bounds(dimy, dimx, location + offset) else {
+            continue;
+        };
+        if placed_pixels.get(neighbor) {
+            total += image[neighbor];
+            count += 1;
+        }
+    }
+    (count > 0).then(|| total / Color::splat(count as Channel))
+}
+
+impl OuterGenerator {
+    /// Adds the unplaced pixels that have `placed` as a neighbor to `edges`,
+    /// unless `queued` says they already were.
+    fn add_edges(
+        &self,
+        dimy: NonZeroUsize,
+        dimx: NonZeroUsize,
+        placed: Pixel,
+        locked: &mut CommonLockedData,
+        queued: &mut BitMap,
+    ) {
+        for &Offset { dx, dy } in &self.offsets {
+            let location = placed + Offset { dx: -dx, dy: -dy };
+            let Some(location_yx) = in_bounds(dimy, dimx, location) else {
+                continue;
+            };
+            if !locked.placed_pixels.get(location_yx)
+                && !queued.get(location_yx)
+            {
+                queued.set(location_yx, true);
+                locked.edges.push_back(location);
+            }
+        }
+    }
+
+    /// Finds the best-fitting edge in `range` (of `locked.edges`) for each of
+    /// `colors`. Ties go to the first edge, so that splitting the edges
+    /// between workers does not change the result.
+    fn best_places(
+        &self,
+        dimy: NonZeroUsize,
+        dimx: NonZeroUsize,
+        locked: &CommonLockedData,
+        range: Range<usize>,
+        colors: &[Color],
+    ) -> Vec<Option<(Pixel, Channel)>> {
+        let CommonLockedData { image, placed_pixels, edges, .. } = locked;
+        let mut best_places = vec![None; colors.len()];
+        for i in range {
+            let pixel = edges[i];
+            let Some(average) = neighbor_average(
+                dimy,
+                dimx,
+                pixel,
+                image,
+                placed_pixels,
+                &self.offsets,
+            ) else {
+                continue;
+            };
+            for (best, &color) in best_places.iter_mut().zip(colors) {
+                let fitness = fitness(self.fitness, average, color);
+                match best {
+                    Some((_, best_fitness)) if *best_fitness <= fitness => {}
+                    _ => *best = Some((pixel, fitness)),
+                }
+            }
+        }
+        best_places
+    }
+}
+
+impl Generator for OuterGenerator {
+    fn generate(
+        &mut self,
+        _data: GeneratorData,
+        common_data: Arc<CommonData>,
+        color_generator: &dyn ColorGenerator,
+        rng: &mut dyn RngCore,
+    ) {
+        let start = Instant::now();
+        let (dimy, dimx) = (common_data.dimy, common_data.dimx);
+        // Pixels that are (or were, until they were placed) edges.
+        let mut queued = BitMap::new(dimy.get(), dimx.get()).unwrap();
+        let place_seeds = |fixed: &[(Pixel, Option<Color>)],
+                           count: usize,
+                           locked: &mut CommonLockedData,
+                           queued: &mut BitMap,
+                           rng: &mut dyn RngCore,
+                           generated: &mut usize| {
+            let mut seed_locations = place_fixed_seeds(
+                fixed,
+                locked,
+                color_generator,
+                rng,
+                generated,
+            );
+            seed_locations.extend(place_seeds_common(
+                count,
+                dimx,
+                dimy,
+                locked,
+                color_generator,
+                rng,
+                generated,
+            ));
+            common_data
+                .pixels_generated
+                .fetch_add(seed_locations.len(), Ordering::Relaxed);
+            common_data
+                .pixels_placed
+                .fetch_add(seed_locations.len(), Ordering::Relaxed);
+            for &seed in &seed_locations {
+                self.add_edges(dimy, dimx, seed, locked, queued);
+            }
+            let placed_pixels = &locked.placed_pixels;
+            locked.edges.retain(|&Pixel { x, y }| {
+                !placed_pixels.get((y as usize, x as usize))
+            });
+        };
+        // How many colors have been generated.
+        let mut generated = 0;
+        place_seeds(
+            &self.seedat,
+            self.seeds,
+            &mut common_data.locked.write().unwrap(),
+            &mut queued,
+            rng,
+            &mut generated,
+        );
+
+        let mut colors: Arc<[Color]> =
+            Arc::from(vec![Color::default(); self.colorcount.get()]);
+        let mut best_places = vec![None; self.colorcount.get()];
+        // With more than one worker, they are spawned once, and each is sent
+        // its range of the edges and the colors every iteration.
+        std::thread::scope(|scope| {
+            let this = &*self;
+            let common_data = &common_data;
+            let workers = this.workers.get();
+            let pool = (0..if workers > 1 { workers } else { 0 })
+                .map(|_| {
+                    let (range_tx, range_rx) =
+                        mpsc::channel::<(Range<usize>, Arc<[Color]>)>();
+                    let (result_tx, result_rx) = mpsc::channel();
+                    scope.spawn(move || {
+                        for (range, colors) in range_rx {
+                            let locked = common_data.locked.read().unwrap();
+                            let result = this.best_places(
+                                dimy, dimx, &locked, range, &colors,
+                            );
+                            // Let go of the colors before the supervisor
+                            // regenerates them.
+                            drop((locked, colors));
+                            if result_tx.send(result).is_err() {
+                                break;
+                            }
                         }
+                    });
+                    (range_tx, result_rx)
+                })
+                .collect::<Vec<_>>();
+            loop {
+                {
+                    let mut locked = common_data.locked.write().unwrap();
+                    // Like `InnerGenerator`'s main loop.
+                    if common_data.stop_early() {
+                        log::trace!("generator interrupted");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if self.stop.reached(
+                        start,
+                        common_data.pixels_placed.load(Ordering::Relaxed),
+                    ) {
+                        log::info!("stop condition reached; stopping");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if locked.edges.is_empty() && !self.reseed {
+                        log::trace!("no edges left; generator finished");
+                        common_data.finished.store(true, Ordering::Release);
+                    } else if locked.edges.is_empty() {
+                        log::trace!("re-seeding");
+                        place_seeds(
+                            &[],
+                            1,
+                            &mut locked,
+                            &mut queued,
+                            rng,
+                            &mut generated,
+                        );
                     }
-                    if common_data.pixels_placed.load(Ordering::SeqCst) == common_data.size.get() {
-                        common_data.finished.store(true, Ordering::SeqCst);
-                        log::trace!("generator finished");
+                }
+
+                log::trace!(target: "barriers", "before progress barrier a");
+                common_data.generator_barrier_wait();
+                log::trace!(target: "barriers", "after progress barrier a");
+                if common_data.finished.load(Ordering::Acquire) {
+                    break;
+                }
+
+                let first = generated;
+                generated += colors.len();
+                let new_colors = Arc::make_mut(&mut colors);
+                color_generator.new_colors(rng, first, new_colors);
+                common_data
+                    .pixels_generated
+                    .fetch_add(colors.len(), Ordering::Relaxed);
+                let results = {
+                    let locked = common_data.locked.read().unwrap();
+                    let len = locked.edges.len();
+                    let workers = pool.len().min(len);
+                    if workers <= 1 {
+                        vec![self.best_places(
+                            dimy, dimx, &locked, 0..len, &colors,
+                        )]
                     } else {
-                        validate_inner_edges(
-                            common_data.dimy,
-                            common_data.dimx,
-                            &mut locked.edges,
-                            &mut locked.placed_pixels,
-                            &self.offsets,
-                        );
+                        // The workers read the edges themselves.
+                        drop(locked);
+                        let chunk = len.div_ceil(workers);
+                        let pool = &pool[..workers];
+                        for (w, (range_tx, _)) in pool.iter().enumerate() {
+                            let range = w * chunk..len.min((w + 1) * chunk);
+                            range_tx
+                                .send((range, colors.clone()))
+                                .expect("outer worker exited");
+                        }
+                        pool.iter()
+                            .map(|(_, result_rx)| {
+                                result_rx.recv().expect("outer worker exited")
+                            })
+                            .collect::<Vec<_>>()
+                    }
+                };
+                // Earlier workers win ties, like in `best_places`.
+                best_places.fill(None);
+                for result in results {
+                    for (best, worker) in best_places.iter_mut().zip(result) {
+                        match (&*best, worker) {
+                            (_, None) => {}
+                            (Some((_, best_fitness)), Some((_, fitness)))
+                                if *best_fitness <= fitness => {}
+                            (_, worker) => *best = worker,
+                        }
                     }
                 }
-            });
-            drop(colors_tx);
-            for handle in handles {
-                handle.join().unwrap_or_else(|err| {
-                    log::error!("Worker panicked: {err:?}")
+
+                log::trace!(target: "barriers", "before progress barrier b");
+                common_data.generator_barrier_wait();
+                log::trace!(target: "barriers", "after progress barrier b");
+
+                let mut locked = common_data.locked.write().unwrap();
+                let locked = &mut *locked;
+                locked.recently_placed.clear();
+                for (&color, best) in colors.iter().zip(&best_places) {
+                    let Some((pixel, fitness)) = *best else { continue };
+                    if self.maxfitness.is_some_and(|max| fitness >= max) {
+                        continue;
+                    }
+                    let yx = (pixel.y as usize, pixel.x as usize);
+                    // Another color this iteration may have taken it.
+                    if locked.placed_pixels.get(yx) {
+                        continue;
+                    }
+                    locked.place(yx, color);
+                    self.add_edges(dimy, dimx, pixel, locked, &mut queued);
+                }
+                let placed_pixels = &locked.placed_pixels;
+                locked.edges.retain(|&Pixel { x, y }| {
+                    !placed_pixels.get((y as usize, x as usize))
                 });
+                // Only this thread changes `pixels_placed`, so the count
+                // cannot be stale.
+                let newly_placed = locked.recently_placed.len();
+                let pixels_placed = newly_placed
+                    + common_data
+                        .pixels_placed
+                        .fetch_add(newly_placed, Ordering::Relaxed);
+                if pixels_placed == common_data.size.get() {
+                    common_data.finished.store(true, Ordering::Release);
+                    log::trace!("generator finished");
+                }
             }
-        }
+        });
     }
 
     #[cfg(test)]
@@ -628,15 +1867,144 @@ impl Generator for InnerGenerator {
     }
 }
 
+/// Fills the image row by row, one row per iteration, with colors straight
+/// from the color generator (no seeds, edges, or fitness).
+///
+/// This is a baseline to compare `InnerGenerator` against, e.g. to separate
+/// the cost of generating colors from the cost of placing them.
+#[derive(Debug, Clone)]
+struct ScanlineGenerator {
+    stop: StopConditions,
+}
+
+impl Generator for ScanlineGenerator {
+    fn generate(
+        &mut self,
+        _data: GeneratorData,
+        common_data: Arc<CommonData>,
+        color_generator: &dyn ColorGenerator,
+        rng: &mut dyn RngCore,
+    ) {
+        let start = Instant::now();
+        let dimx = common_data.dimx.get();
+        for y in 0..common_data.dimy.get() {
+            if common_data.stop_early() {
+                log::trace!("generator interrupted");
+                common_data.finished.store(true, Ordering::Release);
+            } else if self.stop.reached(
+                start,
+                common_data.pixels_placed.load(Ordering::Relaxed),
+            ) {
+                log::info!("stop condition reached; stopping");
+                common_data.finished.store(true, Ordering::Release);
+            }
+
+            log::trace!(target: "barriers", "before progress barrier a");
+            common_data.generator_barrier_wait();
+            log::trace!(target: "barriers", "after progress barrier a");
+            if common_data.finished.load(Ordering::Acquire) {
+                return;
+            }
+
+            let colors: Vec<Color> = (0..dimx)
+                .map(|x| color_generator.new_color(rng, y * dimx + x))
+                .collect();
+            common_data
+                .pixels_generated
+                .fetch_add(colors.len(), Ordering::Relaxed);
+
+            log::trace!(target: "barriers", "before progress barrier b");
+            common_data.generator_barrier_wait();
+            log::trace!(target: "barriers", "after progress barrier b");
+
+            let mut locked = common_data.locked.write().unwrap();
+            locked.recently_placed.clear();
+            for (x, color) in colors.into_iter().enumerate() {
+                locked.place((y, x), color);
+            }
+            common_data.pixels_placed.fetch_add(dimx, Ordering::Relaxed);
+        }
+        log::trace!("generator finished");
+        common_data.finished.store(true, Ordering::Release);
+        // Let the progressors see the last row.
+        common_data.generator_barrier_wait();
+    }
+
+    #[cfg(test)]
+    #[doc(hidden)]
+    fn offsets(&self) -> &[Offset] {
+        &[]
+    }
+}
+
 #[derive(Default)]
 pub struct GeneratorSettings {
     // Generator settings
     seeds: Option<NonZeroUsize>,
+    seedat: Vec<(Pixel, Option<Color>)>,
+    seedpattern: Option<SeedPattern>,
     offsets: Option<Vec<Offset>>,
-    workers: Option<NonZeroUsize>,
+    workers: Option<WorkerCount>,
     colorcount: Option<NonZeroUsize>,
     maxfitness: Option<Channel>,
+    invert_fitness_after: Option<usize>,
     outer: Option<bool>,
+    no_reseed: bool,
+    smartplace: bool,
+    scanline: bool,
+    edgelimit: Option<NonZeroUsize>,
+    edgeselect: Option<EdgeSelect>,
+    fitness: Option<Fitness>,
+    neighborhood: bool,
+    worker_colors: bool,
+    maxpixels: Option<NonZeroUsize>,
+    maxseconds: Option<Duration>,
+    mutate: Option<Channel>,
+}
+
+/// A `--workers` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorkerCount {
+    /// `auto` (the default): one worker per available CPU, but at most
+    /// `MAX_AUTO_WORKERS`, and at most one per `MIN_PIXELS_PER_AUTO_WORKER`
+    /// pixels, since the multi-worker path is slower for small images. In
+    /// particular, images with fewer than twice that many pixels use a single
+    /// worker. `0` is accepted as a synonym.
+    Auto,
+    Exactly(NonZeroUsize),
+}
+
+impl FromStr for WorkerCount {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" | "0" => Ok(WorkerCount::Auto),
+            _ => s.parse().map(WorkerCount::Exactly),
+        }
+    }
+}
+
+const MAX_AUTO_WORKERS: usize = 16;
+const MIN_PIXELS_PER_AUTO_WORKER: usize = 128 * 128;
+
+/// Resolves `workers` for an image of `size` pixels on a machine with
+/// `available` parallelism (if known).
+fn resolve_workers(
+    workers: WorkerCount,
+    size: NonZeroUsize,
+    available: Option<NonZeroUsize>,
+) -> NonZeroUsize {
+    match workers {
+        WorkerCount::Exactly(workers) => workers,
+        WorkerCount::Auto => {
+            let workers = available
+                .map_or(1, NonZeroUsize::get)
+                .min(MAX_AUTO_WORKERS)
+                .min(size.get() / MIN_PIXELS_PER_AUTO_WORKER);
+            NonZeroUsize::new(workers).unwrap_or(NonZeroUsize::MIN)
+        }
+    }
 }
 
 const NORMAL_OFFSETS: &[Offset] = &[
@@ -684,33 +2052,58 @@ lazy_static::lazy_static! {
 pub fn opts() -> impl IntoIterator<Item = Opt> {
     [
         Opt::short_long('e', "seeds", getopt::HasArgument::Yes),
+        Opt::long("seedat", getopt::HasArgument::Yes),
+        Opt::long("seedpattern", getopt::HasArgument::Yes),
         Opt::short_long('O', "offsets", getopt::HasArgument::Yes),
         Opt::short_long('w', "workers", getopt::HasArgument::Yes),
         Opt::short_long('C', "colorcount", getopt::HasArgument::Yes),
         Opt::long("maxfitness", getopt::HasArgument::Yes),
+        Opt::long("invert-fitness-after", getopt::HasArgument::Yes),
         Opt::long("outer", getopt::HasArgument::No),
+        Opt::long("no-reseed", getopt::HasArgument::No),
+        Opt::long("smartplace", getopt::HasArgument::No),
+        Opt::long("scanline", getopt::HasArgument::No),
+        Opt::long("edgelimit", getopt::HasArgument::Yes),
+        Opt::long("edgeselect", getopt::HasArgument::Yes),
+        Opt::long("fitness", getopt::HasArgument::Yes),
+        Opt::long("neighborhood", getopt::HasArgument::No),
+        Opt::long("worker-colors", getopt::HasArgument::No),
+        Opt::long("maxpixels", getopt::HasArgument::Yes),
+        Opt::long("maxseconds", getopt::HasArgument::Yes),
+        Opt::long("mutate", getopt::HasArgument::Yes),
     ]
 }
 
-pub fn handle_opts(opts: &[GetoptItem<'_>]) -> Box<dyn Generator + Send> {
+/// `dimx` and `dimy` are the image's dimensions, which `--seedat` locations
+/// must be within, and which the default number of workers depends on.
+pub fn handle_opts(
+    opts: &[GetoptItem<'_>],
+    dimx: NonZeroUsize,
+    dimy: NonZeroUsize,
+) -> Result<Box<dyn Generator + Send>, ConfigError> {
+    let size = dimx.checked_mul(dimy).expect("setup checked the size");
     let mut settings = GeneratorSettings::default();
 
     macro_rules! set {
         ($field:ident) => {
-            let $field = $field.parse().expect(&format!(
-                "{:?} is not a valid {} value",
-                $field,
-                stringify!($field)
-            ));
-            match &mut settings.$field {
-                Some(_) => {
-                    panic!("multiple {} values specified", stringify!($field))
-                }
-                None => settings.$field = Some($field),
+            if settings.$field.is_some() {
+                return Err(ConfigError::Duplicate {
+                    option: stringify!($field),
+                    value: $field.to_string(),
+                });
             }
+            settings.$field = Some($field.parse().map_err(|_| {
+                ConfigError::InvalidValue {
+                    option: stringify!($field),
+                    value: $field.to_string(),
+                }
+            })?);
         };
     }
 
+    // `-O` options accumulate in order, and `-Oclear` drops the ones before
+    // it, e.g. `-On -Oclear -O1,0` uses only `1,0`. If nothing is added after
+    // the last `-Oclear`, the default offsets are used.
     macro_rules! add_offsets {
         ($offsets:expr) => {
             match &mut settings.offsets {
@@ -722,6 +2115,53 @@ pub fn handle_opts(opts: &[GetoptItem<'_>]) -> Box<dyn Generator + Send> {
 
     for opt in opts {
         match opt {
+            GetoptItem::Opt { opt, arg: Some(seedat) }
+                if opt.is_long("seedat") =>
+            {
+                let invalid = || ConfigError::InvalidValue {
+                    option: "seedat",
+                    value: seedat.to_string(),
+                };
+                let (location, color) = match seedat.split_once(':') {
+                    Some((location, color)) => (location, Some(color)),
+                    None => (*seedat, None),
+                };
+                let (x, y) = location.split_once(',').ok_or_else(invalid)?;
+                let (x, y) = (x.parse().ok(), y.parse().ok());
+                let (Some(x), Some(y)) = (x, y) else {
+                    return Err(invalid());
+                };
+                let pixel = Pixel { x, y };
+                in_bounds(dimy, dimx, pixel).ok_or_else(invalid)?;
+                let color = color
+                    .map(|color| parse_color(color, color255(opts)))
+                    .transpose()
+                    .map_err(|_| invalid())?;
+                if settings.seedat.iter().any(|&(seed, _)| seed == pixel) {
+                    return Err(ConfigError::RepeatedValue {
+                        option: "seedat",
+                        value: location.to_string(),
+                    });
+                }
+                settings.seedat.push((pixel, color));
+            }
+            GetoptItem::Opt { opt, arg: Some(seedpattern) }
+                if opt.is_long("seedpattern") =>
+            {
+                if settings.seedpattern.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "seedpattern",
+                        value: seedpattern.to_string(),
+                    });
+                }
+                settings.seedpattern = Some(seedpattern.parse().map_err(
+                    |()| ConfigError::UnknownValue {
+                        option: "seedpattern",
+                        value: seedpattern.to_string(),
+                        expected: "random, center, corners, grid:NxM, border",
+                    },
+                )?);
+            }
             GetoptItem::Opt { opt, arg: Some(seeds) }
                 if opt.is_long("seeds") =>
             {
@@ -735,25 +2175,24 @@ pub fn handle_opts(opts: &[GetoptItem<'_>]) -> Box<dyn Generator + Send> {
                     "o" => add_offsets!(ORTHOGONAL_OFFSETS),
                     "d" => add_offsets!(DIAGONAL_OFFSETS),
                     "k" => add_offsets!(KNIGHT_OFFSETS),
+                    "clear" => settings.offsets = None,
                     _ => {
-                        if let Some(captures) = OFFSET_REGEX.captures(offset) {
-                            match (
-                                captures.get(1).and_then(|mtch| {
-                                    mtch.as_str().parse().ok()
-                                }),
-                                captures.get(2).and_then(|mtch| {
-                                    mtch.as_str().parse().ok()
-                                }),
-                            ) {
-                                (None, None) => todo!(),
-                                (None, Some(_)) => todo!(),
-                                (Some(_), None) => todo!(),
-                                (Some(dx), Some(dy)) => {
-                                    add_offsets!([Offset { dx, dy }])
-                                }
+                        let invalid = || ConfigError::InvalidValue {
+                            option: "offsets",
+                            value: offset.to_string(),
+                        };
+                        let captures =
+                            OFFSET_REGEX.captures(offset).ok_or_else(invalid)?;
+                        // The regex ensures these are integers, but they may
+                        // not fit in an i32.
+                        match (
+                            captures[1].parse::<i32>(),
+                            captures[2].parse::<i32>(),
+                        ) {
+                            (Ok(dx), Ok(dy)) => {
+                                add_offsets!([Offset { dx, dy }])
                             }
-                        } else {
-                            todo!("error");
+                            _ => return Err(invalid()),
                         }
                     }
                 }
@@ -773,42 +2212,504 @@ pub fn handle_opts(opts: &[GetoptItem<'_>]) -> Box<dyn Generator + Send> {
             {
                 set!(maxfitness);
             }
+            GetoptItem::Opt { opt, arg: Some(after) }
+                if opt.is_long("invert-fitness-after") =>
+            {
+                if settings.invert_fitness_after.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "invert-fitness-after",
+                        value: after.to_string(),
+                    });
+                }
+                settings.invert_fitness_after = Some(after.parse().map_err(
+                    |_| ConfigError::InvalidValue {
+                        option: "invert-fitness-after",
+                        value: after.to_string(),
+                    },
+                )?);
+            }
             GetoptItem::Opt { opt, arg: None } if opt.is_long("outer") => {
-                todo!("figure out wait handling")
+                settings.outer = Some(true);
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("no-reseed") =>
+            {
+                settings.no_reseed = true;
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("smartplace") =>
+            {
+                settings.smartplace = true;
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("scanline") => {
+                settings.scanline = true;
+            }
+            GetoptItem::Opt { opt, arg: Some(edgelimit) }
+                if opt.is_long("edgelimit") =>
+            {
+                set!(edgelimit);
+            }
+            // Only matters with --edgelimit.
+            GetoptItem::Opt { opt, arg: Some(edgeselect) }
+                if opt.is_long("edgeselect") =>
+            {
+                if settings.edgeselect.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "edgeselect",
+                        value: edgeselect.to_string(),
+                    });
+                }
+                settings.edgeselect = Some(match *edgeselect {
+                    "fifo" => EdgeSelect::Fifo,
+                    "lifo" => EdgeSelect::Lifo,
+                    "random" => EdgeSelect::Random,
+                    _ => {
+                        return Err(ConfigError::UnknownValue {
+                            option: "edgeselect",
+                            value: edgeselect.to_string(),
+                            expected: "fifo, lifo, random",
+                        })
+                    }
+                });
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("neighborhood") =>
+            {
+                settings.neighborhood = true;
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("worker-colors") =>
+            {
+                settings.worker_colors = true;
+            }
+            GetoptItem::Opt { opt, arg: Some(maxpixels) }
+                if opt.is_long("maxpixels") =>
+            {
+                set!(maxpixels);
+            }
+            GetoptItem::Opt { opt, arg: Some(amount) }
+                if opt.is_long("mutate") =>
+            {
+                if settings.mutate.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "mutate",
+                        value: amount.to_string(),
+                    });
+                }
+                settings.mutate = match amount.parse::<Channel>() {
+                    Ok(value) if value >= 0.0 && value.is_finite() => {
+                        Some(value)
+                    }
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            option: "mutate",
+                            value: amount.to_string(),
+                        })
+                    }
+                };
+            }
+            GetoptItem::Opt { opt, arg: Some(seconds) }
+                if opt.is_long("maxseconds") =>
+            {
+                if settings.maxseconds.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "maxseconds",
+                        value: seconds.to_string(),
+                    });
+                }
+                // Like `--timeout`.
+                settings.maxseconds = Some(
+                    seconds
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|&seconds| seconds > 0.0)
+                        .and_then(|seconds| {
+                            Duration::try_from_secs_f64(seconds).ok()
+                        })
+                        .ok_or_else(|| ConfigError::InvalidValue {
+                            option: "maxseconds",
+                            value: seconds.to_string(),
+                        })?,
+                );
+            }
+            GetoptItem::Opt { opt, arg: Some(fitness) }
+                if opt.is_long("fitness") =>
+            {
+                if settings.fitness.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "fitness",
+                        value: fitness.to_string(),
+                    });
+                }
+                settings.fitness = Some(fitness.parse().map_err(|()| {
+                    ConfigError::UnknownValue {
+                        option: "fitness",
+                        value: fitness.to_string(),
+                        expected: "sumsq, abs, maxchannel, luma",
+                    }
+                })?);
             }
             _ => {}
         }
     }
-    match settings.outer {
-        Some(true) => todo!(),
+    let stop = StopConditions {
+        max_pixels: settings.maxpixels.map(NonZeroUsize::get),
+        max_duration: settings.maxseconds,
+    };
+    // `--mask` is a setup option, but not every generator supports it.
+    let mask = opts.iter().any(|item| {
+        matches!(item, GetoptItem::Opt { opt, arg: Some(_) }
+            if opt.is_long("mask"))
+    });
+    if settings.scanline {
+        // None of the other options apply to the scanline generator.
+        let other = [
+            ("seeds", settings.seeds.is_some()),
+            ("seedat", !settings.seedat.is_empty()),
+            ("seedpattern", settings.seedpattern.is_some()),
+            ("offsets", settings.offsets.is_some()),
+            ("workers", settings.workers.is_some()),
+            ("colorcount", settings.colorcount.is_some()),
+            ("maxfitness", settings.maxfitness.is_some()),
+            (
+                "invert-fitness-after",
+                settings.invert_fitness_after.is_some(),
+            ),
+            ("outer", settings.outer.is_some()),
+            ("no-reseed", settings.no_reseed),
+            ("smartplace", settings.smartplace),
+            ("edgelimit", settings.edgelimit.is_some()),
+            ("edgeselect", settings.edgeselect.is_some()),
+            ("fitness", settings.fitness.is_some()),
+            ("neighborhood", settings.neighborhood),
+            ("worker-colors", settings.worker_colors),
+            ("mutate", settings.mutate.is_some()),
+            ("mask", mask),
+        ]
+        .into_iter()
+        .find_map(|(other, given)| given.then_some(other));
+        if let Some(other) = other {
+            return Err(ConfigError::Conflict { option: "scanline", other });
+        }
+        log::info!("using the scanline generator");
+        return Ok(Box::new(ScanlineGenerator { stop }));
+    }
+    if settings.outer == Some(true) {
+        // The outer generator picks the best edge for each color, so the
+        // options for choosing among edges do not apply to it. It also only
+        // ever minimizes fitness.
+        let other = [
+            ("smartplace", settings.smartplace),
+            ("edgelimit", settings.edgelimit.is_some()),
+            ("edgeselect", settings.edgeselect.is_some()),
+            ("neighborhood", settings.neighborhood),
+            (
+                "invert-fitness-after",
+                settings.invert_fitness_after.is_some(),
+            ),
+            ("mask", mask),
+            ("mutate", settings.mutate.is_some()),
+            ("worker-colors", settings.worker_colors),
+        ]
+        .into_iter()
+        .find_map(|(other, given)| given.then_some(other));
+        if let Some(other) = other {
+            return Err(ConfigError::Conflict { option: "outer", other });
+        }
+    }
+    if settings.mutate.is_some() {
+        // Mutation makes one color per iteration from an edge's color, so
+        // there are no new colors to compare, or choose among edges for.
+        let other = [
+            ("colorcount", settings.colorcount.is_some()),
+            ("maxfitness", settings.maxfitness.is_some()),
+            (
+                "invert-fitness-after",
+                settings.invert_fitness_after.is_some(),
+            ),
+            ("smartplace", settings.smartplace),
+            ("edgelimit", settings.edgelimit.is_some()),
+            ("edgeselect", settings.edgeselect.is_some()),
+            ("fitness", settings.fitness.is_some()),
+            ("neighborhood", settings.neighborhood),
+            ("worker-colors", settings.worker_colors),
+        ]
+        .into_iter()
+        .find_map(|(other, given)| given.then_some(other));
+        if let Some(other) = other {
+            return Err(ConfigError::Conflict { option: "mutate", other });
+        }
+    }
+    // Masked-out pixels count as placed, but have no color of their own, so
+    // they must not be compared to when choosing where to place a color.
+    let other = [
+        ("smartplace", settings.smartplace),
+        ("neighborhood", settings.neighborhood),
+    ]
+    .into_iter()
+    .find_map(|(other, given)| (mask && given).then_some(other));
+    if let Some(other) = other {
+        return Err(ConfigError::Conflict { option: "mask", other });
+    }
+    if settings.neighborhood && settings.smartplace {
+        // Both choose where to place a color next to its edge.
+        return Err(ConfigError::Conflict {
+            option: "neighborhood",
+            other: "smartplace",
+        });
+    }
+    let workers = settings.workers.unwrap_or(WorkerCount::Auto);
+    let available = std::thread::available_parallelism().ok();
+    // `--single-thread` runs everything on the main thread, which only the
+    // single-worker generator does.
+    let single_thread = opts.iter().any(|item| {
+        matches!(item, GetoptItem::Opt { opt, arg: None }
+            if opt.is_long("single-thread"))
+    });
+    // Only the single-worker generator supports `--mutate`, too.
+    let mutate = settings.mutate.is_some();
+    let resolved = match (single_thread || mutate, workers) {
+        (true, WorkerCount::Exactly(workers)) if workers.get() > 1 => {
+            return Err(ConfigError::Conflict {
+                option: if single_thread { "single-thread" } else { "mutate" },
+                other: "workers",
+            });
+        }
+        (true, _) => NonZeroUsize::MIN,
+        (false, _) => resolve_workers(workers, size, available),
+    };
+    match workers {
+        _ if single_thread => log::info!("using 1 worker (single thread)"),
+        _ if mutate => log::info!("using 1 worker (mutating)"),
+        WorkerCount::Exactly(_) => log::info!("using {resolved} worker(s)"),
+        WorkerCount::Auto => log::info!(
+            "using {resolved} worker(s) for {size} pixels ({} available)",
+            available.map_or("unknown".to_string(), |n| n.to_string()),
+        ),
+    }
+    // `--worker-colors` splits the colors between workers, so it needs more
+    // than one. Only an automatic worker count can end up with one anyway.
+    if settings.worker_colors && resolved.get() == 1 {
+        if single_thread || matches!(workers, WorkerCount::Exactly(_)) {
+            return Err(ConfigError::Conflict {
+                option: "worker-colors",
+                other: if single_thread { "single-thread" } else { "workers" },
+            });
+        }
+        log::warn!("--worker-colors has no effect with only 1 worker");
+    }
+    // With `--seedat`, only `--seeds` (or `--seedpattern`) adds seeds.
+    let pattern = settings.seedpattern.unwrap_or_default();
+    let seeds = match (settings.seeds, settings.seedpattern) {
+        (Some(seeds), _) => seeds.get(),
+        (None, Some(pattern)) => pattern.default_count(),
+        (None, None) if settings.seedat.is_empty() => 1,
+        (None, None) => 0,
+    };
+    // Pattern locations are placed like `--seedat` ones without a color, and
+    // any seeds the pattern has no room for are placed randomly.
+    let mut seedat = settings.seedat;
+    let mut random_seeds = seeds;
+    for pixel in pattern.locations(seeds, dimx, dimy) {
+        random_seeds -= 1;
+        if !seedat.iter().any(|&(seed, _)| seed == pixel) {
+            seedat.push((pixel, None));
+        }
+    }
+    Ok(match settings.outer {
+        Some(true) => Box::new(OuterGenerator {
+            seeds: random_seeds,
+            seedat,
+            offsets: settings
+                .offsets
+                .unwrap_or_else(|| Vec::from(NORMAL_OFFSETS)),
+            workers: resolved,
+            colorcount: settings
+                .colorcount
+                .unwrap_or(NonZeroUsize::new(1).unwrap()),
+            maxfitness: settings.maxfitness,
+            reseed: !settings.no_reseed,
+            fitness: settings.fitness.unwrap_or_default(),
+            stop,
+        }),
         Some(false) | None => Box::new(InnerGenerator {
-            seeds: settings.seeds.unwrap_or(NonZeroUsize::new(1).unwrap()),
+            seeds: random_seeds,
+            seedat,
             offsets: settings
                 .offsets
                 .unwrap_or_else(|| Vec::from(NORMAL_OFFSETS)),
-            workers: settings.workers.unwrap_or(NonZeroUsize::new(1).unwrap()),
+            workers: resolved,
             colorcount: settings
                 .colorcount
                 .unwrap_or(NonZeroUsize::new(1).unwrap()),
             maxfitness: settings.maxfitness,
+            invert_fitness_after: settings.invert_fitness_after,
+            reseed: !settings.no_reseed,
+            smartplace: settings.smartplace,
+            edgelimit: settings.edgelimit,
+            edgeselect: settings.edgeselect.unwrap_or(EdgeSelect::Fifo),
+            fitness: settings.fitness.unwrap_or_default(),
+            neighborhood: settings.neighborhood,
+            worker_colors: settings.worker_colors,
+            mutate: settings.mutate,
+            stop,
         }),
-    }
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use std::borrow::Cow;
+    use std::{
+        borrow::Cow,
+        collections::VecDeque,
+        num::NonZeroUsize,
+        simd::num::SimdFloat,
+        sync::{atomic::Ordering, Arc},
+    };
 
+    use bitmap::BitMap;
     use getopt::Getopt;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
 
     use super::{
-        Offset, DIAGONAL_OFFSETS, KNIGHT_OFFSETS, NORMAL_OFFSETS,
-        ORTHOGONAL_OFFSETS,
+        Fitness, GeneratorData, Offset, Pixel, WorkerCount, DIAGONAL_OFFSETS,
+        KNIGHT_OFFSETS, NORMAL_OFFSETS, ORTHOGONAL_OFFSETS,
+    };
+    use crate::{
+        color::{from_3, Channel, Color, ColorGenerator},
+        progress::{NoOpProgressor, ProgressData, ProgressInterval, Progressor},
+        CommonData,
     };
 
+    /// Removes every edge without any open neighbors, which is what
+    /// `validate_edges_near` does for the edges near the placed pixels.
+    fn validate_inner_edges(
+        dimy: NonZeroUsize,
+        dimx: NonZeroUsize,
+        edges: &mut VecDeque<Pixel>,
+        placed_pixels: &BitMap,
+        offsets: &[Offset],
+    ) {
+        edges.retain(|&pixel| {
+            placed_pixels.get((pixel.y as usize, pixel.x as usize))
+                && super::has_open_neighbor(
+                    dimy,
+                    dimx,
+                    pixel,
+                    placed_pixels,
+                    offsets,
+                )
+        });
+    }
+
+    /// Runs a generator configured by `args` to completion, with a no-op
+    /// progressor on the current thread.
+    fn run_generator(args: &[&str]) -> Arc<CommonData> {
+        run_generator_with(args, |_| {})
+    }
+
+    /// Like `run_generator`, but calls `on_start` after the generator thread
+    /// has been spawned.
+    fn run_generator_with(
+        args: &[&str],
+        on_start: impl FnOnce(&Arc<CommonData>),
+    ) -> Arc<CommonData> {
+        run_generator_with_colors(args, None, on_start)
+    }
+
+    /// Like `run_generator_with`, but uses `color_generator` instead of the
+    /// one configured by `args`, if given.
+    fn run_generator_with_colors(
+        args: &[&str],
+        color_generator: Option<Box<dyn ColorGenerator + Send>>,
+        on_start: impl FnOnce(&Arc<CommonData>),
+    ) -> Arc<CommonData> {
+        let getopt = Getopt::from_iter(
+            crate::setup::opts()
+                .into_iter()
+                .chain(super::opts())
+                .chain(crate::color::opts()),
+        )
+        .unwrap();
+        let opts = getopt
+            .parse(args.iter().copied())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let (common_data, mut rng) =
+            crate::setup::handle_opts(&opts, None).unwrap().build();
+        let mut generator =
+            super::handle_opts(&opts, common_data.dimx, common_data.dimy)
+                .unwrap();
+        let color_generator = color_generator
+            .unwrap_or_else(|| crate::color::handle_opts(&opts).unwrap());
+
+        let gen_thread = std::thread::spawn({
+            let common_data = common_data.clone();
+            move || {
+                generator.generate(
+                    GeneratorData {},
+                    common_data,
+                    &*color_generator,
+                    &mut rng,
+                );
+            }
+        });
+        on_start(&common_data);
+        NoOpProgressor.run_alone(
+            ProgressData {
+                progress_interval: ProgressInterval::Cycles(1),
+                progress_count: 1,
+                sample_every: None,
+                eta_window: NonZeroUsize::MIN,
+            },
+            common_data.clone(),
+        );
+        gen_thread.join().unwrap();
+        common_data
+    }
+
+    #[test]
+    fn distance_cache() {
+        use super::{fitness, DistanceCache, Fitness, MAX_CACHED_PALETTE};
+        use rand::seq::SliceRandom;
+
+        let palette = [
+            from_3(1.0, 0.0, 0.0),
+            from_3(0.0, 1.0, 0.0),
+            from_3(0.2, 0.4, 0.6),
+        ];
+        let mut rng = ChaCha12Rng::seed_from_u64(5);
+        let mut cached = DistanceCache::new(Some(palette.len()), Fitness::Abs);
+        for _ in 0..3 {
+            let colors = (0..8)
+                .map(|_| *palette.choose(&mut rng).unwrap())
+                .collect::<Vec<_>>();
+            cached.clear();
+            for _ in 0..20 {
+                let edge = *palette.choose(&mut rng).unwrap();
+                let row = cached.row(edge, &colors).unwrap();
+                for (&distance, &color) in row.iter().zip(&colors) {
+                    assert_eq!(
+                        distance.to_bits(),
+                        fitness(Fitness::Abs, edge, color).to_bits()
+                    );
+                }
+            }
+            assert!(cached.used <= palette.len());
+        }
+
+        // Generators without a small palette skip the cache.
+        for palette_size in [None, Some(MAX_CACHED_PALETTE + 1)] {
+            let mut direct = DistanceCache::new(palette_size, Fitness::Abs);
+            assert!(direct.row(palette[0], &palette).is_none());
+        }
+    }
+
     #[test]
     fn basic_offsets() {
-        let args_iter: [(&[&str], Cow<'_, [Offset]>); 9] = [
+        let args_iter: [(&[&str], Cow<'_, [Offset]>); 11] = [
             (&[], NORMAL_OFFSETS.into()),
             (&["-On"], NORMAL_OFFSETS.into()),
             (&["-Oo"], ORTHOGONAL_OFFSETS.into()),
@@ -832,6 +2733,11 @@ mod tests {
                     .chain(std::iter::once(Offset { dx: -3, dy: -3 }))
                     .collect(),
             ),
+            (
+                &["-On", "-Oclear", "-O1,0"],
+                Cow::Borrowed(&[Offset { dx: 1, dy: 0 }]),
+            ),
+            (&["-Ok", "-O1,0", "-Oclear"], NORMAL_OFFSETS.into()),
         ];
 
         let getopt = Getopt::from_iter(super::opts()).unwrap();
@@ -842,8 +2748,1016 @@ mod tests {
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap();
 
-            let should_be_normal = super::handle_opts(&opts);
+            let min = NonZeroUsize::MIN;
+            let should_be_normal =
+                super::handle_opts(&opts, min, min).unwrap();
             assert_eq!(should_be_normal.offsets(), &*expected);
         }
     }
+
+    #[test]
+    fn resolve_workers() {
+        use super::resolve_workers;
+
+        let n = |n| NonZeroUsize::new(n).unwrap();
+        let large = n(4096 * 4096);
+        let tiny = n(16 * 16);
+
+        assert_eq!("auto".parse(), Ok(WorkerCount::Auto));
+        assert_eq!("0".parse(), Ok(WorkerCount::Auto));
+        assert_eq!("3".parse(), Ok(WorkerCount::Exactly(n(3))));
+
+        // Explicit counts are used as-is.
+        let three = WorkerCount::Exactly(n(3));
+        assert_eq!(resolve_workers(three, large, Some(n(8))), n(3));
+        assert_eq!(resolve_workers(three, tiny, Some(n(8))), n(3));
+        assert_eq!(resolve_workers(three, large, None), n(3));
+
+        let auto = WorkerCount::Auto;
+        assert_eq!(resolve_workers(auto, large, Some(n(8))), n(8));
+        assert_eq!(resolve_workers(auto, large, Some(n(64))), n(16));
+        assert_eq!(resolve_workers(auto, large, None), n(1));
+        // Small images use fewer workers, and tiny ones only one.
+        assert_eq!(resolve_workers(auto, n(256 * 256), Some(n(8))), n(4));
+        assert_eq!(resolve_workers(auto, tiny, Some(n(8))), n(1));
+    }
+
+    #[test]
+    fn choose_empty_pixels_uniform() {
+        let mut placed_pixels = BitMap::new(4, 4).unwrap();
+        for col in 0..4 {
+            placed_pixels.set((0, col), true);
+            placed_pixels.set((2, col), true);
+        }
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        // Choosing 3 of the 8 empty pixels, each should be chosen 3/8 of the
+        // time.
+        const TRIALS: usize = 40000;
+        let mut counts = [[0usize; 4]; 4];
+        for _ in 0..TRIALS {
+            let chosen =
+                super::choose_empty_pixels(&placed_pixels, 3, &mut rng);
+            assert_eq!(chosen.len(), 3);
+            for &(row, col) in &chosen {
+                assert!(!placed_pixels.get((row, col)));
+                counts[row][col] += 1;
+            }
+            let mut deduped = chosen.clone();
+            deduped.sort();
+            deduped.dedup();
+            assert_eq!(deduped.len(), 3, "{chosen:?} has duplicates");
+        }
+        let expected = TRIALS * 3 / 8;
+        let mut chi_square = 0.0;
+        for row in [1, 3] {
+            for (col, &count) in counts[row].iter().enumerate() {
+                assert!(
+                    count.abs_diff(expected) < expected / 20,
+                    "({row},{col}) chosen {count} times, expected ~{expected}"
+                );
+                let diff = count as f64 - expected as f64;
+                chi_square += diff * diff / expected as f64;
+            }
+        }
+        // The 99.9th percentile of the chi-square distribution with 7 degrees
+        // of freedom (8 buckets).
+        assert!(chi_square < 24.32, "chi-square statistic {chi_square}");
+    }
+
+    #[test]
+    fn place_seeds_slow_path() {
+        let getopt = Getopt::from_iter(crate::setup::opts()).unwrap();
+        let opts = getopt
+            .parse(["-s8x8", "-S1"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let (common_data, mut rng) =
+            crate::setup::handle_opts(&opts, None).unwrap().build();
+        let color_generator = crate::color::handle_opts(&[]).unwrap();
+        let mut locked = common_data.locked.write().unwrap();
+        // Leave only 5 pixels empty, so random probing almost always fails.
+        for row in 0..8 {
+            for col in 0..8 {
+                if row * 8 + col >= 5 {
+                    locked.placed_pixels.set((row, col), true);
+                }
+            }
+        }
+
+        let placed = super::place_seeds_common(
+            3,
+            common_data.dimx,
+            common_data.dimy,
+            &mut locked,
+            &*color_generator,
+            &mut rng,
+            &mut 0,
+        );
+        assert_eq!(placed.len(), 3);
+        assert_eq!(locked.placed_pixels.count_ones(), 64 - 2);
+
+        // Asking for more seeds than there is room for places what it can.
+        let placed = super::place_seeds_common(
+            10,
+            common_data.dimx,
+            common_data.dimy,
+            &mut locked,
+            &*color_generator,
+            &mut rng,
+            &mut 0,
+        );
+        assert_eq!(placed.len(), 2);
+        assert_eq!(locked.placed_pixels.count_ones(), 64);
+    }
+
+    #[test]
+    fn smartplace() {
+        let getopt = Getopt::from_iter(crate::setup::opts()).unwrap();
+        let opts = getopt
+            .parse(["-s5x5"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let black = from_3(0.0, 0.0, 0.0);
+        let white = from_3(1.0, 1.0, 1.0);
+        let gray = from_3(0.3, 0.3, 0.3);
+        let blue = from_3(0.0, 0.0, 1.0);
+        // The first open neighbor of the edge at (2,2) is (2,1), but a white
+        // pixel at (2,4) makes (2,3) the best fit for white. Blue is nearer
+        // gray than black by the sum of squares, but not by luma.
+        let cases = [
+            (false, Fitness::SumSq, white, white, Pixel { x: 1, y: 2 }),
+            (true, Fitness::SumSq, white, white, Pixel { x: 3, y: 2 }),
+            (true, Fitness::SumSq, white, black, Pixel { x: 1, y: 2 }),
+            (true, Fitness::SumSq, gray, blue, Pixel { x: 3, y: 2 }),
+            (true, Fitness::Luma, gray, blue, Pixel { x: 1, y: 2 }),
+        ];
+        for (smart, fitness, other, color, expected) in cases {
+            let (common_data, _) =
+                crate::setup::handle_opts(&opts, None).unwrap().build();
+            let mut locked = common_data.locked.write().unwrap();
+            let locked = &mut *locked;
+            locked.place((2, 2), black);
+            locked.place((2, 4), other);
+
+            let placement = super::Placement {
+                dimy: common_data.dimy,
+                dimx: common_data.dimx,
+                offsets: ORTHOGONAL_OFFSETS,
+                smart,
+                fitness,
+            };
+            let placed = super::place_pixel_inner(
+                placement,
+                Pixel { x: 2, y: 2 },
+                color,
+                &mut locked.image,
+                &mut locked.edges,
+                &mut locked.placed_pixels,
+            );
+            assert_eq!(placed, Ok(expected), "smart: {smart}, {fitness:?}");
+            let (y, x) = (expected.y as usize, expected.x as usize);
+            assert!(locked.placed_pixels.get((y, x)));
+            assert_eq!(locked.image[(y, x)], color);
+            assert_eq!(locked.edges.back(), Some(&expected));
+        }
+    }
+
+    #[test]
+    fn edges_stay_unique() {
+        let getopt = Getopt::from_iter(crate::setup::opts()).unwrap();
+        let opts = getopt
+            .parse(["-s32x24"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let (common_data, _) =
+            crate::setup::handle_opts(&opts, None).unwrap().build();
+        let (dimy, dimx) = (common_data.dimy, common_data.dimx);
+        let mut locked = common_data.locked.write().unwrap();
+        let locked = &mut *locked;
+        let color = from_3(0.5, 0.5, 0.5);
+        for seed in [Pixel { x: 3, y: 4 }, Pixel { x: 20, y: 10 }] {
+            locked.place((seed.y as usize, seed.x as usize), color);
+            locked.edges.push_back(seed);
+        }
+        let mut placed = 2;
+        while let Some(&edge) = locked.edges.front() {
+            for offsets in [NORMAL_OFFSETS, DIAGONAL_OFFSETS] {
+                placed += super::place_pixel_inner(
+                    super::Placement {
+                        dimy,
+                        dimx,
+                        offsets,
+                        smart: false,
+                        fitness: Fitness::SumSq,
+                    },
+                    edge,
+                    color,
+                    &mut locked.image,
+                    &mut locked.edges,
+                    &mut locked.placed_pixels,
+                )
+                .map_or(0, |_| 1);
+            }
+            validate_inner_edges(
+                dimy,
+                dimx,
+                &mut locked.edges,
+                &locked.placed_pixels,
+                NORMAL_OFFSETS,
+            );
+            // The edges are exactly the placed pixels next to open ones, each
+            // once.
+            let mut seen = BitMap::new(24, 32).unwrap();
+            for &Pixel { x, y } in &locked.edges {
+                let (y, x) = (y as usize, x as usize);
+                assert!(!seen.get((y, x)), "duplicate edge ({x},{y})");
+                seen.set((y, x), true);
+            }
+            let placed_pixels = &locked.placed_pixels;
+            let mut boundary = 0;
+            placed_pixels.for_each_true(|y, x| {
+                let pixel = Pixel { x: x as i32, y: y as i32 };
+                boundary += usize::from(NORMAL_OFFSETS.iter().any(|&offset| {
+                    super::in_bounds(dimy, dimx, pixel + offset)
+                        .is_some_and(|location| !placed_pixels.get(location))
+                }));
+            });
+            assert_eq!(locked.edges.len(), boundary);
+        }
+        assert_eq!(placed, 32 * 24);
+    }
+
+    #[test]
+    fn validate_edges_near() {
+        let getopt = Getopt::from_iter(crate::setup::opts()).unwrap();
+        let opts = getopt
+            .parse(["-s40x30"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let lopsided = &[
+            Offset { dx: 1, dy: 0 },
+            Offset { dx: 0, dy: 1 },
+            Offset { dx: 2, dy: -1 },
+        ];
+        let color = from_3(0.5, 0.5, 0.5);
+        for offsets in [NORMAL_OFFSETS, KNIGHT_OFFSETS, lopsided] {
+            let (common_data, _) =
+                crate::setup::handle_opts(&opts, None).unwrap().build();
+            let (dimy, dimx) = (common_data.dimy, common_data.dimx);
+            let mut locked = common_data.locked.write().unwrap();
+            let locked = &mut *locked;
+            let mut rng = ChaCha12Rng::seed_from_u64(6);
+            let mut incremental = VecDeque::new();
+            let mut closed = BitMap::new(30, 40).unwrap();
+            let mut steps = 0;
+            while locked.placed_pixels.count_ones() < 40 * 30 {
+                let mut placed = vec![];
+                // Sometimes seed, as the generator does when it runs out of
+                // edges.
+                if locked.edges.is_empty() || rng.gen_ratio(1, 20) {
+                    let (y, x) = super::choose_empty_pixels(
+                        &locked.placed_pixels,
+                        1,
+                        &mut rng,
+                    )[0];
+                    let seed = Pixel { x: x as i32, y: y as i32 };
+                    locked.place((y, x), color);
+                    locked.edges.push_back(seed);
+                    incremental.push_back(seed);
+                    placed.push(seed);
+                }
+                for _ in 0..rng.gen_range(1..4) {
+                    let index = rng.gen_range(0..locked.edges.len());
+                    let edge = locked.edges[index];
+                    if let Ok(pixel) = super::place_pixel_inner(
+                        super::Placement {
+                            dimy,
+                            dimx,
+                            offsets,
+                            smart: false,
+                            fitness: Fitness::SumSq,
+                        },
+                        edge,
+                        color,
+                        &mut locked.image,
+                        &mut locked.edges,
+                        &mut locked.placed_pixels,
+                    ) {
+                        incremental.push_back(pixel);
+                        placed.push(pixel);
+                    }
+                }
+                validate_inner_edges(
+                    dimy,
+                    dimx,
+                    &mut locked.edges,
+                    &locked.placed_pixels,
+                    offsets,
+                );
+                super::validate_edges_near(
+                    dimy,
+                    dimx,
+                    &mut incremental,
+                    &locked.placed_pixels,
+                    offsets,
+                    placed,
+                    &mut closed,
+                );
+                assert_eq!(incremental, locked.edges, "step {steps}");
+                assert_eq!(closed.count_ones(), 0);
+                steps += 1;
+            }
+            assert!(steps > 300, "{steps} steps");
+        }
+    }
+
+    #[test]
+    fn invalid_opts() {
+        #[rustfmt::skip]
+        let args_iter: [(&[&str], &str); 29] = [
+            (&["-w2", "-w3"], "multiple values specified for --workers (extra value: \"3\")"),
+            (&["--edgelimit=0"], "invalid value for --edgelimit: \"0\""),
+            (&["--edgeselect=oldest"], "unknown value for --edgeselect: \"oldest\" (expected one of: fifo, lifo, random)"),
+            (&["--fitness=cubic"], "unknown value for --fitness: \"cubic\" (expected one of: sumsq, abs, maxchannel, luma)"),
+            (&["--scanline", "--edgelimit=4"], "--scanline cannot be used together with --edgelimit"),
+            (&["--scanline", "-e4"], "--scanline cannot be used together with --seeds"),
+            (&["--scanline", "--outer"], "--scanline cannot be used together with --outer"),
+            (&["--outer", "--smartplace"], "--outer cannot be used together with --smartplace"),
+            (&["--neighborhood", "--smartplace"], "--neighborhood cannot be used together with --smartplace"),
+            (&["-wmany"], "invalid value for --workers: \"many\""),
+            (&["-w-1"], "invalid value for --workers: \"-1\""),
+            (&["-Ox"], "invalid value for --offsets: \"x\""),
+            (&["-O1,99999999999"], "invalid value for --offsets: \"1,99999999999\""),
+            (&["--seedat=16,0"], "invalid value for --seedat: \"16,0\""),
+            (&["--seedat=3,-1"], "invalid value for --seedat: \"3,-1\""),
+            (&["--seedat=3,4:red"], "invalid value for --seedat: \"3,4:red\""),
+            (&["--seedat=3,4", "--seedat=3,4:1,0,0"], "--seedat specifies \"3,4\" more than once"),
+            (&["--seedpattern=grid:0x4"], "unknown value for --seedpattern: \"grid:0x4\" (expected one of: random, center, corners, grid:NxM, border)"),
+            (&["--scanline", "--seedpattern=center"], "--scanline cannot be used together with --seedpattern"),
+            (&["--invert-fitness-after=-5"], "invalid value for --invert-fitness-after: \"-5\""),
+            (&["--outer", "--invert-fitness-after=5"], "--outer cannot be used together with --invert-fitness-after"),
+            (&["--maxpixels=0"], "invalid value for --maxpixels: \"0\""),
+            (&["--maxseconds=-1"], "invalid value for --maxseconds: \"-1\""),
+            (&["--maxseconds=1", "--maxseconds=2"], "multiple values specified for --maxseconds (extra value: \"2\")"),
+            (&["--mutate=-0.1"], "invalid value for --mutate: \"-0.1\""),
+            (&["--mutate=0.1", "-C2"], "--mutate cannot be used together with --colorcount"),
+            (&["--mutate=0.1", "-w2"], "--mutate cannot be used together with --workers"),
+            (&["--outer", "--worker-colors"], "--outer cannot be used together with --worker-colors"),
+            (&["--worker-colors", "-w1"], "--worker-colors cannot be used together with --workers"),
+        ];
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+
+        for (args, expected) in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let dim = NonZeroUsize::new(16).unwrap();
+            let err = super::handle_opts(&opts, dim, dim).err().unwrap();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn edge_select() {
+        use super::EdgeSelect;
+
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let limit = NonZeroUsize::new(4);
+        let mut selected = vec![];
+        for select in [EdgeSelect::Fifo, EdgeSelect::Lifo, EdgeSelect::Random] {
+            assert!(!select.select(None, 10, &mut rng, &mut selected));
+            assert!(!select.select(limit, 4, &mut rng, &mut selected));
+            assert!(select.select(limit, 10, &mut rng, &mut selected));
+            assert_eq!(selected.len(), 4);
+            assert!(selected.iter().all(|&edge| edge < 10));
+            match select {
+                EdgeSelect::Fifo => assert_eq!(selected, [0, 1, 2, 3]),
+                EdgeSelect::Lifo => assert_eq!(selected, [9, 8, 7, 6]),
+                EdgeSelect::Random => {
+                    assert!(selected.windows(2).all(|w| w[0] < w[1]))
+                }
+            }
+        }
+
+        // Each strategy makes a different image, but the same one for any
+        // number of workers.
+        let mut images = vec![];
+        for select in ["fifo", "lifo", "random"] {
+            let select = format!("--edgeselect={select}");
+            let mut outputs = ["-w1", "-w3"].map(|workers| {
+                let common_data = run_generator(&[
+                    "-s40x30", "-S9", "-C5", "--edgelimit=8", &select, workers,
+                ]);
+                assert!(common_data.finished.load(Ordering::SeqCst));
+                let mut output = vec![];
+                let locked = common_data.locked.read().unwrap();
+                locked.write_image(&mut output).unwrap();
+                output
+            });
+            assert!(outputs[0] == outputs[1], "{select}");
+            images.push(std::mem::take(&mut outputs[0]));
+        }
+        let unlimited = run_generator(&["-s40x30", "-S9", "-C5"]);
+        let mut output = vec![];
+        unlimited.locked.read().unwrap().write_image(&mut output).unwrap();
+        images.push(output);
+        for (i, image) in images.iter().enumerate() {
+            assert!(images[..i].iter().all(|other| other != image), "{i}");
+        }
+    }
+
+    #[test]
+    fn no_reseed() {
+        // Diagonal offsets can only reach half of the pixels from one seed.
+        for workers in ["1", "2"] {
+            let common_data = run_generator(&[
+                "-s8x8",
+                "-S1",
+                "-e1",
+                "-Od",
+                "-w",
+                workers,
+                "--no-reseed",
+            ]);
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            let placed = common_data.pixels_placed.load(Ordering::SeqCst);
+            assert!(placed <= 32, "placed {placed} pixels");
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), placed);
+            assert!(locked.edges.is_empty());
+        }
+    }
+
+    #[test]
+    fn multi_worker_stress() {
+        // The counters and `finished` are shared between the generator,
+        // its workers, and the progressor, with relaxed orderings.
+        for seed in ["1", "2", "3", "4", "5", "6", "7", "8"] {
+            let common_data =
+                run_generator(&["-s48x40", "-S", seed, "-w4", "-C7"]);
+            let size = common_data.size.get();
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+            let generated = common_data.pixels_generated.load(Ordering::SeqCst);
+            assert!(generated >= size, "generated {generated} pixels");
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), size);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "f32", ignore = "hashes are of 64-bit channels")]
+    fn fixed_seed_output() {
+        // FNV-1a, so that the expected hashes do not depend on std.
+        let hash = |bytes: &[u8]| {
+            bytes.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+        };
+        // Regression test: these must not change for a fixed seed.
+        let cases: [(&[&str], u64); 3] = [
+            (&["-w1"], 0xb491b966ab334365),
+            (&["-w1", "-C5", "-e3"], 0x6422c87dfe5f0bf8),
+            (&["-w3", "-C5", "-e3"], 0x6422c87dfe5f0bf8),
+        ];
+        for (args, expected) in cases {
+            let args: Vec<_> =
+                ["-s40x30", "-S9"].iter().chain(args).copied().collect();
+            let common_data = run_generator(&args);
+            let mut output = vec![];
+            let locked = common_data.locked.read().unwrap();
+            locked.write_image(&mut output).unwrap();
+            assert_eq!(hash(&output), expected, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn fitness_kinds() {
+        let mut outputs = vec![];
+        for kind in ["sumsq", "abs", "maxchannel", "luma"] {
+            let fitness = format!("--fitness={kind}");
+            let mut output = None;
+            // Every kind must pick the same places with one or more workers.
+            for workers in ["-w1", "-w3"] {
+                let args = ["-s40x30", "-S9", "-C5", "-e3", workers, &fitness];
+                let common_data = run_generator(&args);
+                let mut image = vec![];
+                let locked = common_data.locked.read().unwrap();
+                locked.write_image(&mut image).unwrap();
+                match &output {
+                    None => output = Some(image),
+                    Some(output) => assert!(*output == image, "{args:?}"),
+                }
+            }
+            outputs.push(output.unwrap());
+        }
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                assert!(a != b);
+            }
+        }
+    }
+
+    #[test]
+    fn neighborhood() {
+        let mut outputs = vec![];
+        for args in [
+            &["-w1", "--neighborhood"][..],
+            &["-w3", "--neighborhood"],
+            &["-w3", "--neighborhood", "--fitness=abs"],
+            &["-w1"],
+        ] {
+            let args: Vec<_> = ["-s40x30", "-S9", "-C5", "-e3"]
+                .iter()
+                .chain(args)
+                .copied()
+                .collect();
+            let common_data = run_generator(&args);
+            let size = common_data.size.get();
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), size);
+            let mut output = vec![];
+            locked.write_image(&mut output).unwrap();
+            outputs.push(output);
+        }
+        // The same with any number of workers, and it composes with
+        // `--fitness`.
+        assert!(outputs[0] == outputs[1]);
+        assert!(outputs[1] != outputs[2]);
+        assert!(outputs[0] != outputs[3]);
+    }
+
+    #[test]
+    fn seedat() {
+        let red = from_3(1.0, 0.0, 0.0);
+        for generator in ["--outer", "--smartplace"] {
+            let common_data = run_generator(&[
+                "-s8x6",
+                "--color255",
+                "--seedat=2,3:255,0,0",
+                "--seedat=7,5",
+                generator,
+            ]);
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), 48);
+            assert_eq!(locked.image[(3, 2)], red);
+        }
+
+        // `--seeds` only counts the random seeds, and there are none by
+        // default with `--seedat`.
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        for (args, seeds) in [
+            (&["--seedat=1,1"][..], "seeds: 0, "),
+            (&["--seedat=1,1", "-e2"], "seeds: 2, "),
+            (&[], "seeds: 1, "),
+        ] {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let dim = NonZeroUsize::new(4).unwrap();
+            let generator = super::handle_opts(&opts, dim, dim).unwrap();
+            assert!(format!("{generator:?}").contains(seeds), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn seedpattern() {
+        use super::{Pixel, SeedPattern};
+
+        let dim = |n| NonZeroUsize::new(n).unwrap();
+        let pixels = |locations: &[(i32, i32)]| -> Vec<Pixel> {
+            locations.iter().map(|&(x, y)| Pixel { x, y }).collect()
+        };
+        let grid: SeedPattern = "grid:4x4".parse().unwrap();
+        let locations = grid.locations(16, dim(16), dim(8));
+        assert_eq!(locations.len(), 16);
+        let first_row = pixels(&[(2, 1), (6, 1), (10, 1), (14, 1)]);
+        assert_eq!(locations[..4], first_row);
+        assert_eq!(locations[15], Pixel { x: 14, y: 7 });
+        let corners = SeedPattern::Corners.locations(9, dim(5), dim(3));
+        assert_eq!(corners, pixels(&[(0, 0), (4, 0), (4, 2), (0, 2)]));
+        let border = SeedPattern::Border.locations(4, dim(4), dim(4));
+        assert_eq!(border, pixels(&[(0, 0), (3, 0), (3, 3), (0, 3)]));
+        // Patterns with more locations than pixels only use each pixel once.
+        assert_eq!(grid.locations(16, dim(2), dim(1)).len(), 2);
+        assert_eq!(SeedPattern::Corners.locations(4, dim(1), dim(1)).len(), 1);
+
+        // Seeds beyond the pattern's capacity are placed randomly.
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        for (args, seeds) in [
+            (&["--seedpattern=corners"][..], "seeds: 0, "),
+            (&["--seedpattern=corners", "-e6"], "seeds: 2, "),
+            (&["--seedpattern=center", "--seedat=1,1"], "seeds: 0, "),
+            (&["--seedpattern=random", "-e3"], "seeds: 3, "),
+        ] {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let generator = super::handle_opts(&opts, dim(4), dim(4)).unwrap();
+            assert!(format!("{generator:?}").contains(seeds), "{args:?}");
+        }
+
+        let common_data = run_generator(&["-s16x8", "--seedpattern=grid:4x4"]);
+        assert!(common_data.finished.load(Ordering::SeqCst));
+        let locked = common_data.locked.read().unwrap();
+        assert_eq!(locked.placed_pixels.count_ones(), 128);
+    }
+
+    #[test]
+    fn worker_colors() {
+        let mut outputs = vec![];
+        for args in [["-w3", "-C5"], ["-w3", "-C5"], ["-w4", "-C2"]] {
+            let args = [&["-s32x24", "-S9", "--worker-colors"], &args[..]];
+            let common_data = run_generator(&args.concat());
+            let size = common_data.size.get();
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), size);
+            let mut output = vec![];
+            locked.write_image(&mut output).unwrap();
+            outputs.push(output);
+        }
+        // Deterministic for a fixed seed, even with more workers than colors.
+        assert_eq!(outputs[0], outputs[1]);
+
+        // Including when the vectorsets are used in turn, which each worker
+        // does for its own colors.
+        let args = [
+            "-s32x24",
+            "-S9",
+            "--hues",
+            "--vectorsetorder=cycle",
+            "-w4",
+            "--worker-colors",
+            "-C8",
+        ];
+        let outputs = (0..3)
+            .map(|_| {
+                let common_data = run_generator(&args);
+                let mut output = vec![];
+                let locked = common_data.locked.read().unwrap();
+                locked.write_image(&mut output).unwrap();
+                output
+            })
+            .collect::<Vec<_>>();
+        assert!(outputs[0] == outputs[1] && outputs[1] == outputs[2]);
+    }
+
+    #[test]
+    fn maxfitness() {
+        // Nothing fits better than 0, so only the seed is ever placed.
+        for workers in ["1", "2"] {
+            let args = ["-s4x4", "--maxfitness=0", "-C4", "-w", workers];
+            let common_data = run_generator_with(&args, |data| {
+                let interrupted = data.interrupted.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    interrupted.store(true, Ordering::SeqCst);
+                });
+            });
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), 1);
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), 1, "-w{workers}");
+        }
+    }
+
+    #[test]
+    fn invert_fitness_after() {
+        use super::{maximizing, update_best_place, BestPlaces};
+
+        let candidates = [(0, 0.5), (1, 0.25), (2, 0.75), (3, 0.5)];
+        let best = |placed, maxfitness| {
+            let maximize = maximizing(Some(10), placed);
+            let mut best = BestPlaces::default();
+            for (x, fitness) in candidates {
+                let place = (Pixel { x, y: 0 }, fitness);
+                update_best_place(&mut best, place, maxfitness, maximize, 1);
+            }
+            best.best().map(|(pixel, _)| pixel.x)
+        };
+        // The best fit before the threshold, and the worst from it on, but
+        // never one rejected by --maxfitness.
+        assert_eq!(best(0, None), Some(1));
+        assert_eq!(best(9, None), Some(1));
+        assert_eq!(best(10, None), Some(2));
+        assert_eq!(best(11, Some(0.75)), Some(3));
+        assert!(!maximizing(None, usize::MAX));
+
+        // The same image for any number of workers, but not the usual one.
+        let args = ["-s40x30", "-S9", "-C5"];
+        let outputs = ["", "-w1", "-w3"].map(|workers| {
+            let common_data = match workers {
+                "" => run_generator(&args),
+                _ => run_generator(
+                    &[&args[..], &["--invert-fitness-after=300", workers]]
+                        .concat(),
+                ),
+            };
+            let size = common_data.size.get();
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+            let mut output = vec![];
+            let locked = common_data.locked.read().unwrap();
+            locked.write_image(&mut output).unwrap();
+            output
+        });
+        assert!(outputs[1] == outputs[2]);
+        assert!(outputs[0] != outputs[1]);
+    }
+
+    #[test]
+    fn stop_conditions() {
+        // Each iteration places at most `-C` pixels, so generation stops
+        // within one iteration of `--maxpixels`.
+        let generators: [&[&str]; 4] =
+            [&["-w1"], &["-w2"], &["--outer"], &["--scanline"]];
+        for generator in generators {
+            let args = [&["-s32x24", "--maxpixels=100"], generator].concat();
+            let args = match generator {
+                ["--scanline"] => args,
+                _ => [&args[..], &["-C3"]].concat(),
+            };
+            let common_data = run_generator(&args);
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            let placed = common_data.pixels_placed.load(Ordering::SeqCst);
+            // Scanline places a row of 32 at a time.
+            assert!((100..132).contains(&placed), "{generator:?}: {placed}");
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), placed);
+        }
+
+        // Nothing fits better than 0, so only `--maxseconds` can stop it.
+        for workers in ["-w1", "-w2"] {
+            let args =
+                ["-s4x4", "--maxfitness=0", "--maxseconds=0.05", workers];
+            let common_data = run_generator(&args);
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn mutate() {
+        // Every pixel but the seed was made from a neighbor's color.
+        let args = ["-s32x24", "-S5", "--mutate=0.05"];
+        let common_data = run_generator(&args);
+        assert!(common_data.finished.load(Ordering::SeqCst));
+        let size = common_data.size.get();
+        assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+        let locked = common_data.locked.read().unwrap();
+        let (dimy, dimx) = (common_data.dimy, common_data.dimx);
+        let mut near_neighbor = 0;
+        for y in 0..dimy.get() {
+            for x in 0..dimx.get() {
+                let color = locked.image[(y, x)];
+                let pixel = Pixel { x: x as _, y: y as _ };
+                let near = NORMAL_OFFSETS.iter().any(|&offset| {
+                    let neighbor = super::in_bounds(dimy, dimx, pixel + offset);
+                    neighbor.is_some_and(|neighbor| {
+                        let difference = locked.image[neighbor] - color;
+                        difference.abs().reduce_max() <= 0.05 + 1e-6
+                    })
+                });
+                near_neighbor += near as usize;
+            }
+        }
+        assert_eq!(near_neighbor, size);
+
+        // Without mutation, every pixel is the seed's color.
+        let common_data = run_generator(&["-s8x8", "-S5", "--mutate=0"]);
+        let locked = common_data.locked.read().unwrap();
+        let seed = locked.image[(0, 0)];
+        assert!(locked.image.rawdata.iter().all(|&color| color == seed));
+    }
+
+    #[test]
+    fn distinct_places() {
+        use super::{update_best_place, BestPlaces};
+
+        // Ties go to the later place, and only the best `limit` are kept.
+        let places = [(0, 0.5), (1, 0.25), (2, 0.75), (3, 0.5), (4, 0.25)];
+        let mut best = BestPlaces::default();
+        for (x, fitness) in places {
+            let place = (Pixel { x, y: 0 }, fitness);
+            update_best_place(&mut best, place, None, false, 3);
+        }
+        let xs = best.0.iter().map(|(pixel, _)| pixel.x).collect::<Vec<_>>();
+        assert_eq!(xs, [4, 1, 3]);
+
+        // Each of the 8 colors in the first batch goes next to a different
+        // one of the 8 seeds, so every seed ends up with one neighbor.
+        for workers in ["-w1", "-w2"] {
+            let args = [
+                "-s32x16",
+                "--seedpattern=grid:4x2",
+                "-C8",
+                "--maxpixels=9",
+                workers,
+            ];
+            let common_data = run_generator(&args);
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), 16);
+            let locked = common_data.locked.read().unwrap();
+            let (dimy, dimx) = (common_data.dimy, common_data.dimx);
+            let mut seen = BitMap::new(dimy.get(), dimx.get()).unwrap();
+            let mut sizes = vec![];
+            let pixels = (0..dimy.get())
+                .flat_map(|y| (0..dimx.get()).map(move |x| (y, x)));
+            for (y, x) in pixels {
+                if !locked.placed_pixels.get((y, x)) || seen.get((y, x)) {
+                    continue;
+                }
+                // The size of this group of placed pixels.
+                seen.set((y, x), true);
+                let mut stack = vec![Pixel { x: x as _, y: y as _ }];
+                let mut size = 0;
+                while let Some(pixel) = stack.pop() {
+                    size += 1;
+                    for &offset in NORMAL_OFFSETS {
+                        let Some(neighbor) =
+                            super::in_bounds(dimy, dimx, pixel + offset)
+                        else {
+                            continue;
+                        };
+                        if locked.placed_pixels.get(neighbor)
+                            && !seen.get(neighbor)
+                        {
+                            seen.set(neighbor, true);
+                            let (y, x) = neighbor;
+                            stack.push(Pixel { x: x as _, y: y as _ });
+                        }
+                    }
+                }
+                sizes.push(size);
+            }
+            assert_eq!(sizes, [2; 8], "{workers}");
+        }
+    }
+
+    #[test]
+    fn mask() {
+        // A ring, with an island in the middle, that the generator has to
+        // reseed to reach.
+        let (dimx, dimy) = (24, 16);
+        let inside = |y: usize, x: usize| {
+            let (dx, dy) = (x.abs_diff(12), y.abs_diff(8));
+            let distance = dx * dx + dy * dy;
+            distance <= 4 || (16..=49).contains(&distance)
+        };
+        let mut pbm = format!("P1\n{dimx} {dimy}\n");
+        for y in 0..dimy {
+            for x in 0..dimx {
+                pbm += if inside(y, x) { "0" } else { "1" };
+            }
+            pbm += "\n";
+        }
+        let path = std::env::temp_dir().join(format!(
+            "imagegen-mask-test-{}.pbm",
+            std::process::id()
+        ));
+        std::fs::write(&path, pbm).unwrap();
+        let mask = format!("--mask={}", path.display());
+        let unmasked = (0..dimy)
+            .flat_map(|y| (0..dimx).filter(move |&x| inside(y, x)))
+            .count();
+
+        let background = from_3(0.0, 0.0, 1.0);
+        let mut outputs = vec![];
+        for workers in ["-w1", "-w3"] {
+            let args = [
+                "-s24x16", "-S3", "-C3", "--seedat=0,0", "--background=0,0,1",
+                &mask, workers,
+            ];
+            let common_data = run_generator(&args);
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.size.get(), unmasked);
+            let placed = common_data.pixels_placed.load(Ordering::SeqCst);
+            assert_eq!(placed, unmasked);
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), dimx * dimy);
+            for y in 0..dimy {
+                for x in 0..dimx {
+                    let color = locked.image[(y, x)];
+                    assert_eq!(color == background, !inside(y, x), "{x},{y}");
+                }
+            }
+            let mut output = vec![];
+            locked.write_image(&mut output).unwrap();
+            outputs.push(output);
+        }
+        assert!(outputs[0] == outputs[1]);
+        std::fs::remove_file(&path).unwrap();
+
+        // Generators and options that compare colors to placed pixels.
+        let getopt = Getopt::from_iter(
+            crate::setup::opts().into_iter().chain(super::opts()),
+        )
+        .unwrap();
+        #[rustfmt::skip]
+        let conflicts = [
+            ("--scanline", "--scanline cannot be used together with --mask"),
+            ("--outer", "--outer cannot be used together with --mask"),
+            ("--smartplace", "--mask cannot be used together with --smartplace"),
+            ("--neighborhood", "--mask cannot be used together with --neighborhood"),
+        ];
+        for (other, expected) in conflicts {
+            let opts = getopt
+                .parse(["--mask=mask.pbm", other])
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let dim = NonZeroUsize::new(16).unwrap();
+            let err = super::handle_opts(&opts, dim, dim).err().unwrap();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn outer() {
+        let mut outputs = vec![];
+        for workers in ["-w1", "-w3"] {
+            let args = ["-s32x24", "-S4", "--outer", "-e3", "-C4", workers];
+            let common_data = run_generator(&args);
+            let size = common_data.size.get();
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), size);
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), size);
+            assert!(locked.edges.is_empty());
+            let mut output = vec![];
+            locked.write_image(&mut output).unwrap();
+            outputs.push(output);
+        }
+        // Ties between workers go to the first edge, like with one worker.
+        assert!(outputs[0] == outputs[1]);
+    }
+
+    /// Gives each color its index in the order they were generated.
+    #[derive(Debug, Default)]
+    struct CountingColors;
+
+    impl ColorGenerator for CountingColors {
+        fn new_color(
+            &self,
+            _rng: &mut dyn rand::RngCore,
+            index: usize,
+        ) -> Color {
+            from_3(index as Channel, 0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn scanline() {
+        let common_data = run_generator_with_colors(
+            &["-s7x5", "--scanline"],
+            Some(Box::<CountingColors>::default()),
+            |_| {},
+        );
+        assert!(common_data.finished.load(Ordering::SeqCst));
+        assert_eq!(common_data.pixels_placed.load(Ordering::SeqCst), 35);
+        assert_eq!(common_data.pixels_generated.load(Ordering::SeqCst), 35);
+        let locked = common_data.locked.read().unwrap();
+        assert_eq!(locked.placed_pixels.count_ones(), 35);
+        for y in 0..5 {
+            for x in 0..7 {
+                let index = (y * 7 + x) as Channel;
+                assert_eq!(locked.image[(y, x)], from_3(index, 0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn interrupted() {
+        for workers in ["1", "2"] {
+            let common_data =
+                run_generator_with(&["-s512x512", "-w", workers], |data| {
+                    let interrupted = data.interrupted.clone();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        interrupted.store(true, Ordering::SeqCst);
+                    });
+                });
+            assert!(common_data.finished.load(Ordering::SeqCst));
+            let placed = common_data.pixels_placed.load(Ordering::SeqCst);
+            assert!(placed < common_data.size.get());
+
+            let locked = common_data.locked.read().unwrap();
+            assert_eq!(locked.placed_pixels.count_ones(), placed);
+            let mut output = vec![];
+            locked.write_image(&mut output).unwrap();
+            let header = format!(
+                "P6\n# seed: {}\n512 512\n255\n",
+                common_data.rng_seed
+            );
+            assert!(output.starts_with(header.as_bytes()));
+            assert_eq!(output.len(), header.len() + 512 * 512 * 3);
+        }
+    }
 }
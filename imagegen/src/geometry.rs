@@ -2,17 +2,18 @@ use std::{num::NonZeroUsize, sync::Arc};
 
 use getopt::{GetoptItem, Opt};
 
-use crate::{generate::Pixel, CommonData};
+use crate::{error::ConfigError, generate::Pixel};
 
 pub struct CanonicalPixel {
     pub x: usize,
     pub y: usize,
 }
 
-pub trait Geometry {
+pub trait Geometry: std::fmt::Debug {
     fn canonicalize(&self, location: Pixel) -> Option<CanonicalPixel>;
 }
 
+#[derive(Debug)]
 struct NSWrappingGeometry<const NS_WRAP: bool, const EW_WRAP: bool> {
     /// Must be <= isize::MAX
     dimx: NonZeroUsize,
@@ -20,6 +21,7 @@ struct NSWrappingGeometry<const NS_WRAP: bool, const EW_WRAP: bool> {
     dimy: NonZeroUsize,
 }
 
+#[derive(Debug)]
 struct NEWrappingGeometry<const NE_WRAP: bool, const SW_WRAP: bool> {
     /// Must be <= isize::MAX
     dim: NonZeroUsize,
@@ -72,8 +74,9 @@ pub fn opts() -> impl IntoIterator<Item = Opt> {
 
 pub fn handle_opts(
     opts: &[GetoptItem<'_>],
-    common_data: &CommonData,
-) -> Arc<dyn Geometry + Send + Sync> {
+    dimx: NonZeroUsize,
+    dimy: NonZeroUsize,
+) -> Result<Arc<dyn Geometry + Send + Sync>, ConfigError> {
     #[cfg(any())]
     {
         let mut size = (None, None);
@@ -171,5 +174,5 @@ pub fn handle_opts(
 
         (data, rng)
     }
-    Arc::new(NormalGeometry { dimx: common_data.dimx, dimy: common_data.dimy })
+    Ok(Arc::new(NormalGeometry { dimx, dimy }))
 }
@@ -2,37 +2,87 @@
 #![deny(rust_2018_idioms)]
 
 use std::{
+    borrow::Cow,
     collections::VecDeque,
+    io::{IsTerminal, Write},
     num::NonZeroUsize,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, AtomicUsize},
-        Arc, Barrier, RwLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Barrier, Mutex, Once, PoisonError, RwLock,
     },
+    time::Duration,
 };
 
 mod color;
+mod config;
+mod error;
 mod generate;
 mod geometry;
+mod output;
 mod pnmdata;
 mod progress;
 mod setup;
 
 use bitmap::BitMap;
-use generate::Pixel;
-use getopt::Getopt;
-use pnmdata::PnmData;
+use color::{Color, ColorGenerator};
+use generate::{Generator, Pixel};
+use geometry::Geometry;
+use getopt::{Getopt, GetoptItem, GetoptIter};
+use output::RunIndex;
+use pnmdata::{OutputConfig, PnmData};
+use progress::{ProgressData, ProgressSettings, Progressor};
+use rand::RngCore;
+use setup::SetupSettings;
 
-use crate::generate::GeneratorData;
+use crate::{
+    error::{ConfigError, RunError},
+    generate::GeneratorData,
+};
 
 pub struct CommonLockedData {
     //geometry: Arc<dyn Geometry>,
     image: PnmData,
     placed_pixels: BitMap,
     /// Represents to-be-placed pixels
+    ///
+    /// Never has duplicates: the inner generator only adds pixels as they are
+    /// placed (and drops them in `validate_edges_near` once surrounded), and
+    /// the outer generator tracks what it has queued.
     edges: VecDeque<Pixel>,
-    // TODO:
-    // Pixels placed since the last iteration. Can be used to optimize
-    // progressors recently_placed: VecDeque<Pixel>,
+    /// Pixels placed since the generator last passed barrier b, as
+    /// `(row, col)`, for updating `progress::ImageSnapshot`s.
+    ///
+    /// The supervisor reads this between barriers a and b, so the generator
+    /// clears it when it next writes after barrier b.
+    recently_placed: Vec<(usize, usize)>,
+}
+
+impl CommonLockedData {
+    /// Places `color` at `(y, x)`, and records it in `recently_placed`.
+    fn place(&mut self, (y, x): (usize, usize), color: Color) {
+        self.image[(y, x)] = color;
+        self.placed_pixels.set((y, x), true);
+        self.recently_placed.push((y, x));
+    }
+
+    /// Writes the image with its own output settings, showing unplaced pixels
+    /// in the background color.
+    pub fn write_image<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        self.write_image_as(writer, &self.image.output)
+    }
+
+    /// Like `write_image`, but with `config` instead of the image's own
+    /// output settings.
+    pub fn write_image_as<W: Write>(
+        &self,
+        writer: W,
+        config: &OutputConfig,
+    ) -> std::io::Result<()> {
+        self.image.write_to(writer, config, Some(&self.placed_pixels))
+    }
 }
 
 pub struct CommonData {
@@ -41,12 +91,108 @@ pub struct CommonData {
     pub dimx: NonZeroUsize,
     pub size: NonZeroUsize,
     pub progress_barrier: Barrier,
+    /// How many times the generator has waited at `progress_barrier`, so that
+    /// it can be taken over if the generator panics. Odd between barriers a
+    /// and b.
+    pub generator_barrier_waits: AtomicUsize,
+    /// Set when no progressor runs (e.g. with `--bench`), so that the
+    /// generator does not wait at `progress_barrier` at all.
+    pub no_progressor: bool,
+    /// Set for `--single-thread`, where the progressors run on the
+    /// generator's thread, so that instead of waiting at `progress_barrier`
+    /// the generator runs them up to their next barrier (see
+    /// `progress::step`).
+    pub single_thread: bool,
+    /// Set by the generator (or the progress supervisor) when generation is
+    /// over. It may only change while no progressor is between barriers a and
+    /// b (see `interrupted`).
+    ///
+    /// Ordering: the barrier waits already order every store before the loads
+    /// that act on it, since it is only read after waiting at barrier a. Stores
+    /// use `Release` and loads `Acquire` anyway, so that a thread that sees
+    /// `finished` (e.g. a worker checking it outside the barriers) also sees
+    /// the final image and counters.
     pub finished: AtomicBool,
+    /// Set (e.g. by the SIGINT handler) to request that generation stop early.
+    ///
+    /// Unlike `finished`, this may be set at any time from any thread.
+    /// `finished` may only change while no progressor is between barriers a
+    /// and b (otherwise the generator and progressors could disagree about
+    /// whether to wait at barrier b, and deadlock), so the generator checks
+    /// this at the start of each iteration and sets `finished` itself.
+    ///
+    /// This is in its own `Arc` so that it can be shared with the signal
+    /// handler without preventing `main` from getting unique access to the
+    /// `CommonData` at the end.
+    pub interrupted: Arc<AtomicBool>,
+    /// Set by the `--timeout` watchdog. Like `interrupted`, but only for this
+    /// run, and it does not stop any later `--count` runs.
+    pub timed_out: AtomicBool,
+    /// The first error a progressor failed with (see
+    /// `ProgressSupervisorData::fail`), returned once the image is written.
+    pub progress_error: Mutex<Option<RunError>>,
+    /// Only changed by the generator thread, and only read elsewhere for
+    /// progress reports, which do not need an exact count, and after the
+    /// generator has been joined, which synchronizes. So every access is
+    /// `Relaxed`; the generator always sees its own latest value.
     pub pixels_placed: AtomicUsize,
+    /// Like `pixels_placed`.
     pub pixels_generated: AtomicUsize,
     pub rng_seed: u64,
 }
 
+impl CommonData {
+    /// Whether generation should stop early, because of `interrupted` or
+    /// `timed_out`. The generator checks this at the start of each iteration
+    /// and sets `finished` itself.
+    pub fn stop_early(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+            || self.timed_out.load(Ordering::SeqCst)
+    }
+
+    /// Waits at `progress_barrier` on the generator's side.
+    pub fn generator_barrier_wait(&self) {
+        if self.no_progressor {
+            return;
+        }
+        if self.single_thread {
+            progress::step(self);
+        } else {
+            self.progress_barrier.wait();
+        }
+        self.generator_barrier_waits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Takes the place of a generator that panicked at the barriers until the
+    /// progressors have finished the current iteration and seen `finished`.
+    fn stand_in_for_generator(&self) {
+        if self.no_progressor {
+            return;
+        }
+        if self.generator_barrier_waits.load(Ordering::SeqCst) % 2 == 1 {
+            // If the generator already set `finished` and passed barrier a,
+            // the progressors are already exiting.
+            if self.finished.load(Ordering::Acquire) {
+                return;
+            }
+            self.progress_barrier.wait();
+        }
+        self.finished.store(true, Ordering::Release);
+        self.progress_barrier.wait();
+    }
+}
+
+/// The message of a panic, if it has one.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
 macro_rules! chain {
     ( $iter:expr $(,)? ) => {
         $iter.into_iter()
@@ -59,7 +205,6 @@ macro_rules! chain {
 fn main() {
     env_logger::builder()
         .format(|f, record| {
-            use std::io::Write;
             let tid = std::thread::current().id();
             let color = match record.level() {
                 log::Level::Error => "31;1",
@@ -85,59 +230,1131 @@ fn main() {
         })
         .init();
 
-    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let getopt = getopt();
+    let command = shell_command(getopt::env_args().iter().copied());
+    match run_all(
+        &getopt,
+        getopt.parse_env(),
+        &command,
+        &interrupted,
+        install_interrupt_handler,
+    ) {
+        Ok(()) => {}
+        Err(RunError::Config(err)) => config_error(err),
+        Err(err @ RunError::Panicked { .. }) => {
+            eprintln!("error: {err}");
+            std::process::exit(3);
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+    }
+
+    if interrupted.load(Ordering::SeqCst) {
+        // Conventional exit status for a process stopped by SIGINT.
+        std::process::exit(130);
+    }
+}
 
-    let getopt = Getopt::from_iter(chain!(
+fn getopt() -> Getopt {
+    Getopt::from_iter(chain!(
+        output::opts(),
         setup::opts(),
         geometry::opts(),
         generate::opts(),
         color::opts(),
         progress::opts(),
+        config::opts(),
     ))
-    .unwrap();
-
-    let opts = getopt
-        .parse(args.iter().map(String::as_str))
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
-
-    let (mut common_data, mut rng) = setup::handle_opts(&opts);
-    let mut generator = generate::handle_opts(&opts);
-    let color_generator = color::handle_opts(&opts);
-    log::trace!("color_generator: {:?}", color_generator);
-    let (progressor, progress_data) = progress::handle_opts(&opts);
-    let geometry = geometry::handle_opts(&opts, &common_data);
-    // TODO: put geometry in common_data, maybe by having setup::handle_opts
-    // cann geometry::handle_opts
-
-    let _gen_thread = std::thread::spawn({
-        let common_data = common_data.clone();
-        move || {
-            let data = GeneratorData {};
-            generator.generate(data, common_data, &*color_generator, &mut rng);
+    .unwrap()
+}
+
+/// Parses `cli_args` and generates (and writes) every image they ask for.
+/// `command` is the shell command they came from, for provenance comments.
+/// `install_handler` is called with `interrupted` once each run's
+/// `CommonData` exists (see `install_interrupt_handler`).
+fn run_all<'a>(
+    getopt: &'a Getopt,
+    cli_args: GetoptIter<'a, impl Iterator<Item = &'a str>>,
+    command: &str,
+    interrupted: &Arc<AtomicBool>,
+    install_handler: fn(&Arc<AtomicBool>),
+) -> Result<(), RunError> {
+    let cli_opts = options_only(cli_args)?;
+    let config = config::handle_opts(&cli_opts)?;
+    let config_opts =
+        options_only(getopt.parse(config.args.iter().map(String::as_str)))?;
+    let opts = config::merge(config_opts, cli_opts);
+
+    let output = output::handle_opts(&opts)?;
+    if !config.dump && !output.dry_run {
+        output.check_stdout(|| std::io::stdout().is_terminal())?;
+    }
+    // Runs after the first use consecutive seeds after the first run's seed.
+    let mut base_seed = None;
+    for index in 0..output.count.get() {
+        let run_index = RunIndex { index, count: output.count };
+        let seed = base_seed.map(|seed: u64| seed.wrapping_add(index as u64));
+        let settings = RunSettings::parse(&opts, run_index, seed)?;
+        base_seed.get_or_insert(settings.setup.seed);
+
+        if config.dump {
+            print!("{}", config::dump(&opts, settings.setup.seed));
+            return Ok(());
         }
-    });
 
-    let _prog_thread = std::thread::spawn({
-        let common_data = common_data.clone();
-        move || {
-            progressor.run_alone(progress_data, common_data);
+        let paths = output
+            .paths
+            .iter()
+            .map(|path| path.as_deref().map(|path| run_index.path(path)))
+            .collect::<Vec<_>>();
+        if output.dry_run {
+            print!("{}", settings.summary(&paths));
+            continue;
+        }
+
+        let run = Run::configure(settings, command, interrupted)?;
+        install_handler(interrupted);
+        run.generate_to(&paths, output.timeout, output.single_thread)?;
+
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Collects the options parsed by `args`. imagegen has no positional
+/// arguments, so any non-option argument is an error (usually a forgotten
+/// `-o`).
+fn options_only<'a>(
+    args: GetoptIter<'a, impl Iterator<Item = &'a str>>,
+) -> Result<Vec<GetoptItem<'a>>, ConfigError> {
+    let (opts, non_opts) = args.partitioned()?;
+    match non_opts.first() {
+        Some(arg) => Err(ConfigError::UnexpectedArgument(arg.to_string())),
+        None => Ok(opts),
+    }
+}
+
+/// Formats `args` as a shell command that runs `imagegen` with them, so that
+/// it can be copied to reproduce a run.
+fn shell_command<'a>(args: impl IntoIterator<Item = &'a str>) -> String {
+    std::iter::once("imagegen")
+        .chain(args)
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes `arg` for a POSIX shell, if it needs to be.
+fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// Writes the image in `locked` to the file at `path`, in the format for its
+/// extension, or to stdout if `path` is `None`.
+fn write_image(
+    locked: &CommonLockedData,
+    path: Option<&Path>,
+) -> Result<(), RunError> {
+    let result = match path {
+        Some(path) => std::fs::File::create(path).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            let config = locked.image.output.for_path(path);
+            locked.write_image_as(&mut writer, &config)?;
+            writer.flush()
+        }),
+        None => locked.write_image(&mut std::io::stdout().lock()),
+    };
+    result.map_err(|source| RunError::Output {
+        destination: path
+            .map_or_else(|| "stdout".to_string(), |path| format!("{path:?}")),
+        source,
+    })
+}
+
+/// The validated settings for a single run, before the image is allocated or
+/// any progress file is opened.
+struct RunSettings {
+    index: RunIndex,
+    setup: SetupSettings,
+    generator: Box<dyn Generator + Send>,
+    color_generator: Box<dyn ColorGenerator + Send>,
+    geometry: Arc<dyn Geometry + Send + Sync>,
+    progress: ProgressSettings,
+}
+
+impl RunSettings {
+    /// Parses the settings for run `index` from `opts`. If `seed` is given,
+    /// it overrides the `--seed` option.
+    fn parse(
+        opts: &[GetoptItem<'_>],
+        index: RunIndex,
+        seed: Option<u64>,
+    ) -> Result<Self, ConfigError> {
+        let setup = setup::handle_opts(opts, seed)?;
+        let generator = generate::handle_opts(opts, setup.dimx, setup.dimy)?;
+        let color_generator = color::handle_opts(opts)?;
+        log::trace!("color_generator: {:?}", color_generator);
+        let progress = progress::handle_opts(opts, index)?;
+        let geometry = geometry::handle_opts(opts, setup.dimx, setup.dimy)?;
+        Ok(Self {
+            index,
+            setup,
+            generator,
+            color_generator,
+            geometry,
+            progress,
+        })
+    }
+
+    /// Summarizes the settings for `--dry-run`, one `key: value` line each.
+    fn summary(&self, paths: &[Option<PathBuf>]) -> String {
+        let RunSettings {
+            index,
+            setup,
+            generator,
+            color_generator,
+            geometry,
+            progress,
+        } = self;
+        let mut summary = String::new();
+        if let Some(label) = index.label() {
+            summary += &format!("[{label}]\n");
+        }
+        summary += &format!(
+            "dimensions: {}x{} ({} pixels, ~{})\n",
+            setup.dimx,
+            setup.dimy,
+            setup.size,
+            setup::format_bytes(setup::estimate_memory(
+                setup.dimx.get(),
+                setup.dimy.get(),
+            )),
+        );
+        summary += &format!("seed: {}\n", setup.seed);
+        summary += &format!("background: {:?}\n", setup.background);
+        summary += &format!("generator: {generator:?}\n");
+        summary += &format!("color: {color_generator:?}\n");
+        summary += &format!("geometry: {geometry:?}\n");
+        let progressors = match &progress.progressors[..] {
+            [] if progress.bench => "none (bench)".to_string(),
+            [] => "Text (default)".to_string(),
+            progressors => progressors
+                .iter()
+                .map(|kind| format!("{kind:?}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        summary += &format!(
+            "progress: {progressors} (interval {}, count {})\n",
+            progress.data.progress_interval, progress.data.progress_count,
+        );
+        for path in paths {
+            let (destination, config) = match path {
+                Some(path) => {
+                    (path.display().to_string(), setup.output.for_path(path))
+                }
+                None => ("stdout".to_string(), setup.output),
+            };
+            summary += &format!("output: {destination} {config:?}\n");
+        }
+        summary
+    }
+}
+
+/// A single configured run of the generator and progressor.
+struct Run {
+    common_data: Arc<CommonData>,
+    rng: Box<dyn RngCore + Send>,
+    generator: Box<dyn Generator + Send>,
+    color_generator: Box<dyn ColorGenerator + Send>,
+    progressor: Box<dyn Progressor + Send>,
+    progress_data: ProgressData,
+}
+
+impl Run {
+    /// Allocates the image and creates the progressors for `settings`.
+    /// Unless `--no-provenance` is given, `command` is recorded in the image,
+    /// along with the seed, settings, and version.
+    fn configure(
+        settings: RunSettings,
+        command: &str,
+        interrupted: &Arc<AtomicBool>,
+    ) -> Result<Self, ConfigError> {
+        let RunSettings {
+            index,
+            setup,
+            generator,
+            color_generator,
+            geometry: _,
+            progress,
+        } = settings;
+        let (progressor, progress_data) = progress.build(index)?;
+        let provenance = setup.provenance;
+        let (mut common_data, rng) = setup.build();
+        let data = Arc::get_mut(&mut common_data).expect("just created");
+        data.interrupted = interrupted.clone();
+        data.no_progressor = progressor.is_noop();
+        if provenance {
+            let image = &mut data.locked.get_mut().unwrap().image;
+            image.add_comment(format!("size: {}x{}", data.dimx, data.dimy));
+            image.add_comment(format!("generator: {generator:?}"));
+            image.add_comment(format!("command: {command}"));
+            image.add_comment(format!(
+                "version: imagegen {}",
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        // TODO: put geometry in common_data, maybe by having setup::handle_opts
+        // cann geometry::handle_opts
+
+        Ok(Self {
+            common_data,
+            rng: Box::new(rng),
+            generator,
+            color_generator,
+            progressor,
+            progress_data,
+        })
+    }
+
+    /// Runs the generator and progressor to completion, and writes the image
+    /// to each of `paths` (see `write_image`).
+    ///
+    /// The image is written through `CommonData::locked`, so a progressor may
+    /// leave threads behind that still hold a reference to the `CommonData`.
+    ///
+    /// If either thread panics, the other is stopped, and the (incomplete)
+    /// image is still written, with a comment saying so, before returning
+    /// `RunError::Panicked`. Likewise if a progressor fails, but returning its
+    /// error.
+    ///
+    /// If `timeout` is given, generation is stopped (as if interrupted) once it
+    /// has passed, and the incomplete image is written with a comment saying
+    /// so.
+    ///
+    /// With `single_thread` (`--single-thread`), the generator and progressors
+    /// both run on this thread instead, for debugging. A panic in either is
+    /// reported as the `main` thread panicking.
+    fn generate_to(
+        self,
+        paths: &[Option<PathBuf>],
+        timeout: Option<Duration>,
+        single_thread: bool,
+    ) -> Result<(), RunError> {
+        let Run {
+            mut common_data,
+            mut rng,
+            mut generator,
+            color_generator,
+            progressor,
+            progress_data,
+        } = self;
+
+        Arc::get_mut(&mut common_data)
+            .expect("no other threads have started")
+            .single_thread = single_thread;
+
+        // Dropping `done_tx` once the run is over wakes the watchdog early.
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let watchdog = timeout.map(|timeout| {
+            let common_data = common_data.clone();
+            std::thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) =
+                    done_rx.recv_timeout(timeout)
+                {
+                    log::warn!("Timed out after {timeout:?}, stopping");
+                    common_data.timed_out.store(true, Ordering::SeqCst);
+                }
+            })
+        });
+
+        let joined = if single_thread {
+            if !common_data.no_progressor {
+                progress::start_stepped(
+                    &*progressor,
+                    progress_data,
+                    common_data.clone(),
+                );
+            }
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                generator.generate(
+                    GeneratorData {},
+                    common_data.clone(),
+                    &*color_generator,
+                    &mut rng,
+                );
+            }));
+            // The progressors hold a reference to `common_data`.
+            progress::stop_stepped();
+            vec![("main", result)]
+        } else {
+            let _gen_thread = std::thread::spawn({
+                let common_data = common_data.clone();
+                move || {
+                    let data = GeneratorData {};
+                    let result = catch_unwind(AssertUnwindSafe(|| {
+                        generator.generate(
+                            data,
+                            common_data.clone(),
+                            &*color_generator,
+                            &mut rng,
+                        );
+                    }));
+                    if let Err(payload) = result {
+                        common_data.stand_in_for_generator();
+                        resume_unwind(payload);
+                    }
+                }
+            });
+
+            // With no progressor, the generator skips the barriers, so nothing
+            // may wait at them.
+            let _prog_thread = (!common_data.no_progressor).then(|| {
+                std::thread::spawn({
+                    let common_data = common_data.clone();
+                    move || {
+                        progressor.run_alone(progress_data, common_data);
+                    }
+                })
+            });
+
+            vec![
+                ("generator", _gen_thread.join()),
+                (
+                    "progress",
+                    _prog_thread.map_or(Ok(()), |thread| thread.join()),
+                ),
+            ]
+        };
+        drop(done_tx);
+        if let Some(watchdog) = watchdog {
+            watchdog.join().expect("the watchdog does not panic");
+        }
+        // A progressor may have left a thread behind that still holds a
+        // reference to `common_data` (e.g. one waiting on a window event), so
+        // this goes through the lock instead of needing unique access. The
+        // generator has finished, so nothing else writes to it any more.
+        let timed_out = common_data.timed_out.load(Ordering::SeqCst)
+            && common_data.pixels_placed.load(Ordering::Relaxed)
+                < common_data.size.get();
+        let mut locked = common_data
+            .locked
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let (true, Some(timeout)) = (timed_out, timeout) {
+            let comment = format!("incomplete: timed out after {timeout:?}");
+            locked.image.add_comment(comment);
+        }
+        let mut result = Ok(());
+        for (thread, joined) in joined {
+            let Err(payload) = joined else { continue };
+            let message = panic_message(&*payload).to_string();
+            log::error!(
+                "The {thread} thread panicked ({message}), writing the incomplete image"
+            );
+            let comment = format!("incomplete: the {thread} thread panicked");
+            locked.image.add_comment(comment);
+            result = result.and(Err(RunError::Panicked { thread, message }));
+        }
+        let progress_error = common_data
+            .progress_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(err) = progress_error {
+            if common_data.pixels_placed.load(Ordering::Relaxed)
+                < common_data.size.get()
+            {
+                locked.image.add_comment(format!("incomplete: {err}"));
+            }
+            result = result.and(Err(err));
+        }
+        for path in paths {
+            write_image(&locked, path.as_deref())?;
+        }
+        result
+    }
+}
+
+/// Reports an invalid command-line configuration and exits with the
+/// conventional usage-error status.
+fn config_error(err: ConfigError) -> ! {
+    eprintln!("error: {err}");
+    std::process::exit(2);
+}
+
+/// Installs a SIGINT handler that sets `interrupted` (which is shared with
+/// each run's `CommonData::interrupted`), so that the generator and
+/// progressors shut down normally and the partial image is still written. A
+/// second SIGINT exits immediately.
+///
+/// Only the first call installs it. `run_all` calls this once a run's
+/// `CommonData` exists, so that until then (e.g. while parsing options, or
+/// with `--dry-run`) SIGINT still exits immediately, instead of leaving
+/// `interrupted` set for the first run to start with.
+///
+/// The generator checks `interrupted` at the start of each iteration, before
+/// barrier a, and it always reaches barrier a, so setting the flag is enough
+/// to stop every thread waiting at a barrier (see `CommonData::interrupted`).
+fn install_interrupt_handler(interrupted: &Arc<AtomicBool>) {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let interrupted = interrupted.clone();
+        let result = ctrlc::set_handler(move || {
+            if interrupted.swap(true, Ordering::SeqCst) {
+                log::warn!("Interrupted again, exiting immediately");
+                std::process::exit(130);
+            }
+            log::warn!(
+                "Interrupted, finishing the current iteration (interrupt again to exit immediately)"
+            );
+        });
+        if let Err(err) = result {
+            log::error!("Failed to install SIGINT handler: {err}");
         }
     });
+}
 
-    _gen_thread.join().unwrap();
-    _prog_thread.join().unwrap();
-
-    let locked = Arc::get_mut(&mut common_data)
-        .expect("all other threads have exited")
-        .locked
-        .get_mut()
-        .unwrap();
-    // TODO: output file
-    locked.image.write_to(&mut std::io::stdout().lock()).unwrap_or_else(
-        |err| {
-            // TODO: better error handling (everywhere)
-            panic!("Failed to write output image: {err:?}");
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
         },
-    );
+    };
+
+    use super::{ConfigError, RunError};
+
+    /// Runs `imagegen` as if it was given `args` on the command line.
+    fn run_all(
+        args: &[impl AsRef<str>],
+        interrupted: &Arc<AtomicBool>,
+    ) -> Result<(), RunError> {
+        let getopt = super::getopt();
+        let args = args.iter().map(AsRef::as_ref);
+        let command = super::shell_command(args.clone());
+        // Without a SIGINT handler, so that tests can still be interrupted.
+        super::run_all(
+            &getopt,
+            getopt.parse(args),
+            &command,
+            interrupted,
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn count() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-count-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let args =
+            ["-s4x3", "-S7", "--count", "3", "-o", output.to_str().unwrap()]
+                .map(String::from);
+
+        run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        let images = ["out-001.ppm", "out-002.ppm", "out-003.ppm"]
+            .map(|name| std::fs::read(dir.join(name)).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let [dimx, dimy] =
+            [4, 3].map(|dim| std::num::NonZeroUsize::new(dim).unwrap());
+        let generator =
+            crate::generate::handle_opts(&[], dimx, dimy).unwrap();
+        let command = format!(
+            "imagegen -s4x3 -S7 --count 3 -o {}",
+            super::shell_quote(output.to_str().unwrap())
+        );
+        // Each run uses the next seed.
+        for (image, seed) in images.iter().zip(7..) {
+            let header = format!(
+                "P6\n# seed: {seed}\n# size: 4x3\n# generator: {generator:?}\n\
+                 # command: {command}\n# version: imagegen {}\n4 3\n255\n",
+                env!("CARGO_PKG_VERSION")
+            );
+            assert!(image.starts_with(header.as_bytes()));
+            assert_eq!(image.len(), header.len() + 4 * 3 * 3);
+        }
+        assert_ne!(images[0], images[1]);
+        assert_ne!(images[1], images[2]);
+        assert_ne!(images[0], images[2]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "f32", ignore = "estimates assume 64-bit channels")]
+    fn dry_run_summary() {
+        let getopt = super::getopt();
+        let args = [
+            "-s4x3", "-S7", "-e2", "-Oo", "-w2", "-C3", "--maxfitness=0.5",
+            "-b0,0,0.5", "-v1,0,0", "-T", "-I100", "--plain", "--count=2",
+            "-o", "out.ppm",
+        ];
+        let opts = getopt
+            .parse(args)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let output = crate::output::handle_opts(&opts).unwrap();
+        assert!(!output.dry_run);
+        let index = super::RunIndex { index: 1, count: output.count };
+        let settings = super::RunSettings::parse(&opts, index, None).unwrap();
+        let path = index.path(output.paths[0].as_deref().unwrap());
+        let summary = settings.summary(&[Some(path)]);
+        #[rustfmt::skip]
+        let expected = [
+            "[run 2/2]",
+            "dimensions: 4x3 (12 pixels, ~515 B)",
+            "seed: 7",
+            "background: [0.0, 0.0, 0.0, 0.0]",
+            "generator: InnerGenerator { seeds: 2, seedat: [], offsets: [Offset { dx: -1, dy: 0 }, Offset { dx: 0, dy: -1 }, Offset { dx: 0, dy: 1 }, Offset { dx: 1, dy: 0 }], workers: 2, colorcount: 3, maxfitness: Some(0.5), invert_fitness_after: None, reseed: true, smartplace: false, edgelimit: None, edgeselect: Fifo, fitness: SumSq, neighborhood: false, worker_colors: false, mutate: None, stop: StopConditions { max_pixels: None, max_duration: None } }",
+            "color: VectorSetGroup { vectorsets: [VectorSet { start: [0.0, 0.0, 0.5, 0.0], jitter: 0.0, vectors: [[1.0, 0.0, 0.0, 0.0]], chance: 1, kind: Full }], total_chance: 1, order: Random, .. }",
+            "geometry: NSWrappingGeometry { dimx: 4, dimy: 3 }",
+            "progress: Text (interval 100, count 1)",
+            "output: out-002.ppm OutputConfig { format: Plain, maxval: 255, depth: 3, dither: false, tonemap: Clamp, tile: None }",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(summary, expected);
+    }
+
+    #[test]
+    fn outputs() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-outputs-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ppm = dir.join("a.ppm");
+        let png = dir.join("b.png");
+        let args = [
+            "-s4x3",
+            "-S7",
+            "-o",
+            ppm.to_str().unwrap(),
+            "-o",
+            png.to_str().unwrap(),
+        ]
+        .map(String::from);
+
+        run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        let ppm = std::fs::read(ppm).unwrap();
+        let png = std::fs::read(png).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(ppm.starts_with(b"P6\n# seed: 7\n"));
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn provenance() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-provenance-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let run = |extra: &[&str]| {
+            let args = ["-s4x3", "-S7", "-o", output.to_str().unwrap()]
+                .iter()
+                .chain(extra)
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>();
+            run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+            std::fs::read(&output).unwrap()
+        };
+
+        let image = run(&[]);
+        let header = String::from_utf8_lossy(&image[..image.len() - 4 * 3 * 3]);
+        let comments = header.lines().filter(|line| line.starts_with('#'));
+        let comments = comments.collect::<Vec<_>>();
+        assert!(comments.contains(&"# seed: 7"), "{header}");
+        let version =
+            format!("# version: imagegen {}", env!("CARGO_PKG_VERSION"));
+        assert!(comments.contains(&&*version), "{header}");
+
+        let image = run(&["--no-provenance"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(image.len(), "P6\n4 3\n255\n".len() + 4 * 3 * 3);
+        assert!(image.starts_with(b"P6\n4 3\n255\n"));
+    }
+
+    #[test]
+    fn bench() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-bench-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let run = |extra: &[&str]| {
+            let args = ["-s40x30", "-S5", "--no-provenance"]
+                .iter()
+                .chain(extra)
+                .chain(&["-o", output.to_str().unwrap()])
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>();
+            run_all(&args, &Arc::new(AtomicBool::new(false)))
+                .map(|()| std::fs::read(&output).unwrap())
+        };
+
+        for args in [&["-w1"][..], &["-w3", "-C4"], &["--scanline"]] {
+            let normal = run(&[&["-T"], args].concat()).unwrap();
+            let bench = run(&[&["--bench"], args].concat()).unwrap();
+            assert!(normal == bench, "{args:?}");
+        }
+
+        let err = run(&["--bench", "-T"]).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            matches!(
+                err,
+                crate::error::RunError::Config(
+                    crate::error::ConfigError::Conflict {
+                        option: "bench",
+                        other: "progresstext"
+                    }
+                )
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-timeout-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        // Far too large to finish in a second.
+        let args = ["-s1000x1000", "-S1", "--timeout=1", "--background=1,1,1"]
+            .iter()
+            .chain(&["-o", output.to_str().unwrap()])
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        run_all(&args, &interrupted).unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= std::time::Duration::from_secs(1), "{elapsed:?}");
+        assert!(elapsed < std::time::Duration::from_secs(20), "{elapsed:?}");
+        // A timeout is not an interrupt.
+        assert!(!interrupted.load(Ordering::SeqCst));
+
+        let image = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let comment = b"\n# incomplete: timed out after 1s\n";
+        assert!(image.windows(comment.len()).any(|window| window == comment));
+        let header = b"\n1000 1000\n255\n";
+        let end = image
+            .windows(header.len())
+            .position(|window| window == header)
+            .unwrap()
+            + header.len();
+        assert_eq!(image.len(), end + 1000 * 1000 * 3);
+    }
+
+    #[test]
+    fn background() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-background-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let args = [
+            "-s16x16",
+            "-S1",
+            "--background",
+            "1,1,1",
+            "-o",
+            output.to_str().unwrap(),
+        ]
+        .map(String::from);
+
+        // Stop as soon as possible, so that almost every pixel is unplaced.
+        run_all(&args, &Arc::new(AtomicBool::new(true))).unwrap();
+
+        let image = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(image.starts_with(b"P6\n# seed: 1\n"));
+        assert!(image.len() > 16 * 16 * 3);
+        let samples = &image[image.len() - 16 * 16 * 3..];
+        let white = samples.iter().filter(|&&sample| sample == 255).count();
+        assert!(white >= samples.len() * 9 / 10, "{white} white samples");
+    }
+
+    #[test]
+    fn progress_snapshot() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-snapshot-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let progress = dir.join("progress.ppm");
+        let args_iter: [&[&str]; 3] =
+            [&["-w1"], &["-w2", "-C4"], &["--scanline", "-T"]];
+        for args in args_iter {
+            let args = ["-s24x16", "-S5", "-I1", "-o", output.to_str().unwrap()]
+                .into_iter()
+                .chain(["--progressfile", progress.to_str().unwrap()])
+                .chain(args.iter().copied())
+                .map(String::from)
+                .collect::<Vec<_>>();
+            run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+
+            // The progress file ends with the finished image, displayed from
+            // the snapshot, which should match the real output exactly.
+            let image = std::fs::read(&output).unwrap();
+            let frames = std::fs::read(&progress).unwrap();
+            assert!(frames.len() > 2 * image.len(), "{args:?}");
+            assert!(frames.ends_with(&image), "{args:?}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn single_thread() {
+        let dir = std::env::temp_dir().join(format!(
+            "imagegen-single-thread-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let progress = dir.join("progress.ppm");
+        let args_iter: [&[&str]; 3] =
+            [&["-w1", "-T"], &["-C4", "-e3"], &["--scanline"]];
+        for args in args_iter {
+            let args = ["-s24x16", "-S5", "-I1", "--no-provenance"]
+                .into_iter()
+                .chain(["-o", output.to_str().unwrap()])
+                .chain(["--progressfile", progress.to_str().unwrap()])
+                .chain(args.iter().copied())
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let mut outputs = vec![];
+            for single_thread in [false, true] {
+                let mut args = args.clone();
+                if single_thread {
+                    args.push("--single-thread".to_string());
+                }
+                run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+                outputs.push((
+                    std::fs::read(&output).unwrap(),
+                    std::fs::read(&progress).unwrap(),
+                ));
+            }
+            // Both the image and every progress frame match exactly.
+            assert!(outputs[0] == outputs[1], "{args:?}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let err = run_all(&["--single-thread", "-w2"], &Arc::default());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "--single-thread cannot be used together with --workers"
+        );
+        let args = ["--single-thread", "--worker-colors"];
+        let err = run_all(&args, &Arc::default());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "--worker-colors cannot be used together with --single-thread"
+        );
+    }
+
+    #[test]
+    fn sample_every() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-sample-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let progress = dir.join("progress.ppm");
+        // 1000 pixels, and far fewer frames by `--progressinterval`.
+        let args = ["-s40x25", "-S2", "-w1", "-I100000", "--sample-every=100"]
+            .into_iter()
+            .chain(["-o", output.to_str().unwrap()])
+            .chain(["--progressfile", progress.to_str().unwrap()])
+            .collect::<Vec<_>>();
+        run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        let image = std::fs::read(&output).unwrap();
+        let frames = std::fs::read(&progress).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(frames.len() % image.len(), 0);
+        // Besides the final frame, and its copy for `--progresscount`.
+        let sampled = frames.len() / image.len() - 2;
+        assert!((9..=11).contains(&sampled), "{sampled} frames");
+    }
+
+    #[test]
+    fn frames() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-frames-test-{}", std::process::id()));
+        let output = dir.join("out.ppm");
+        // Created if missing, along with `dir`.
+        let frames = dir.join("frames");
+        // 1000 pixels, placed one per cycle, so 10 frames every 100 cycles.
+        let args = ["-s40x25", "-S2", "-w1", "-I100"]
+            .into_iter()
+            .chain(["--frames", frames.to_str().unwrap()])
+            .chain(["-o", output.to_str().unwrap()])
+            .collect::<Vec<_>>();
+        run_all(&args, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        let image = std::fs::read(&output).unwrap();
+        let mut names = std::fs::read_dir(&frames)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        let last = std::fs::read(frames.join(names.last().unwrap())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let expected = (1..=names.len())
+            .map(|frame| format!("frame-{frame:05}.ppm"))
+            .collect::<Vec<_>>();
+        assert_eq!(names, expected);
+        // About 10, plus the final frame and its copy for `--progresscount`.
+        assert!((11..=13).contains(&names.len()), "{names:?}");
+        assert_eq!(last, image);
+    }
+
+    #[test]
+    fn frames_error() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-frames-error-test-{}", std::process::id()));
+        let output = dir.join("out.pgm");
+        let frames = dir.join("frames");
+        // Frames are named for the image's format, and a directory is in the
+        // way of the second one.
+        std::fs::create_dir_all(frames.join("frame-00002.pgm")).unwrap();
+        let args = ["-s40x25", "-S2", "-w1", "-I100", "--depth=1"]
+            .into_iter()
+            .chain(["--frames", frames.to_str().unwrap()])
+            .chain(["-o", output.to_str().unwrap()])
+            .collect::<Vec<_>>();
+        let result = run_all(&args, &Arc::new(AtomicBool::new(false)));
+
+        let image = std::fs::read(&output).unwrap();
+        let first = std::fs::read(frames.join("frame-00001.pgm")).unwrap();
+        let third = frames.join("frame-00003.pgm").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            matches!(result, Err(RunError::Progress { .. })),
+            "{result:?}"
+        );
+        assert!(first.starts_with(b"P5\n"));
+        // The failure stops the run, but the image is still written.
+        assert!(!third);
+        let image = String::from_utf8_lossy(&image);
+        assert!(image.starts_with("P5\n"));
+        assert!(image.contains("# incomplete: failed to write progress frame"));
+    }
+
+    /// A generator that runs a few iterations, then panics while holding the
+    /// lock.
+    #[derive(Debug)]
+    struct PanickingGenerator;
+
+    impl crate::generate::Generator for PanickingGenerator {
+        fn generate(
+            &mut self,
+            _data: crate::generate::GeneratorData,
+            common_data: Arc<crate::CommonData>,
+            _color_generator: &dyn crate::color::ColorGenerator,
+            _rng: &mut dyn rand::RngCore,
+        ) {
+            for _ in 0..3 {
+                common_data.generator_barrier_wait();
+                common_data.generator_barrier_wait();
+            }
+            let _locked = common_data.locked.write().unwrap();
+            panic!("deliberate generator panic");
+        }
+
+        fn offsets(&self) -> &[crate::generate::Offset] {
+            &[]
+        }
+    }
+
+    /// A progressor that panics between barriers a and b.
+    struct PanickingProgressor;
+
+    impl crate::progress::Progressor for PanickingProgressor {
+        fn make_supervised_progressor(
+            &self,
+        ) -> Box<
+            dyn Send
+                + for<'a> FnOnce(
+                    crate::progress::ProgressData,
+                    &'a crate::progress::ProgressSupervisorData<'a>,
+                ) -> Pin<Box<dyn Future<Output = ()> + 'a>>,
+        > {
+            Box::new(|_progress_data, common_data| {
+                Box::pin(async move {
+                    common_data.progress_barrier.wait().await;
+                    panic!("deliberate progressor panic");
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn panics() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-panic-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let progress = format!("--progressfile={}/progress.ppm", dir.display());
+        let getopt = super::getopt();
+        let index =
+            super::RunIndex { index: 0, count: std::num::NonZeroUsize::MIN };
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        // With one progressor, and with several under a supervisor.
+        for args in [&["-T"][..], &["-T", &progress]] {
+            let opts = getopt
+                .parse(["-s64x64", "-S1", "-I1"].iter().chain(args).copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            // With --single-thread, both panic on the main thread.
+            let cases = [
+                ("generator", false, "generator"),
+                ("progress", false, "progress"),
+                ("generator", true, "main"),
+                ("progress", true, "main"),
+            ];
+            for (panicking, single_thread, thread) in cases {
+                let mut settings =
+                    super::RunSettings::parse(&opts, index, None).unwrap();
+                if panicking == "generator" {
+                    settings.generator = Box::new(PanickingGenerator);
+                }
+                let mut run =
+                    super::Run::configure(settings, "imagegen", &interrupted)
+                        .unwrap();
+                if panicking == "progress" {
+                    run.progressor = Box::new(PanickingProgressor);
+                }
+
+                let paths = [Some(output.clone())];
+                let err = run.generate_to(&paths, None, single_thread);
+                let err = err.unwrap_err();
+                assert!(
+                    matches!(
+                        err,
+                        crate::error::RunError::Panicked { thread: t, .. }
+                            if t == thread
+                    ),
+                    "{err}"
+                );
+                let image = std::fs::read(&output).unwrap();
+                let comment =
+                    format!("\n# incomplete: the {thread} thread panicked\n");
+                assert!(image
+                    .windows(comment.len())
+                    .any(|window| window == comment.as_bytes()));
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A progressor that follows the barrier protocol, but leaves behind a
+    /// thread that holds on to `common_data` for a while after it returns.
+    struct LingeringProgressor;
+
+    impl crate::progress::Progressor for LingeringProgressor {
+        fn make_supervised_progressor(
+            &self,
+        ) -> Box<
+            dyn Send
+                + for<'a> FnOnce(
+                    crate::progress::ProgressData,
+                    &'a crate::progress::ProgressSupervisorData<'a>,
+                ) -> Pin<Box<dyn Future<Output = ()> + 'a>>,
+        > {
+            unreachable!("only run alone")
+        }
+
+        fn run_alone(
+            &self,
+            _data: crate::progress::ProgressData,
+            common_data: Arc<crate::CommonData>,
+        ) {
+            std::thread::spawn({
+                let common_data = common_data.clone();
+                move || {
+                    std::thread::sleep(std::time::Duration::from_millis(500));
+                    drop(common_data);
+                }
+            });
+            loop {
+                common_data.progress_barrier.wait();
+                if common_data.finished.load(Ordering::Acquire) {
+                    break;
+                }
+                common_data.progress_barrier.wait();
+            }
+        }
+    }
+
+    #[test]
+    fn lingering_progressor() {
+        let dir = std::env::temp_dir()
+            .join(format!("imagegen-linger-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.ppm");
+        let getopt = super::getopt();
+        let index =
+            super::RunIndex { index: 0, count: std::num::NonZeroUsize::MIN };
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let opts = getopt
+            .parse(["-s16x16", "-S1", "-I1"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let settings = super::RunSettings::parse(&opts, index, None).unwrap();
+        let mut run =
+            super::Run::configure(settings, "imagegen", &interrupted).unwrap();
+        run.progressor = Box::new(LingeringProgressor);
+
+        let paths = [Some(output.clone())];
+        run.generate_to(&paths, None, false).unwrap();
+        let image = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(image.starts_with(b"P6\n"));
+        let comment = b"incomplete";
+        assert!(!image.windows(comment.len()).any(|window| window == comment));
+    }
+
+    #[test]
+    fn shell_command() {
+        let args =
+            ["-s4x3", "--seed", "it's", "", "-o", "a b/out.ppm", "-v1,0"];
+        assert_eq!(
+            super::shell_command(args),
+            r"imagegen -s4x3 --seed 'it'\''s' '' -o 'a b/out.ppm' -v1,0"
+        );
+    }
+
+    #[test]
+    fn unexpected_argument() {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        for args in [&["-s4x3", "foo.ppm"][..], &["foo.ppm", "-s4x3", "bar"]] {
+            let err = run_all(args, &interrupted).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "unexpected argument \"foo.ppm\" (did you mean `-o foo.ppm`?)"
+            );
+        }
+        // Arguments after `--` are never options.
+        let err = run_all(&["--", "-s4x3"], &interrupted).unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::Config(ConfigError::UnexpectedArgument(arg))
+                if arg == "-s4x3"
+        ));
+    }
 }
@@ -0,0 +1,267 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use getopt::{GetoptItem, Opt};
+
+use crate::error::ConfigError;
+
+pub fn opts() -> impl IntoIterator<Item = Opt> {
+    [
+        Opt::short_long('o', "output", getopt::HasArgument::Yes),
+        Opt::long("count", getopt::HasArgument::Yes),
+        Opt::long("dry-run", getopt::HasArgument::No),
+        Opt::long("force-stdout", getopt::HasArgument::No),
+        Opt::long("timeout", getopt::HasArgument::Yes),
+        Opt::long("single-thread", getopt::HasArgument::No),
+    ]
+}
+
+pub struct OutputSettings {
+    /// Where to write each image, in the format for each path's extension
+    /// (see `OutputConfig::for_path`). `None` (from `-o -`, or if no `-o` was
+    /// given) is stdout.
+    pub paths: Vec<Option<String>>,
+    /// How many images to generate.
+    pub count: NonZeroUsize,
+    /// Whether to only print a summary of each run's settings instead of
+    /// generating anything.
+    pub dry_run: bool,
+    /// Whether to write to stdout even if it is a terminal, either because
+    /// `--force-stdout` or `-o -` was given.
+    pub force_stdout: bool,
+    /// How long each run may take before it is stopped and its partial image
+    /// is written.
+    pub timeout: Option<Duration>,
+    /// Whether to run the generator and progressors on the main thread,
+    /// for debugging.
+    pub single_thread: bool,
+}
+
+pub fn handle_opts(
+    opts: &[GetoptItem<'_>],
+) -> Result<OutputSettings, ConfigError> {
+    let mut paths = vec![];
+    let mut count = None;
+    let mut dry_run = false;
+    let mut force_stdout = false;
+    let mut timeout = None;
+    let mut single_thread = false;
+    for opt in opts {
+        match opt {
+            GetoptItem::Opt { opt, arg: Some(path_str) }
+                if opt.is_long("output") =>
+            {
+                // `-o -` explicitly asks for stdout.
+                let path = (*path_str != "-").then(|| path_str.to_string());
+                if paths.contains(&path) {
+                    return Err(ConfigError::Duplicate {
+                        option: "output",
+                        value: path_str.to_string(),
+                    });
+                }
+                force_stdout |= path.is_none();
+                paths.push(path);
+            }
+            GetoptItem::Opt { opt, arg: Some(count_str) }
+                if opt.is_long("count") =>
+            {
+                if count.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "count",
+                        value: count_str.to_string(),
+                    });
+                }
+                count = Some(count_str.parse().map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "count",
+                        value: count_str.to_string(),
+                    }
+                })?);
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("dry-run") => {
+                dry_run = true;
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("force-stdout") =>
+            {
+                force_stdout = true;
+            }
+            GetoptItem::Opt { opt, arg: Some(timeout_str) }
+                if opt.is_long("timeout") =>
+            {
+                if timeout.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "timeout",
+                        value: timeout_str.to_string(),
+                    });
+                }
+                let seconds = timeout_str.parse::<f64>().ok();
+                timeout = Some(
+                    seconds
+                        .filter(|&seconds| seconds > 0.0)
+                        .and_then(|seconds| {
+                            Duration::try_from_secs_f64(seconds).ok()
+                        })
+                        .ok_or_else(|| ConfigError::InvalidValue {
+                            option: "timeout",
+                            value: timeout_str.to_string(),
+                        })?,
+                );
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("single-thread") =>
+            {
+                single_thread = true;
+            }
+            _ => {}
+        }
+    }
+    if paths.is_empty() {
+        paths.push(None);
+    }
+    Ok(OutputSettings {
+        paths,
+        count: count.unwrap_or(NonZeroUsize::MIN),
+        dry_run,
+        force_stdout,
+        timeout,
+        single_thread,
+    })
+}
+
+impl OutputSettings {
+    /// Refuses to write binary image data to stdout if it is a terminal
+    /// (according to `is_terminal`), unless that was explicitly asked for.
+    pub fn check_stdout(
+        &self,
+        is_terminal: impl FnOnce() -> bool,
+    ) -> Result<(), ConfigError> {
+        let stdout = self.paths.contains(&None);
+        if stdout && !self.force_stdout && is_terminal() {
+            return Err(ConfigError::StdoutIsTerminal);
+        }
+        Ok(())
+    }
+}
+
+/// Which of the `--count` runs of this invocation is being generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunIndex {
+    /// 0-based.
+    pub index: usize,
+    pub count: NonZeroUsize,
+}
+
+impl RunIndex {
+    /// A label like `run 2/3` for progress output, or `None` if there is only
+    /// one run.
+    pub fn label(&self) -> Option<String> {
+        (self.count.get() > 1)
+            .then(|| format!("run {}/{}", self.index + 1, self.count))
+    }
+
+    /// Adds the (1-based) run index to `path` before its extension, e.g.
+    /// `out.ppm` becomes `out-002.ppm`, if there is more than one run.
+    pub fn path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+        if self.count.get() == 1 {
+            return path.to_owned();
+        }
+        let width = std::cmp::max(3, self.count.to_string().len());
+        let mut name = path.file_stem().unwrap_or_default().to_owned();
+        name.push(format!("-{:0width$}", self.index + 1));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        path.with_file_name(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::Path, time::Duration};
+
+    use getopt::Getopt;
+
+    use super::RunIndex;
+    use crate::error::ConfigError;
+
+    #[test]
+    fn check_stdout() {
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        #[rustfmt::skip]
+        let cases: [(&[&str], bool, bool); 7] = [
+            (&[], true, false),
+            (&[], false, true),
+            (&["--force-stdout"], true, true),
+            (&["-o", "-"], true, true),
+            (&["-o", "out.ppm"], true, true),
+            (&["-o", "out.ppm"], false, true),
+            (&["-o", "out.ppm", "-o", "out.png"], true, true),
+        ];
+        for (args, is_terminal, allowed) in cases {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let output = super::handle_opts(&opts).unwrap();
+            let result = output.check_stdout(|| is_terminal);
+            assert_eq!(result.is_ok(), allowed, "{args:?}");
+            if !allowed {
+                assert!(matches!(result, Err(ConfigError::StdoutIsTerminal)));
+            }
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let parse = |args: &[&str]| {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            super::handle_opts(&opts).map(|output| output.timeout)
+        };
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert_eq!(
+            parse(&["--timeout=1.5"]).unwrap(),
+            Some(Duration::from_millis(1500))
+        );
+        for value in ["0", "-1", "inf", "NaN", "1e30", "soon"] {
+            let err = parse(&["--timeout", value]).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    ConfigError::InvalidValue { option: "timeout", .. }
+                ),
+                "{value}: {err}"
+            );
+        }
+        assert!(matches!(
+            parse(&["--timeout=1", "--timeout=2"]),
+            Err(ConfigError::Duplicate { option: "timeout", .. })
+        ));
+    }
+
+    #[test]
+    fn run_index_path() {
+        let single = RunIndex { index: 0, count: NonZeroUsize::MIN };
+        assert_eq!(single.path("dir/out.ppm"), Path::new("dir/out.ppm"));
+        assert_eq!(single.label(), None);
+
+        let count = NonZeroUsize::new(3).unwrap();
+        let second = RunIndex { index: 1, count };
+        assert_eq!(second.path("dir/out.ppm"), Path::new("dir/out-002.ppm"));
+        assert_eq!(second.path("out"), Path::new("out-002"));
+        assert_eq!(second.label().as_deref(), Some("run 2/3"));
+
+        let count = NonZeroUsize::new(1500).unwrap();
+        let last = RunIndex { index: 1499, count };
+        assert_eq!(last.path("out.tar.ppm"), Path::new("out.tar-1500.ppm"));
+    }
+}
@@ -1,16 +1,129 @@
-use std::simd::num::SimdFloat;
+use std::{
+    io::{Read, Write},
+    num::NonZeroU32,
+    path::Path,
+    simd::num::SimdFloat,
+};
+
+use bitmap::BitMap;
 
 use crate::color::{Channel, Color};
 
+#[derive(Clone)]
 pub struct PnmData {
     pub dimx: u32,
     pub dimy: u32,
-    pub maxval: u32,
-    pub depth: u32,
+    /// How this image is written by default.
+    pub output: OutputConfig,
     pub comments: Vec<String>,
+    /// The color written for pixels that have not been placed (e.g. if
+    /// generation was interrupted) by `write_to`.
+    ///
+    /// This is applied when writing instead of being stored in `rawdata`, so
+    /// that it cannot affect the fitness of neighboring pixels.
+    pub background: Color,
     pub rawdata: Vec<Color>,
 }
 
+/// Which encoding of the PNM format (or other file format) to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PnmFormat {
+    /// Binary samples (`P5`/`P6`).
+    Raw,
+    /// ASCII decimal samples (`P2`/`P3`).
+    Plain,
+    /// PAM (`P7`), with binary samples.
+    Pam,
+    /// PNG, with 8-bit samples, or 16-bit samples if `maxval` is above 255.
+    /// Comments are written as `Comment` text chunks.
+    Png,
+}
+
+/// How channel values outside of `0.0..=1.0` are mapped into range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Clamp each channel to `0.0..=1.0`.
+    Clamp,
+    /// Scale the whole image so that its brightest channel is `1.0` (only if
+    /// it is above `1.0`), then clamp.
+    Normalize,
+}
+
+/// Settings for writing a `PnmData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputConfig {
+    pub format: PnmFormat,
+    /// Must be in `1..=65535`. Values above 255 use 2 bytes per sample.
+    pub maxval: u32,
+    /// 1 for grayscale (PGM) or 3 for color (PPM).
+    pub depth: u32,
+    /// Whether to apply ordered dithering when quantizing. Mostly useful for
+    /// small `maxval`s.
+    pub dither: bool,
+    pub tonemap: Tonemap,
+    /// If given, the image is repeated in a grid when written (see
+    /// `PnmData::tiled`).
+    pub tile: Option<Tile>,
+}
+
+/// How `--tile` repeats the image: `across` copies wide and `down` copies
+/// high. With `mirror` (`--mirror`), every other column of copies is flipped
+/// horizontally, and every other row vertically, so that the copies meet
+/// seamlessly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub across: NonZeroU32,
+    pub down: NonZeroU32,
+    pub mirror: bool,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            format: PnmFormat::Raw,
+            maxval: 255,
+            depth: 3,
+            dither: false,
+            tonemap: Tonemap::Clamp,
+            tile: None,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// This config, adjusted for the extension of `path`: `.ppm` and `.pgm`
+    /// are color and grayscale PNM (raw unless `--plain` was given), `.pam`
+    /// is PAM, and `.png` is PNG. Other extensions are written as configured.
+    pub fn for_path(mut self, path: &Path) -> Self {
+        let pnm = match self.format {
+            PnmFormat::Plain => PnmFormat::Plain,
+            _ => PnmFormat::Raw,
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("ppm") => (self.format, self.depth) = (pnm, 3),
+            Some("pgm") => (self.format, self.depth) = (pnm, 1),
+            Some("pam") => self.format = PnmFormat::Pam,
+            Some("png") => self.format = PnmFormat::Png,
+            _ => {}
+        }
+        self
+    }
+
+    /// The extension that `for_path` maps to this config's format and depth.
+    pub fn extension(&self) -> &'static str {
+        match (self.format, self.depth) {
+            (PnmFormat::Png, _) => "png",
+            (PnmFormat::Pam, _) => "pam",
+            (PnmFormat::Raw | PnmFormat::Plain, 1) => "pgm",
+            (PnmFormat::Raw | PnmFormat::Plain, _) => "ppm",
+        }
+    }
+}
+
 impl std::ops::Index<(usize, usize)> for PnmData {
     type Output = Color;
 
@@ -28,33 +141,721 @@ impl std::ops::IndexMut<(usize, usize)> for PnmData {
     }
 }
 
+/// Quantizes a channel value to a sample in `0..=maxval`: clamps it to
+/// `0.0..=1.0`, scales it, and rounds to nearest.
+///
+/// Every output path (each file format, and the progressors that display the
+/// image) quantizes with this, so they all agree. Samples used to be
+/// truncated instead, so they can be one level higher than in older output.
+pub fn quantize(value: Channel, maxval: u32) -> u16 {
+    quantize_dithered(value, maxval as Channel, 0.5)
+}
+
+/// Like `quantize`, but rounding up from `threshold` instead of one half, for
+/// ordered dithering.
+fn quantize_dithered(
+    value: Channel,
+    maxval: Channel,
+    threshold: Channel,
+) -> u16 {
+    (value.clamp(0.0, 1.0) * maxval + threshold).min(maxval) as u16
+}
+
+/// Quantizes each channel of `color` (including alpha) to 8 bits, for
+/// displaying it.
+#[cfg(any(feature = "sdl2", feature = "framebuffer"))]
+pub fn quantize_8bit(color: Color) -> [u8; 4] {
+    color.to_array().map(|channel| quantize(channel, 255) as u8)
+}
+
+/// 4x4 Bayer matrix, for ordered dithering.
+const BAYER_4X4: [[u8; 4]; 4] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
 impl PnmData {
-    pub fn write_to<W: std::io::Write>(
+    /// Appends a comment to be written in the image's header. Each line of a
+    /// multi-line comment is written as a separate comment.
+    pub fn add_comment(&mut self, comment: impl Into<String>) {
+        self.comments.push(comment.into());
+    }
+
+    /// Reads a PNM image (`P1` through `P6`), with samples scaled to
+    /// `0.0..=1.0` and grayscale expanded to gray colors. Its comments and
+    /// format are kept, so that writing it again gives a similar file.
+    ///
+    /// Bitmaps (`P1`/`P4`) are read as black and white grayscale images with
+    /// a maxval of 1.
+    pub fn read_from<R: Read>(mut reader: R) -> std::io::Result<PnmData> {
+        let invalid = |msg: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+        };
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let (format, depth) = match bytes.get(..2) {
+            Some(b"P1") => (PnmFormat::Plain, 0),
+            Some(b"P4") => (PnmFormat::Raw, 0),
+            Some(b"P2") => (PnmFormat::Plain, 1),
+            Some(b"P3") => (PnmFormat::Plain, 3),
+            Some(b"P5") => (PnmFormat::Raw, 1),
+            Some(b"P6") => (PnmFormat::Raw, 3),
+            _ => return Err(invalid("not a PBM, PGM, or PPM image")),
+        };
+        // Bitmaps have no maxval, and each sample is a single bit (or digit),
+        // where 1 is black.
+        let bitmap = depth == 0;
+        let depth = depth.max(1);
+        let mut pos = 2;
+        let mut comments = vec![];
+        // Reads a decimal number of at most `max_digits` digits, skipping
+        // whitespace and comments before it.
+        let number = |pos: &mut usize,
+                      comments: &mut Vec<String>,
+                      max_digits: usize| {
+            loop {
+                match bytes.get(*pos) {
+                    Some(b'#') => {
+                        let line = bytes[*pos + 1..]
+                            .split(|&byte| byte == b'\n')
+                            .next()
+                            .unwrap_or_default();
+                        *pos += line.len() + 1;
+                        let line = String::from_utf8_lossy(line);
+                        comments.push(line.trim().to_string());
+                    }
+                    Some(byte) if byte.is_ascii_whitespace() => *pos += 1,
+                    _ => break,
+                }
+            }
+            let rest = bytes.get(*pos..).unwrap_or_default();
+            let digits = rest
+                .iter()
+                .take(max_digits)
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            let number = std::str::from_utf8(&rest[..digits])
+                .ok()
+                .and_then(|digits| digits.parse::<u32>().ok())
+                .ok_or_else(|| invalid("invalid header or sample"))?;
+            *pos += digits;
+            Ok::<_, std::io::Error>(number)
+        };
+        let dimx = number(&mut pos, &mut comments, usize::MAX)?;
+        let dimy = number(&mut pos, &mut comments, usize::MAX)?;
+        let maxval = match bitmap {
+            true => 1,
+            false => number(&mut pos, &mut comments, usize::MAX)?,
+        };
+        if !(1..=65535).contains(&maxval) {
+            return Err(invalid("invalid maxval"));
+        }
+        let len = (dimx as usize)
+            .checked_mul(dimy as usize)
+            .and_then(|pixels| pixels.checked_mul(depth))
+            .ok_or_else(|| invalid("image too large"))?;
+        let mut samples = Vec::with_capacity(len.min(bytes.len()));
+        match format {
+            PnmFormat::Plain if bitmap => {
+                for _ in 0..len {
+                    match number(&mut pos, &mut comments, 1)? {
+                        0 => samples.push(1),
+                        1 => samples.push(0),
+                        _ => return Err(invalid("sample larger than maxval")),
+                    }
+                }
+            }
+            PnmFormat::Plain => {
+                for _ in 0..len {
+                    samples.push(number(&mut pos, &mut comments, usize::MAX)?);
+                }
+            }
+            _ if bitmap => {
+                // Each row is packed into whole bytes, most significant bit
+                // first.
+                pos += 1;
+                let stride = (dimx as usize).div_ceil(8);
+                let raster = bytes
+                    .get(pos..)
+                    .and_then(|raster| raster.get(..stride * dimy as usize))
+                    .ok_or_else(|| invalid("truncated image data"))?;
+                for row in raster.chunks(stride.max(1)).take(dimy as usize) {
+                    samples.extend((0..dimx as usize).map(|col| {
+                        u32::from(row[col / 8] & (0x80 >> (col % 8)) == 0)
+                    }));
+                }
+            }
+            _ => {
+                // Exactly one whitespace byte separates the header from the
+                // samples.
+                pos += 1;
+                let width = if maxval > 255 { 2 } else { 1 };
+                let raster = bytes
+                    .get(pos..)
+                    .and_then(|raster| raster.get(..len * width))
+                    .ok_or_else(|| invalid("truncated image data"))?;
+                samples.extend(raster.chunks(width).map(|sample| {
+                    sample.iter().fold(0, |acc, &byte| acc << 8 | byte as u32)
+                }));
+            }
+        }
+        if samples.iter().any(|&sample| sample > maxval) {
+            return Err(invalid("sample larger than maxval"));
+        }
+        let scale = maxval as Channel;
+        let rawdata = samples
+            .chunks(depth)
+            .map(|pixel| {
+                let channel = |i: usize| pixel[i % depth] as Channel / scale;
+                Color::from_array([channel(0), channel(1), channel(2), 0.0])
+            })
+            .collect();
+        Ok(PnmData {
+            dimx,
+            dimy,
+            output: OutputConfig {
+                format,
+                maxval,
+                depth: depth as u32,
+                ..OutputConfig::default()
+            },
+            comments,
+            background: Color::default(),
+            rawdata,
+        })
+    }
+
+    /// This image repeated as `tile` says, with pixels that are not set in
+    /// `placed` (if given) replaced by `self.background`.
+    ///
+    /// The caller must check that the result is not too large.
+    pub fn tiled(&self, tile: Tile, placed: Option<&BitMap>) -> PnmData {
+        let (dimx, dimy) = (self.dimx as usize, self.dimy as usize);
+        let (across, down) = (tile.across.get(), tile.down.get());
+        let mut rawdata = Vec::with_capacity(
+            self.rawdata.len() * across as usize * down as usize,
+        );
+        for cell_y in 0..down as usize {
+            for y in 0..dimy {
+                let y = match tile.mirror && cell_y % 2 == 1 {
+                    true => dimy - 1 - y,
+                    false => y,
+                };
+                for cell_x in 0..across as usize {
+                    let flip = tile.mirror && cell_x % 2 == 1;
+                    rawdata.extend((0..dimx).map(|x| {
+                        let x = if flip { dimx - 1 - x } else { x };
+                        match placed {
+                            Some(placed) if !placed.get((y, x)) => {
+                                self.background
+                            }
+                            _ => self[(y, x)],
+                        }
+                    }));
+                }
+            }
+        }
+        PnmData {
+            dimx: self.dimx * across,
+            dimy: self.dimy * down,
+            output: self.output,
+            comments: self.comments.clone(),
+            background: self.background,
+            rawdata,
+        }
+    }
+
+    /// Writes the image with `config`. If `placed` is given, pixels that are
+    /// not set in it are written as `self.background`.
+    pub fn write_to<W: Write>(
         &self,
         mut writer: W,
+        config: &OutputConfig,
+        placed: Option<&BitMap>,
     ) -> std::io::Result<()> {
-        if self.maxval > 255 {
-            todo!("16-bit pnm");
+        if let Some(tile) = config.tile {
+            let config = OutputConfig { tile: None, ..*config };
+            return self.tiled(tile, placed).write_to(writer, &config, None);
+        }
+        let invalid = |msg: String| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+        };
+        if !(1..=65535).contains(&config.maxval) {
+            return Err(invalid(format!("invalid maxval {}", config.maxval)));
         }
-        if self.depth != 3 {
-            todo!("non-ppm pnm");
+        if !matches!(config.depth, 1 | 3) {
+            return Err(invalid(format!("invalid depth {}", config.depth)));
+        }
+        let maxval = match config.format {
+            // PNG only has 8- and 16-bit samples.
+            PnmFormat::Png if config.maxval > 255 => 65535,
+            PnmFormat::Png => 255,
+            _ => config.maxval,
+        };
+        let comment_lines =
+            self.comments.iter().flat_map(|comment| comment.lines());
+        match (config.format, config.depth) {
+            (PnmFormat::Raw | PnmFormat::Plain, depth) => {
+                let magic = match (config.format, depth) {
+                    (PnmFormat::Raw, 1) => "P5",
+                    (PnmFormat::Raw, _) => "P6",
+                    (_, 1) => "P2",
+                    (_, _) => "P3",
+                };
+                writeln!(writer, "{magic}")?;
+                for line in comment_lines {
+                    writeln!(writer, "# {line}")?;
+                }
+                writeln!(writer, "{} {}", self.dimx, self.dimy)?;
+                writeln!(writer, "{maxval}")?;
+            }
+            (PnmFormat::Pam, depth) => {
+                writeln!(writer, "P7")?;
+                for line in comment_lines {
+                    writeln!(writer, "# {line}")?;
+                }
+                let tupltype = if depth == 1 { "GRAYSCALE" } else { "RGB" };
+                write!(
+                    writer,
+                    "WIDTH {}\nHEIGHT {}\nDEPTH {depth}\nMAXVAL {maxval}\n\
+                     TUPLTYPE {tupltype}\nENDHDR\n",
+                    self.dimx, self.dimy,
+                )?;
+            }
+            // The header is written with the samples, below.
+            (PnmFormat::Png, _) => {}
         }
-        writeln!(writer, "P6")?;
-        writeln!(writer, "{} {}", self.dimx, self.dimy)?;
-        write!(writer, "{}\n", self.maxval)?;
-
-        let maxval = self.maxval as Channel;
 
-        let to_bytes = |color: Color| {
-            let a = color * Color::splat(maxval);
-            a.cast::<u8>()
+        let scale = match config.tonemap {
+            Tonemap::Clamp => 1.0,
+            Tonemap::Normalize => {
+                let background = placed.map(|_| &self.background);
+                let brightest = self
+                    .rawdata
+                    .iter()
+                    .chain(background)
+                    .map(|color| color.reduce_max())
+                    .fold(1.0, Channel::max);
+                1.0 / brightest
+            }
         };
+        let binary_maxval = maxval;
+        let maxval = maxval as Channel;
 
-        for &color in &self.rawdata {
-            let bytes = to_bytes(color);
-            writer.write_all(&bytes.as_array()[..3])?;
+        let mut row = vec![];
+        // PNG rows, each starting with its filter type, which are compressed
+        // together.
+        let mut png_data = vec![];
+        for (y, pixels) in self.rawdata.chunks(self.dimx as usize).enumerate() {
+            row.clear();
+            if config.format == PnmFormat::Png {
+                // No filter.
+                row.push(0);
+            }
+            for (x, &color) in pixels.iter().enumerate() {
+                let color = match placed {
+                    Some(placed) if !placed.get((y, x)) => self.background,
+                    _ => color,
+                };
+                let threshold = config.dither.then(|| {
+                    (BAYER_4X4[y % 4][x % 4] as Channel + 0.5) / 16.0
+                });
+                let [r, g, b, _] = color.to_array();
+                let samples = match config.depth {
+                    // Same weights as netpbm's `ppmtopgm`.
+                    1 => [0.299 * r + 0.587 * g + 0.114 * b, 0.0, 0.0],
+                    _ => [r, g, b],
+                };
+                for &sample in &samples[..config.depth as usize] {
+                    let sample = match threshold {
+                        Some(threshold) => {
+                            quantize_dithered(sample * scale, maxval, threshold)
+                        }
+                        None => quantize(sample * scale, binary_maxval),
+                    };
+                    match config.format {
+                        PnmFormat::Plain => {
+                            // One sample per line, since plain PNM lines
+                            // should be at most 70 characters.
+                            write!(row, "{sample}")?;
+                            row.push(b'\n');
+                        }
+                        _ if binary_maxval > 255 => {
+                            row.extend_from_slice(&sample.to_be_bytes())
+                        }
+                        _ => row.push(sample as u8),
+                    }
+                }
+            }
+            match config.format {
+                PnmFormat::Png => png_data.extend_from_slice(&row),
+                _ => writer.write_all(&row)?,
+            }
         }
 
+        if config.format == PnmFormat::Png {
+            self.write_png(writer, config.depth, binary_maxval, &png_data)?;
+        }
         Ok(())
     }
+
+    /// Writes a PNG with `data` as its (uncompressed) image data.
+    fn write_png<W: Write>(
+        &self,
+        mut writer: W,
+        depth: u32,
+        maxval: u32,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = vec![];
+        header.extend_from_slice(&self.dimx.to_be_bytes());
+        header.extend_from_slice(&self.dimy.to_be_bytes());
+        // Bit depth and color type (grayscale or truecolor).
+        header.push(if maxval > 255 { 16 } else { 8 });
+        header.push(if depth == 1 { 0 } else { 2 });
+        // Compression method, filter method, and no interlacing.
+        header.extend_from_slice(&[0, 0, 0]);
+        write_png_chunk(&mut writer, b"IHDR", &header)?;
+
+        for comment in &self.comments {
+            // An uncompressed international text chunk, without a language
+            // tag or translated keyword.
+            let mut text = b"Comment\0\0\0\0\0".to_vec();
+            text.extend_from_slice(comment.as_bytes());
+            write_png_chunk(&mut writer, b"iTXt", &text)?;
+        }
+
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(data, 6);
+        write_png_chunk(&mut writer, b"IDAT", &compressed)?;
+        write_png_chunk(&mut writer, b"IEND", &[])
+    }
+}
+
+fn write_png_chunk<W: Write>(
+    mut writer: W,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> std::io::Result<()> {
+    let len = u32::try_from(data.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "image too large for PNG",
+        )
+    })?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc32(kind.iter().chain(data)).to_be_bytes())
+}
+
+/// The CRC-32 used by PNG (and zlib, etc.).
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroU32, path::Path};
+
+    use bitmap::BitMap;
+
+    use super::{OutputConfig, PnmData, PnmFormat, Tile, Tonemap};
+    use crate::color::{Channel, Color};
+
+    fn image() -> PnmData {
+        PnmData {
+            dimx: 2,
+            dimy: 1,
+            output: OutputConfig::default(),
+            comments: vec![],
+            background: Color::default(),
+            rawdata: vec![
+                Color::from_array([1.0, 0.5, 0.0, 0.0]),
+                Color::from_array([2.0, 0.0, -1.0, 0.0]),
+            ],
+        }
+    }
+
+    fn write(image: &PnmData, config: &OutputConfig) -> Vec<u8> {
+        let mut output = vec![];
+        image.write_to(&mut output, config, None).unwrap();
+        output
+    }
+
+    #[test]
+    fn output_configs() {
+        let image = image();
+
+        let ppm = write(&image, &image.output);
+        assert_eq!(ppm, b"P6\n2 1\n255\n\xff\x80\x00\xff\x00\x00");
+
+        let pgm = write(
+            &image,
+            &OutputConfig {
+                maxval: 65535,
+                depth: 1,
+                ..OutputConfig::default()
+            },
+        );
+        assert!(pgm.starts_with(b"P5\n2 1\n65535\n"));
+        assert_eq!(pgm.len(), 13 + 2 * 2);
+
+        let plain = write(
+            &image,
+            &OutputConfig {
+                format: PnmFormat::Plain,
+                maxval: 100,
+                tonemap: Tonemap::Normalize,
+                ..OutputConfig::default()
+            },
+        );
+        assert_eq!(plain, b"P3\n2 1\n100\n50\n25\n0\n100\n0\n0\n");
+    }
+
+    #[test]
+    fn tile() {
+        let image = image();
+        let tile = |across, down, mirror| {
+            let across = NonZeroU32::new(across).unwrap();
+            let down = NonZeroU32::new(down).unwrap();
+            Some(Tile { across, down, mirror })
+        };
+
+        // The halves are the same, or mirror each other.
+        let config = OutputConfig { tile: tile(2, 1, false), ..image.output };
+        let tiled = write(&image, &config);
+        let (a, b) = (b"\xff\x80\x00", b"\xff\x00\x00");
+        assert_eq!(tiled, [&b"P6\n4 1\n255\n"[..], a, b, a, b].concat());
+        let config = OutputConfig { tile: tile(2, 1, true), ..image.output };
+        let mirrored = write(&image, &config);
+        assert_eq!(mirrored, [&b"P6\n4 1\n255\n"[..], a, b, b, a].concat());
+
+        // Unplaced pixels are replaced by the background, and rows are
+        // mirrored too.
+        let mut image = image;
+        image.dimy = 2;
+        image.rawdata.extend([Color::splat(0.25), Color::splat(0.75)]);
+        image.background = Color::splat(1.0);
+        let mut placed = BitMap::new(2, 2).unwrap();
+        placed.set((0, 0), true);
+        placed.set((1, 1), true);
+        let tiled = image.tiled(tile(2, 2, true).unwrap(), Some(&placed));
+        let (a, b) = (image.rawdata[0], image.background);
+        let (c, d) = (image.background, image.rawdata[3]);
+        #[rustfmt::skip]
+        let expected = [
+            a, b, b, a,
+            c, d, d, c,
+            c, d, d, c,
+            a, b, b, a,
+        ];
+        assert_eq!((tiled.dimx, tiled.dimy), (4, 4));
+        assert_eq!(tiled.rawdata, expected);
+    }
+
+    #[test]
+    fn pam() {
+        let image = image();
+        let config =
+            OutputConfig { format: PnmFormat::Pam, ..OutputConfig::default() };
+        let pam = write(&image, &config);
+        let header = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\n\
+                       TUPLTYPE RGB\nENDHDR\n";
+        assert_eq!(pam[..header.len()], header[..]);
+        assert_eq!(pam[header.len()..], *b"\xff\x80\x00\xff\x00\x00");
+    }
+
+    #[test]
+    fn png() {
+        assert_eq!(super::crc32(b"IEND"), 0xae42_6082);
+
+        let mut image = image();
+        image.comments = vec!["seed: 1".into()];
+        let config = OutputConfig {
+            format: PnmFormat::Png,
+            maxval: 1000,
+            depth: 1,
+            ..OutputConfig::default()
+        };
+        let png = write(&image, &config);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"));
+        // 2x1, 16-bit grayscale.
+        assert_eq!(png[16..29], *b"\0\0\0\x02\0\0\0\x01\x10\0\0\0\0");
+        assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+
+        let chunk = |kind: &[u8]| {
+            let start = png.windows(4).position(|window| window == kind);
+            let start = start.unwrap() + 4;
+            let len = png[start - 8..start - 4].try_into().unwrap();
+            &png[start..start + u32::from_be_bytes(len) as usize]
+        };
+        assert_eq!(chunk(b"iTXt"), b"Comment\0\0\0\0\0seed: 1");
+        let data =
+            miniz_oxide::inflate::decompress_to_vec_zlib(chunk(b"IDAT"));
+        let gray = |[r, g, b]: [Channel; 3]| {
+            super::quantize(0.299 * r + 0.587 * g + 0.114 * b, 65535)
+        };
+        let [a, b] = gray([1.0, 0.5, 0.0]).to_be_bytes();
+        let [c, d] = gray([2.0, 0.0, -1.0]).to_be_bytes();
+        assert_eq!(data.unwrap(), [0, a, b, c, d]);
+    }
+
+    #[test]
+    fn quantize() {
+        let inputs = [0.0, 0.5, 0.999, 1.0, 1.7];
+        let samples =
+            |maxval| inputs.map(|value| super::quantize(value, maxval));
+        assert_eq!(samples(100), [0, 50, 100, 100, 100]);
+        assert_eq!(samples(255), [0, 128, 255, 255, 255]);
+        assert_eq!(super::quantize(-0.2, 255), 0);
+        assert_eq!(super::quantize(0.7, 1), 1);
+        assert_eq!(super::quantize(0.3, 1), 0);
+
+        #[cfg(any(feature = "sdl2", feature = "framebuffer"))]
+        {
+            let color = Color::from_array([1.7, 0.5, 0.0, 0.2]);
+            assert_eq!(super::quantize_8bit(color), [255, 128, 0, 51]);
+        }
+    }
+
+    /// Rounding instead of truncating only ever raises samples by one level.
+    #[test]
+    fn quantize_regression() {
+        use rand::SeedableRng;
+
+        let generator = crate::color::handle_opts(&[]).unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut rawdata = vec![Color::default(); 64 * 64];
+        generator.new_colors(&mut rng, 0, &mut rawdata);
+        let image = PnmData { dimx: 64, dimy: 64, rawdata, ..image() };
+
+        for maxval in [100, 255, 65535] {
+            let config = OutputConfig {
+                format: PnmFormat::Plain,
+                maxval,
+                ..OutputConfig::default()
+            };
+            let output = String::from_utf8(write(&image, &config)).unwrap();
+            let samples = output.lines().skip(3).map(|s| s.parse().unwrap());
+            let channels = image.rawdata.iter().flat_map(|color| {
+                let [r, g, b, _] = color.to_array();
+                [r, g, b]
+            });
+            for (sample, channel) in samples.zip(channels) {
+                let truncated =
+                    (channel.clamp(0.0, 1.0) * maxval as Channel) as u32;
+                let sample: u32 = sample;
+                assert!(
+                    (truncated..=truncated + 1).contains(&sample),
+                    "{channel} -> {sample} (truncated: {truncated})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn for_path() {
+        let plain = OutputConfig {
+            format: PnmFormat::Plain,
+            ..OutputConfig::default()
+        };
+        let cases = [
+            ("out.ppm", PnmFormat::Plain, 3),
+            ("out.PGM", PnmFormat::Plain, 1),
+            ("out.pam", PnmFormat::Pam, 3),
+            ("out.png", PnmFormat::Png, 3),
+            ("out", PnmFormat::Plain, 3),
+        ];
+        for (path, format, depth) in cases {
+            let config = plain.for_path(Path::new(path));
+            let expected = (format, depth);
+            assert_eq!((config.format, config.depth), expected, "{path}");
+            let named = format!("frame.{}", config.extension());
+            assert_eq!(config.for_path(Path::new(&named)), config, "{path}");
+        }
+        let png = OutputConfig { format: PnmFormat::Png, ..plain };
+        let config = png.for_path(Path::new("out.pgm"));
+        assert_eq!((config.format, config.depth), (PnmFormat::Raw, 1));
+    }
+
+    #[test]
+    fn comments() {
+        let mut image = image();
+        image.comments = vec!["seed: 1".into(), "two\nlines".into()];
+        let output = write(&image, &image.output);
+        let header = b"P6\n# seed: 1\n# two\n# lines\n2 1\n255\n";
+        assert!(output.starts_with(header));
+    }
+
+    #[test]
+    fn read() {
+        let mut image = image();
+        image.comments = vec!["seed: 1".into()];
+        for format in [PnmFormat::Raw, PnmFormat::Plain] {
+            for (maxval, depth) in [(255, 3), (65535, 3), (15, 1)] {
+                let config =
+                    OutputConfig { format, maxval, depth, ..image.output };
+                let read = PnmData::read_from(&write(&image, &config)[..])
+                    .unwrap();
+                assert_eq!((read.dimx, read.dimy), (2, 1));
+                assert_eq!(read.output, config);
+                assert_eq!(read.comments, image.comments);
+                // Reading is exact, so writing it again gives the same file.
+                assert_eq!(write(&read, &config), write(&image, &config));
+            }
+        }
+        let read = PnmData::read_from(&b"P3 1 1 4 0 2 4"[..]).unwrap();
+        assert_eq!(read.rawdata, [Color::from_array([0.0, 0.5, 1.0, 0.0])]);
+
+        // Bitmaps, where 1 is black. Plain samples need not be separated.
+        let black = Color::default();
+        let white = Color::from_array([1.0, 1.0, 1.0, 0.0]);
+        let expected = [
+            [white, black, black, white, white, white, white, white, black],
+            [black, white, white, white, white, white, white, white, white],
+        ]
+        .concat();
+        for pbm in [
+            &b"P1\n# comment\n9 2\n0 1 1 0 0 0 0 0 1\n100000000\n"[..],
+            b"P4\n9 2\n\x60\x80\x80\x00",
+        ] {
+            let read = PnmData::read_from(pbm).unwrap();
+            assert_eq!((read.dimx, read.dimy), (9, 2));
+            assert_eq!((read.output.maxval, read.output.depth), (1, 1));
+            assert_eq!(read.rawdata, expected, "{pbm:?}");
+        }
+
+        for invalid in [
+            &b"P7\n"[..],
+            b"P6\n2 1\n255\n\0\0\0",
+            b"P2\n1 1\n3\n4\n",
+            b"P3\n1 1\n0\n0 0 0\n",
+            b"P5\n1 x\n255\n\0",
+            b"P1\n2 1\n0 2\n",
+            b"P4\n9 2\n\0\0\0",
+        ] {
+            assert!(PnmData::read_from(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_output_configs() {
+        let image = image();
+        for config in [
+            OutputConfig { maxval: 0, ..OutputConfig::default() },
+            OutputConfig { maxval: 65536, ..OutputConfig::default() },
+            OutputConfig { depth: 2, ..OutputConfig::default() },
+        ] {
+            let mut output = vec![];
+            assert!(image.write_to(&mut output, &config, None).is_err());
+            assert!(output.is_empty());
+        }
+    }
 }
@@ -1,19 +1,30 @@
 use std::{
+    cell::RefCell,
     future::Future,
+    io::Write,
     num::NonZeroUsize,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
     path::PathBuf,
     pin::Pin,
+    str::FromStr,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc, RwLock,
+        Arc, Mutex, PoisonError, RwLock,
     },
+    task::{Context, Poll, Waker},
 };
 
+use bitmap::BitMap;
 use getopt::{GetoptItem, Opt};
 
-use crate::{CommonData, CommonLockedData};
+use crate::{
+    error::{ConfigError, RunError},
+    output::RunIndex,
+    pnmdata::PnmData,
+    CommonData, CommonLockedData,
+};
 
-use self::file::FileProgressor;
+use self::file::{FileProgressor, FramesProgressor};
 
 mod file;
 #[cfg(feature = "framebuffer")]
@@ -22,48 +33,379 @@ mod framebuffer;
 mod sdl;
 mod text;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct ProgressData {
-    pub progress_interval: usize,
+    pub progress_interval: ProgressInterval,
     pub progress_count: usize,
+    /// `--sample-every`: write progress file frames each time this many more
+    /// pixels have been placed, instead of every `progress_interval`. Only
+    /// the file progressor uses this.
+    pub sample_every: Option<NonZeroUsize>,
+    /// How many progress intervals the text progressor's ETA is based on.
+    pub eta_window: NonZeroUsize,
+}
+
+/// A `--progressinterval` value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressInterval {
+    /// Report after every this many skipped barrier cycles.
+    Cycles(usize),
+    /// Report each time about this percentage of the image's pixels have
+    /// been placed since the last report, e.g. `1%`.
+    Percent(f64),
+    /// Report each time the number of pixels placed reaches a multiple of
+    /// this, for `--sample-every`.
+    Pixels(NonZeroUsize),
+}
+
+impl FromStr for ProgressInterval {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => {
+                    Ok(ProgressInterval::Percent(percent))
+                }
+                _ => Err(()),
+            },
+            None => s.parse().map(ProgressInterval::Cycles).map_err(|_| ()),
+        }
+    }
+}
+
+impl std::fmt::Display for ProgressInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgressInterval::Cycles(cycles) => write!(f, "{cycles}"),
+            ProgressInterval::Percent(percent) => write!(f, "{percent}%"),
+            ProgressInterval::Pixels(pixels) => write!(f, "{pixels} pixels"),
+        }
+    }
+}
+
+/// Decides at which barrier cycles a progressor reports, according to its
+/// `ProgressInterval`.
+#[derive(Debug, Clone)]
+pub struct ProgressThrottle {
+    interval: ProgressInterval,
+    /// Cycles skipped since the last report, for `Cycles`.
+    step_count: usize,
+    /// Pixels between reports, and the pixel count of the next report, for
+    /// `Percent` and `Pixels`.
+    step: usize,
+    next_threshold: usize,
+    /// The pixel count at the last report.
+    reported: usize,
+}
+
+impl ProgressThrottle {
+    pub fn new(interval: ProgressInterval, size: NonZeroUsize) -> Self {
+        let step = match interval {
+            ProgressInterval::Cycles(_) => 0,
+            ProgressInterval::Percent(percent) => {
+                let step = (size.get() as f64 * percent / 100.0).ceil();
+                (step as usize).max(1)
+            }
+            ProgressInterval::Pixels(pixels) => pixels.get(),
+        };
+        Self {
+            interval,
+            step_count: 0,
+            step,
+            next_threshold: step,
+            reported: 0,
+        }
+    }
+
+    /// Whether to report this cycle, with `pixels_placed` placed so far.
+    ///
+    /// If several thresholds were passed since the last cycle, this reports
+    /// once, and the next threshold is the first one after `pixels_placed`.
+    pub fn ready(&mut self, pixels_placed: usize) -> bool {
+        let ready = match self.interval {
+            ProgressInterval::Cycles(cycles) => {
+                if self.step_count >= cycles {
+                    self.step_count = 0;
+                    true
+                } else {
+                    self.step_count += 1;
+                    false
+                }
+            }
+            ProgressInterval::Percent(_) | ProgressInterval::Pixels(_) => {
+                if pixels_placed >= self.next_threshold {
+                    self.next_threshold =
+                        (pixels_placed / self.step + 1) * self.step;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        if ready {
+            self.reported = pixels_placed;
+        }
+        ready
+    }
+
+    /// Whether to report once more when generation finishes, with
+    /// `pixels_placed` placed in total: for `Percent` and `Pixels`, if
+    /// anything was placed since the last report, so that the final count is
+    /// always shown.
+    /// `Cycles` keeps reporting only on its own schedule.
+    pub fn finish(&mut self, pixels_placed: usize) -> bool {
+        match self.interval {
+            ProgressInterval::Cycles(_) => false,
+            ProgressInterval::Percent(_) | ProgressInterval::Pixels(_) => {
+                let ready = pixels_placed > self.reported;
+                self.reported = pixels_placed;
+                ready
+            }
+        }
+    }
+}
+
+/// A copy of the image for progressors that display it, so that they do not
+/// hold `CommonData::locked` (and hold up the generator) while doing so.
+///
+/// The supervisor brings this up to date after the generator reaches each
+/// barrier a, before the progressors pass it, so it can be read between
+/// barriers a and b just like the image itself.
+pub struct ImageSnapshot {
+    pub image: PnmData,
+    pub placed_pixels: BitMap,
+}
+
+impl ImageSnapshot {
+    fn new(locked: &CommonLockedData) -> Self {
+        Self {
+            image: locked.image.clone(),
+            placed_pixels: locked.placed_pixels.clone(),
+        }
+    }
+
+    /// Copies the pixels placed since the last update from `locked`.
+    fn update(&mut self, locked: &CommonLockedData) {
+        for &(y, x) in &locked.recently_placed {
+            self.image[(y, x)] = locked.image[(y, x)];
+            self.placed_pixels.set((y, x), true);
+        }
+        self.image.comments.clone_from(&locked.image.comments);
+    }
+
+    /// The color that pixel `(y, x)` is shown as: its own color if it has
+    /// been placed, or the background color otherwise.
+    #[cfg(feature = "framebuffer")]
+    pub fn displayed_color(
+        &self,
+        (y, x): (usize, usize),
+    ) -> crate::color::Color {
+        if self.placed_pixels.get((y, x)) {
+            self.image[(y, x)]
+        } else {
+            self.image.background
+        }
+    }
+
+    /// Like `CommonLockedData::write_image`.
+    pub fn write_image<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let placed = Some(&self.placed_pixels);
+        self.image.write_to(writer, &self.image.output, placed)
+    }
+}
+
+/// Makes a snapshot for the supervisor to keep up to date, if `needed`.
+fn make_snapshot(
+    needed: bool,
+    common_data: &CommonData,
+) -> Option<RwLock<ImageSnapshot>> {
+    needed.then(|| {
+        let locked = common_data.locked.read().unwrap();
+        RwLock::new(ImageSnapshot::new(&locked))
+    })
+}
+
+/// Brings `snapshot` (if any) up to date. Only the supervisor calls this,
+/// after the generator has reached barrier a and before the progressors
+/// pass it.
+fn update_snapshot(
+    snapshot: Option<&RwLock<ImageSnapshot>>,
+    common_data: &CommonData,
+) {
+    let Some(snapshot) = snapshot else { return };
+    // The generator may have panicked while holding the lock, and the image
+    // is still written in that case.
+    let locked =
+        common_data.locked.read().unwrap_or_else(PoisonError::into_inner);
+    let mut snapshot = snapshot.write().unwrap_or_else(PoisonError::into_inner);
+    snapshot.update(&locked);
 }
 
 /// CommonData, but with its own progress_barrier.
 /// The supervisor progressor handles the CommonData
 pub struct ProgressSupervisorData<'a> {
+    /// Only for quick, non-blocking peeks (with `try_read`); progressors that
+    /// display the image should use `snapshot`.
     pub locked: &'a RwLock<CommonLockedData>,
+    /// Present if the progressor's `needs_snapshot` returned `true`.
+    pub snapshot: Option<&'a RwLock<ImageSnapshot>>,
     pub dimy: NonZeroUsize,
     pub dimx: NonZeroUsize,
     pub size: NonZeroUsize,
-    pub progress_barrier: Arc<tokio::sync::Barrier>,
+    pub progress_barrier: ProgressBarrier,
     pub finished: &'a AtomicBool,
     pub pixels_placed: &'a AtomicUsize,
     pub pixels_generated: &'a AtomicUsize,
     pub rng_seed: u64,
+    /// Set when a progressor panics, so that the supervisor sets `finished`
+    /// once the generator is between barriers b and a, where changing it is
+    /// safe.
+    stop_requested: Arc<AtomicBool>,
+    /// `CommonData::progress_error`.
+    error: &'a Mutex<Option<RunError>>,
 }
 
+impl ProgressSupervisorData<'_> {
+    /// Reports that the progressor failed with `err`, which the run returns
+    /// once the image is written (only the first such error is kept). The
+    /// supervisor stops the generator, as for a panic, but the progressor
+    /// must keep waiting at the barriers until it sees `finished`.
+    pub fn fail(&self, err: RunError) {
+        log::error!("progressor failed: {err}");
+        let mut error =
+            self.error.lock().unwrap_or_else(PoisonError::into_inner);
+        error.get_or_insert(err);
+        self.stop_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Runs `func`'s progressor to completion. If it panics, this takes its
+    /// place at the barriers, and has the supervisor stop the generator,
+    /// before resuming the panic.
+    async fn run(&self, func: SupervisedProgressor, data: ProgressData) {
+        let mut fut = func(data, self);
+        let result = std::future::poll_fn(|cx| {
+            match catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+                Ok(poll) => poll.map(Ok),
+                Err(payload) => Poll::Ready(Err(payload)),
+            }
+        })
+        .await;
+        let Err(payload) = result else { return };
+        log::error!("progressor panicked: {}", crate::panic_message(&*payload));
+
+        if !self.progress_barrier.between() {
+            self.progress_barrier.wait().await;
+        }
+        // Between barriers a and b, `finished` cannot change, and if it is
+        // already set then everyone else is exiting.
+        if !self.finished.load(Ordering::Acquire) {
+            self.stop_requested.store(true, Ordering::SeqCst);
+            self.progress_barrier.wait().await;
+            // The supervisor sets `finished` after barrier b, so everyone
+            // exits after this barrier a.
+            self.progress_barrier.wait().await;
+        }
+        resume_unwind(payload);
+    }
+}
+
+/// One progressor's handle to the barrier it shares with the other
+/// progressors and the supervisor, which keeps track of which barrier (a or
+/// b) is next, so that it can be taken over if the progressor panics.
+pub struct ProgressBarrier {
+    /// `None` for `--single-thread`, where waiting only yields back to the
+    /// generator (see `SteppedProgress`).
+    barrier: Option<Arc<tokio::sync::Barrier>>,
+    waits: AtomicUsize,
+}
+
+impl ProgressBarrier {
+    fn new(barrier: Arc<tokio::sync::Barrier>) -> Self {
+        Self { barrier: Some(barrier), waits: AtomicUsize::new(0) }
+    }
+
+    fn stepped() -> Self {
+        Self { barrier: None, waits: AtomicUsize::new(0) }
+    }
+
+    pub async fn wait(&self) {
+        match &self.barrier {
+            Some(barrier) => {
+                barrier.wait().await;
+            }
+            None => {
+                let mut yielded = false;
+                std::future::poll_fn(|cx| {
+                    if yielded {
+                        return Poll::Ready(());
+                    }
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })
+                .await
+            }
+        }
+        self.waits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Whether the progressor is between barriers a and b.
+    fn between(&self) -> bool {
+        self.waits.load(Ordering::SeqCst) % 2 == 1
+    }
+}
+
+type SupervisedProgressor = Box<
+    dyn Send
+        + for<'a> FnOnce(
+            ProgressData,
+            &'a ProgressSupervisorData<'a>,
+        ) -> Pin<Box<dyn Future<Output = ()> + 'a>>,
+>;
+
 pub trait Progressor: Send {
+    /// Whether this progressor does nothing, so that it need not be run at all,
+    /// and the generator can skip the progress barriers.
+    fn is_noop(&self) -> bool {
+        false
+    }
+
+    /// Whether this progressor reads `ProgressSupervisorData::snapshot`, so
+    /// the supervisor needs to keep one.
+    fn needs_snapshot(&self) -> bool {
+        false
+    }
+
     /// Caller should run this in a new thread
     fn run_alone(&self, data: ProgressData, common_data: Arc<CommonData>) {
         let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
         let progress_barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let snapshot =
+            Arc::new(make_snapshot(self.needs_snapshot(), &common_data));
         let fut = {
             let common_data = common_data.clone();
             let progress_barrier = progress_barrier.clone();
+            let stop_requested = stop_requested.clone();
+            let snapshot = snapshot.clone();
             let func = self.make_supervised_progressor();
             async move {
                 let supervisor_data = ProgressSupervisorData {
                     locked: &common_data.locked,
+                    snapshot: (*snapshot).as_ref(),
                     dimy: common_data.dimy,
                     dimx: common_data.dimx,
                     size: common_data.size,
-                    progress_barrier,
+                    progress_barrier: ProgressBarrier::new(progress_barrier),
                     finished: &common_data.finished,
                     pixels_placed: &common_data.pixels_placed,
                     pixels_generated: &common_data.pixels_generated,
                     rng_seed: common_data.rng_seed,
+                    stop_requested,
+                    error: &common_data.progress_error,
                 };
-                func(data, &supervisor_data).await;
+                supervisor_data.run(func, data).await;
             }
         };
 
@@ -77,10 +419,11 @@ pub trait Progressor: Send {
                         log::trace!(target: "barriers", "before progress barrier a");
                         common_data.progress_barrier.wait();
                         log::trace!(target: "barriers", "mid progress barrier a");
+                        update_snapshot((*snapshot).as_ref(), &common_data);
                         progress_barrier.wait().await;
                         log::trace!(target: "barriers", "after progress barrier a");
 
-                        if common_data.finished.load(Ordering::SeqCst) {
+                        if common_data.finished.load(Ordering::Acquire) {
                             // Only read this betwee barriers, so we know generator thread wont change it under us
                             log::trace!("supervisor breaking loop");
                             break;
@@ -96,15 +439,27 @@ pub trait Progressor: Send {
                         log::trace!(target: "barriers", "mid progress barrier b");
                         common_data.progress_barrier.wait();
                         log::trace!(target: "barriers", "after progress barrier b");
+                        if stop_requested.load(Ordering::SeqCst) {
+                            common_data.finished.store(true, Ordering::Release);
+                        }
                     }
                     log::trace!("joining task");
-                    task.await.expect("task failed");
+                    if let Err(err) = task.await {
+                        // Keep the original panic payload for `main`.
+                        resume_unwind(err.into_panic());
+                    }
                     log::trace!("supervisor exiting");
                 })
                 .await;
         });
     }
 
+    /// The progressors to run for `--single-thread`: just this one, or each
+    /// of a supervisor's.
+    fn supervised_progressors(&self) -> Vec<SupervisedProgressor> {
+        vec![self.make_supervised_progressor()]
+    }
+
     /// Caller should call this function in another thread, and keep its result
     /// on that thread
     fn make_supervised_progressor(
@@ -119,11 +474,130 @@ pub trait Progressor: Send {
     >;
 }
 
+thread_local! {
+    /// The progressors for `--single-thread`, from `start_stepped`.
+    static STEPPED: RefCell<Option<SteppedProgress>> =
+        const { RefCell::new(None) };
+}
+
+/// Progressors running on the generator's thread, for `--single-thread`.
+///
+/// Their `ProgressBarrier`s only yield, so each time the generator reaches
+/// `CommonData::progress_barrier`, polling them once runs them from one
+/// barrier to the next, as if the generator had waited there for them.
+struct SteppedProgress {
+    /// `None` once every progressor has finished.
+    fut: Option<Pin<Box<dyn Future<Output = ()>>>>,
+    snapshot: Arc<Option<RwLock<ImageSnapshot>>>,
+    /// How many times the generator has reached the barrier, so that the
+    /// snapshot is updated at each barrier a.
+    steps: usize,
+}
+
+impl SteppedProgress {
+    fn poll(&mut self) {
+        let Some(fut) = &mut self.fut else { return };
+        let mut cx = Context::from_waker(Waker::noop());
+        if fut.as_mut().poll(&mut cx).is_ready() {
+            self.fut = None;
+        }
+    }
+}
+
+/// Starts `progressor` on this thread for `--single-thread`, running it up to
+/// its first barrier. From then on, the generator runs it with `step`, until
+/// `stop_stepped`.
+pub fn start_stepped(
+    progressor: &dyn Progressor,
+    data: ProgressData,
+    common_data: Arc<CommonData>,
+) {
+    let funcs = progressor.supervised_progressors();
+    let snapshot =
+        Arc::new(make_snapshot(progressor.needs_snapshot(), &common_data));
+    let fut = {
+        let snapshot = snapshot.clone();
+        async move {
+            let supervisor_data = funcs
+                .iter()
+                .map(|_| ProgressSupervisorData {
+                    locked: &common_data.locked,
+                    snapshot: (*snapshot).as_ref(),
+                    dimy: common_data.dimy,
+                    dimx: common_data.dimx,
+                    size: common_data.size,
+                    progress_barrier: ProgressBarrier::stepped(),
+                    finished: &common_data.finished,
+                    pixels_placed: &common_data.pixels_placed,
+                    pixels_generated: &common_data.pixels_generated,
+                    rng_seed: common_data.rng_seed,
+                    // Only read by the supervisor, which does not run here,
+                    // so a panic is not caught and a failure does not stop
+                    // the generator.
+                    stop_requested: Arc::default(),
+                    error: &common_data.progress_error,
+                })
+                .collect::<Vec<_>>();
+            let mut futs = funcs
+                .into_iter()
+                .zip(&supervisor_data)
+                .map(|(func, data_ref)| func(data.clone(), data_ref))
+                .collect::<Vec<_>>();
+            std::future::poll_fn(|cx| {
+                futs.retain_mut(|fut| fut.as_mut().poll(cx).is_pending());
+                match futs.is_empty() {
+                    true => Poll::Ready(()),
+                    false => Poll::Pending,
+                }
+            })
+            .await
+        }
+    };
+    let mut stepped =
+        SteppedProgress { fut: Some(Box::pin(fut)), snapshot, steps: 0 };
+    stepped.poll();
+    STEPPED.with_borrow_mut(|slot| *slot = Some(stepped));
+}
+
+/// Runs the progressors from `start_stepped` up to their next barrier. The
+/// generator calls this instead of waiting at `CommonData::progress_barrier`.
+pub fn step(common_data: &CommonData) {
+    STEPPED.with_borrow_mut(|stepped| {
+        let stepped = stepped.as_mut().expect("start_stepped was called");
+        if stepped.steps % 2 == 0 {
+            update_snapshot((*stepped.snapshot).as_ref(), common_data);
+        }
+        stepped.steps += 1;
+        stepped.poll();
+    });
+}
+
+/// Drops the progressors from `start_stepped`, even if they did not finish
+/// (e.g. because the generator panicked).
+pub fn stop_stepped() {
+    STEPPED.with_borrow_mut(|stepped| *stepped = None);
+}
+
 pub struct ProgressSupervisor {
     progressors: Vec<Box<dyn Progressor + Send>>,
 }
 
 impl Progressor for ProgressSupervisor {
+    fn is_noop(&self) -> bool {
+        self.progressors.iter().all(|progressor| progressor.is_noop())
+    }
+
+    fn needs_snapshot(&self) -> bool {
+        self.progressors.iter().any(|progressor| progressor.needs_snapshot())
+    }
+
+    fn supervised_progressors(&self) -> Vec<SupervisedProgressor> {
+        self.progressors
+            .iter()
+            .flat_map(|progressor| progressor.supervised_progressors())
+            .collect()
+    }
+
     fn make_supervised_progressor(
         &self,
     ) -> Box<
@@ -142,27 +616,36 @@ impl Progressor for ProgressSupervisor {
     fn run_alone(&self, data: ProgressData, common_data: Arc<CommonData>) {
         let progress_barrier =
             Arc::new(tokio::sync::Barrier::new(self.progressors.len() + 1));
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let snapshot = make_snapshot(self.needs_snapshot(), &common_data);
+        let snapshot = snapshot.as_ref();
 
         std::thread::scope(|scope| {
             for progressor in &self.progressors {
                 scope.spawn({
                     let common_data = common_data.clone();
                     let progress_barrier = progress_barrier.clone();
+                    let stop_requested = stop_requested.clone();
                     let data = data.clone();
                     let func = progressor.make_supervised_progressor();
                     move || {
                         let supervisor_data = ProgressSupervisorData {
                             locked: &common_data.locked,
+                            snapshot,
                             dimy: common_data.dimy,
                             dimx: common_data.dimx,
                             size: common_data.size,
-                            progress_barrier,
+                            progress_barrier: ProgressBarrier::new(
+                                progress_barrier,
+                            ),
                             finished: &common_data.finished,
                             pixels_placed: &common_data.pixels_placed,
                             pixels_generated: &common_data.pixels_generated,
                             rng_seed: common_data.rng_seed,
+                            stop_requested,
+                            error: &common_data.progress_error,
                         };
-                        let fut = func(data, &supervisor_data);
+                        let fut = supervisor_data.run(func, data);
                         let rt = tokio::runtime::Builder::new_current_thread()
                             .build()
                             .unwrap();
@@ -179,9 +662,10 @@ impl Progressor for ProgressSupervisor {
                     log::trace!(target: "barriers", "before progress barrier a");
                     common_data.progress_barrier.wait();
                     log::trace!(target: "barriers", "mid progress barrier a");
+                    update_snapshot(snapshot, &common_data);
                     progress_barrier.wait().await;
                     log::trace!(target: "barriers", "after progress barrier a");
-                    if common_data.finished.load(Ordering::SeqCst) {
+                    if common_data.finished.load(Ordering::Acquire) {
                         break;
                     }
 
@@ -191,6 +675,9 @@ impl Progressor for ProgressSupervisor {
                     log::trace!(target: "barriers", "mid progress barrier b");
                     common_data.progress_barrier.wait();
                     log::trace!(target: "barriers", "after progress barrier b");
+                    if stop_requested.load(Ordering::SeqCst) {
+                        common_data.finished.store(true, Ordering::Release);
+                    }
                 }
                 log::trace!("supervisor exiting");
             })
@@ -201,6 +688,10 @@ impl Progressor for ProgressSupervisor {
 pub struct NoOpProgressor;
 
 impl Progressor for NoOpProgressor {
+    fn is_noop(&self) -> bool {
+        true
+    }
+
     fn make_supervised_progressor(
         &self,
     ) -> Box<
@@ -215,7 +706,7 @@ impl Progressor for NoOpProgressor {
             Box::pin(async move {
                 loop {
                     common_data.progress_barrier.wait().await;
-                    if common_data.finished.load(Ordering::SeqCst) {
+                    if common_data.finished.load(Ordering::Acquire) {
                         break;
                     }
                     common_data.progress_barrier.wait().await;
@@ -228,10 +719,14 @@ impl Progressor for NoOpProgressor {
 pub fn opts() -> impl IntoIterator<Item = Opt> {
     [
         Opt::short_long('P', "progressfile", getopt::HasArgument::Yes),
+        Opt::long("frames", getopt::HasArgument::Yes),
         Opt::short_long('d', "defaultprogressfile", getopt::HasArgument::No),
         Opt::short_long('T', "progresstext", getopt::HasArgument::No),
         Opt::short_long('I', "progressinterval", getopt::HasArgument::Yes),
         Opt::short_long('M', "progresscount", getopt::HasArgument::Yes),
+        Opt::long("eta-window", getopt::HasArgument::Yes),
+        Opt::long("sample-every", getopt::HasArgument::Yes),
+        Opt::long("bench", getopt::HasArgument::No),
         #[cfg(feature = "sdl2")]
         Opt::long("SDL", getopt::HasArgument::No),
         Opt::long("wait", getopt::HasArgument::Yes),
@@ -240,53 +735,139 @@ pub fn opts() -> impl IntoIterator<Item = Opt> {
     ]
 }
 
+/// Makes a `TextProgressor` that prints to stderr, labeled with the run index
+/// if there is more than one run.
+fn text_progressor(run: RunIndex) -> Box<dyn Progressor + Send> {
+    let label = run.label();
+    Box::new(text::TextProgressor::new(move |s| match &label {
+        Some(label) => eprintln!("[{}] {}", label, s),
+        None => eprintln!("{}", s),
+    }))
+}
+
+/// A progressor requested on the command line, before it is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressorKind {
+    /// `--progressfile`, with the run index already applied to the path.
+    File(PathBuf),
+    /// `--frames`, with the run index already applied to the directory.
+    Frames(PathBuf),
+    Text,
+    #[cfg(feature = "sdl2")]
+    Sdl,
+    #[cfg(feature = "framebuffer")]
+    Framebuffer(PathBuf),
+}
+
+impl ProgressorKind {
+    /// The option that requests this progressor.
+    fn option(&self) -> &'static str {
+        match self {
+            ProgressorKind::File(_) => "progressfile",
+            ProgressorKind::Frames(_) => "frames",
+            ProgressorKind::Text => "progresstext",
+            #[cfg(feature = "sdl2")]
+            ProgressorKind::Sdl => "SDL",
+            #[cfg(feature = "framebuffer")]
+            ProgressorKind::Framebuffer(_) => "framebuffer",
+        }
+    }
+}
+
+/// The validated progress options, before any progress file is opened.
+#[derive(Debug, Clone)]
+pub struct ProgressSettings {
+    /// Empty if no progressor was requested, in which case text progress is
+    /// shown (unless `bench` is set).
+    pub progressors: Vec<ProgressorKind>,
+    /// `--bench`: show no progress at all, so that the generator can run
+    /// without stopping at the progress barriers.
+    pub bench: bool,
+    pub data: ProgressData,
+}
+
 pub fn handle_opts(
     opts: &[GetoptItem<'_>],
-) -> (Box<dyn Progressor + Send>, ProgressData) {
-    let mut progressors: Vec<Box<dyn Progressor + Send>> = vec![];
+    run: RunIndex,
+) -> Result<ProgressSettings, ConfigError> {
+    let mut progressors = vec![];
     let mut progress_interval = None;
     let mut progress_count = None;
+    let mut eta_window = None;
+    let mut sample_every = None;
+    let mut bench = false;
     for opt in opts {
         match opt {
             GetoptItem::Opt { opt, arg: Some(filename) }
                 if opt.is_long("progressfile") =>
             {
-                let file = std::fs::OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(*filename)
-                    .unwrap();
-                progressors.push(Box::new(FileProgressor::new(file)));
+                progressors.push(ProgressorKind::File(run.path(filename)));
+            }
+            GetoptItem::Opt { opt, arg: Some(dir) }
+                if opt.is_long("frames") =>
+            {
+                progressors.push(ProgressorKind::Frames(run.path(dir)));
             }
             GetoptItem::Opt { opt, arg: None }
                 if opt.is_long("defaultprogressfile") =>
             {
-                todo!(
-                    "open the default filename and make progress::file::FileProgressor"
-                )
+                return Err(ConfigError::Unsupported {
+                    option: "defaultprogressfile",
+                });
             }
             GetoptItem::Opt { opt, arg: None }
                 if opt.is_long("progresstext") =>
             {
-                progressors.push(Box::new(text::TextProgressor::new(|s| {
-                    eprintln!("{}", s);
-                })));
+                progressors.push(ProgressorKind::Text);
             }
             GetoptItem::Opt { opt, arg: Some(progress_interval_str) }
                 if opt.is_long("progressinterval") =>
             {
                 progress_interval =
-                    Some(progress_interval_str.parse().unwrap());
+                    Some(progress_interval_str.parse().map_err(|_| {
+                        ConfigError::InvalidValue {
+                            option: "progressinterval",
+                            value: progress_interval_str.to_string(),
+                        }
+                    })?);
             }
             GetoptItem::Opt { opt, arg: Some(progress_count_str) }
                 if opt.is_long("progresscount") =>
             {
-                progress_count = Some(progress_count_str.parse().unwrap());
+                progress_count =
+                    Some(progress_count_str.parse().map_err(|_| {
+                        ConfigError::InvalidValue {
+                            option: "progresscount",
+                            value: progress_count_str.to_string(),
+                        }
+                    })?);
+            }
+            GetoptItem::Opt { opt, arg: Some(eta_window_str) }
+                if opt.is_long("eta-window") =>
+            {
+                eta_window = Some(eta_window_str.parse().map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "eta-window",
+                        value: eta_window_str.to_string(),
+                    }
+                })?);
+            }
+            GetoptItem::Opt { opt, arg: Some(sample_every_str) }
+                if opt.is_long("sample-every") =>
+            {
+                sample_every = Some(sample_every_str.parse().map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "sample-every",
+                        value: sample_every_str.to_string(),
+                    }
+                })?);
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("bench") => {
+                bench = true;
             }
             #[cfg(feature = "sdl2")]
             GetoptItem::Opt { opt, arg: None } if opt.is_long("SDL") => {
-                progressors.push(Box::new(sdl::Sdl2Progressor {}));
+                progressors.push(ProgressorKind::Sdl);
             }
             #[cfg(not(feature = "sdl2"))]
             GetoptItem::Opt { opt, arg: None } if opt.is_long("SDL") => {
@@ -297,14 +878,12 @@ pub fn handle_opts(
             GetoptItem::Opt { opt, arg: Some(_wait_time_str) }
                 if opt.is_long("wait") =>
             {
-                todo!("figure out wait handling")
+                return Err(ConfigError::Unsupported { option: "wait" });
             }
             #[cfg(feature = "framebuffer")]
             GetoptItem::Opt { opt, arg } if opt.is_long("framebuffer") => {
                 let fb_path = PathBuf::from(arg.unwrap_or("/dev/fb0"));
-                progressors.push(Box::new(
-                    framebuffer::FramebufferProgressor { fb_path },
-                ));
+                progressors.push(ProgressorKind::Framebuffer(fb_path));
             }
             #[cfg(not(feature = "framebuffer"))]
             GetoptItem::Opt { opt, .. } if opt.is_long("framebuffer") => {
@@ -316,21 +895,163 @@ pub fn handle_opts(
         }
     }
 
+    if let (true, Some(kind)) = (bench, progressors.first()) {
+        return Err(ConfigError::Conflict {
+            option: "bench",
+            other: kind.option(),
+        });
+    }
+
     let data = ProgressData {
-        progress_interval: progress_interval.unwrap_or(1024),
+        progress_interval: progress_interval
+            .unwrap_or(ProgressInterval::Cycles(1024)),
         progress_count: progress_count.unwrap_or(1),
+        sample_every,
+        eta_window: eta_window.unwrap_or(NonZeroUsize::new(8).unwrap()),
     };
 
-    let progressor = if progressors.len() == 0 {
-        log::trace!("no progressor requested, just doing text");
-        Box::new(text::TextProgressor::new(|s| {
-            eprintln!("{}", s);
-        }))
-    } else if progressors.len() == 1 {
-        progressors.pop().unwrap()
-    } else {
-        Box::new(ProgressSupervisor { progressors })
-    };
+    Ok(ProgressSettings { progressors, bench, data })
+}
+
+impl ProgressSettings {
+    /// Creates the progressors, opening (and truncating) any progress files.
+    pub fn build(
+        self,
+        run: RunIndex,
+    ) -> Result<(Box<dyn Progressor + Send>, ProgressData), ConfigError> {
+        let mut progressors = self
+            .progressors
+            .into_iter()
+            .map(|kind| make_progressor(kind, run))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let progressor: Box<dyn Progressor + Send> = if self.bench {
+            Box::new(NoOpProgressor)
+        } else if progressors.len() == 0 {
+            log::trace!("no progressor requested, just doing text");
+            text_progressor(run)
+        } else if progressors.len() == 1 {
+            progressors.pop().unwrap()
+        } else {
+            Box::new(ProgressSupervisor { progressors })
+        };
+
+        Ok((progressor, self.data))
+    }
+}
+
+fn make_progressor(
+    kind: ProgressorKind,
+    run: RunIndex,
+) -> Result<Box<dyn Progressor + Send>, ConfigError> {
+    Ok(match kind {
+        ProgressorKind::File(path) => {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .map_err(|source| ConfigError::OpenFile {
+                    option: "progressfile",
+                    path: path.display().to_string(),
+                    source,
+                })?;
+            Box::new(FileProgressor::new(file))
+        }
+        ProgressorKind::Frames(dir) => {
+            std::fs::create_dir_all(&dir).map_err(|source| {
+                ConfigError::OpenFile {
+                    option: "frames",
+                    path: dir.display().to_string(),
+                    source,
+                }
+            })?;
+            Box::new(FramesProgressor { dir })
+        }
+        ProgressorKind::Text => text_progressor(run),
+        #[cfg(feature = "sdl2")]
+        ProgressorKind::Sdl => {
+            let title = match run.label() {
+                Some(label) => format!("imagegen-rs ({label})"),
+                None => "imagegen-rs".to_string(),
+            };
+            Box::new(sdl::Sdl2Progressor { title })
+        }
+        #[cfg(feature = "framebuffer")]
+        ProgressorKind::Framebuffer(fb_path) => {
+            Box::new(framebuffer::FramebufferProgressor { fb_path })
+        }
+    })
+}
 
-    (progressor, data)
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::{ProgressInterval, ProgressThrottle};
+
+    #[test]
+    fn parse_interval() {
+        let parse = |s: &str| s.parse::<ProgressInterval>();
+        assert_eq!(parse("1024"), Ok(ProgressInterval::Cycles(1024)));
+        assert_eq!(parse("0"), Ok(ProgressInterval::Cycles(0)));
+        assert_eq!(parse("1%"), Ok(ProgressInterval::Percent(1.0)));
+        assert_eq!(parse("0.5%"), Ok(ProgressInterval::Percent(0.5)));
+        assert_eq!(parse("100%"), Ok(ProgressInterval::Percent(100.0)));
+        for invalid in ["", "%", "0%", "-1%", "101%", "nan%", "1.5", "x"] {
+            assert_eq!(parse(invalid), Err(()), "{invalid:?}");
+        }
+        assert_eq!(ProgressInterval::Percent(2.5).to_string(), "2.5%");
+        assert_eq!(ProgressInterval::Cycles(7).to_string(), "7");
+    }
+
+    #[test]
+    fn cycle_throttle() {
+        let size = NonZeroUsize::new(100).unwrap();
+        let mut throttle =
+            ProgressThrottle::new(ProgressInterval::Cycles(2), size);
+        let reports: Vec<bool> =
+            (0..7).map(|cycle| throttle.ready(cycle * 50)).collect();
+        assert_eq!(reports, [false, false, true, false, false, true, false]);
+        assert!(!throttle.finish(400));
+    }
+
+    #[test]
+    fn percent_throttle() {
+        let size = NonZeroUsize::new(1000).unwrap();
+        let mut throttle =
+            ProgressThrottle::new(ProgressInterval::Percent(1.0), size);
+        assert!(!throttle.ready(0));
+        assert!(!throttle.ready(9));
+        assert!(throttle.ready(10));
+        assert!(!throttle.ready(19));
+        // Several thresholds at once report once, and the next threshold is
+        // the one after.
+        assert!(throttle.ready(57));
+        assert!(!throttle.ready(59));
+        assert!(throttle.ready(60));
+        // Generation finished between thresholds: report the final count.
+        assert!(throttle.finish(63));
+        assert!(!throttle.finish(63));
+
+        // Finishing exactly at a report does not repeat it.
+        let mut throttle =
+            ProgressThrottle::new(ProgressInterval::Percent(50.0), size);
+        assert!(throttle.ready(1000));
+        assert!(!throttle.finish(1000));
+
+        // Every 100 pixels, however many are placed each cycle.
+        let pixels = ProgressInterval::Pixels(NonZeroUsize::new(100).unwrap());
+        let mut throttle = ProgressThrottle::new(pixels, size);
+        let reports = [40, 99, 100, 150, 420, 450, 500]
+            .map(|placed| throttle.ready(placed));
+        assert_eq!(reports, [false, false, true, false, true, false, true]);
+
+        // Tiny percentages still need at least one pixel per report.
+        let mut throttle =
+            ProgressThrottle::new(ProgressInterval::Percent(0.01), size);
+        assert!(!throttle.ready(0));
+        assert!(throttle.ready(1));
+        assert!(throttle.ready(2));
+    }
 }
@@ -1,10 +1,15 @@
 use std::{
     io::{BufWriter, Write},
+    path::PathBuf,
     pin::Pin,
     sync::{atomic::Ordering, Arc, Mutex},
 };
 
-use super::{ProgressData, ProgressSupervisorData, Progressor};
+use super::{
+    ImageSnapshot, ProgressData, ProgressInterval, ProgressSupervisorData,
+    ProgressThrottle, Progressor,
+};
+use crate::error::RunError;
 
 pub struct FileProgressor<W: Write> {
     /// TODO: use tokio AsyncWrite
@@ -19,7 +24,55 @@ impl<W: Write> FileProgressor<W> {
     }
 }
 
+/// Calls `write_frame` with the snapshot once per progress interval, then
+/// with the final image, and then `progress_count` more times with it.
+async fn write_frames(
+    progress_data: ProgressData,
+    common_data: &ProgressSupervisorData<'_>,
+    mut write_frame: impl FnMut(&ImageSnapshot),
+) {
+    let ProgressData {
+        progress_interval, progress_count, sample_every, ..
+    } = progress_data;
+    let ProgressSupervisorData {
+        snapshot,
+        ref progress_barrier,
+        finished,
+        pixels_placed,
+        size,
+        ..
+    } = *common_data;
+    let snapshot = snapshot.expect("needs_snapshot is true");
+    let interval =
+        sample_every.map_or(progress_interval, ProgressInterval::Pixels);
+    let mut throttle = ProgressThrottle::new(interval, size);
+    loop {
+        log::trace!(target: "barriers", "before progress barrier a");
+        progress_barrier.wait().await;
+        log::trace!(target: "barriers", "after progress barrier a");
+
+        if throttle.ready(pixels_placed.load(Ordering::Relaxed)) {
+            write_frame(&snapshot.read().unwrap());
+        }
+
+        if finished.load(Ordering::Acquire) {
+            break;
+        }
+        log::trace!(target: "barriers", "before progress barrier b");
+        progress_barrier.wait().await;
+        log::trace!(target: "barriers", "after progress barrier b");
+    }
+    let snapshot = snapshot.read().unwrap();
+    for _ in 0..=progress_count {
+        write_frame(&snapshot);
+    }
+}
+
 impl<W: Write + Send + 'static> Progressor for FileProgressor<W> {
+    fn needs_snapshot(&self) -> bool {
+        true
+    }
+
     fn make_supervised_progressor(
         &self,
     ) -> Box<
@@ -33,47 +86,71 @@ impl<W: Write + Send + 'static> Progressor for FileProgressor<W> {
 
         Box::new(move |progress_data, common_data| {
             Box::pin(async move {
-                let ProgressData {
-                    progress_interval,
-                    progress_count,
-                } = progress_data;
-                let ProgressSupervisorData {
-                    locked,
-                    ref progress_barrier,
-                    finished,
-                    ..
-                } = *common_data;
                 let mut writer = writer.lock().unwrap();
-                let mut step_count = 0;
-                loop {
-                    log::trace!(target: "barriers", "before progress barrier a");
-                    progress_barrier.wait().await;
-                    log::trace!(target: "barriers", "after progress barrier a");
+                write_frames(progress_data, common_data, |snapshot| {
+                    snapshot.write_image(&mut *writer).unwrap();
+                    writer.flush().unwrap();
+                })
+                .await;
+            })
+        })
+    }
+}
 
-                    if step_count >= progress_interval {
-                        step_count = 0;
-                        let locked = locked.read().unwrap();
-                        locked.image.write_to(&mut *writer).unwrap();
-                        writer.flush().unwrap();
-                    } else {
-                        step_count += 1;
-                    }
+/// Like `FileProgressor`, but writes each frame to its own file in `dir`,
+/// numbered from `frame-00001.ppm` (or the extension for the image's output
+/// format), for `--frames`.
+///
+/// If a frame cannot be written, the progressor fails, and writes no more
+/// frames.
+pub struct FramesProgressor {
+    /// Must already exist.
+    pub dir: PathBuf,
+}
+
+impl Progressor for FramesProgressor {
+    fn needs_snapshot(&self) -> bool {
+        true
+    }
+
+    fn make_supervised_progressor(
+        &self,
+    ) -> Box<
+        dyn Send
+            + for<'a> FnOnce(
+                super::ProgressData,
+                &'a super::ProgressSupervisorData<'a>,
+            ) -> Pin<Box<dyn std::future::Future<Output = ()> + 'a>>,
+    > {
+        let dir = self.dir.clone();
 
-                    if finished.load(Ordering::SeqCst) {
-                        break;
+        Box::new(move |progress_data, common_data| {
+            Box::pin(async move {
+                let mut frame = 0usize;
+                let mut failed = false;
+                write_frames(progress_data, common_data, |snapshot| {
+                    if failed {
+                        return;
+                    }
+                    frame += 1;
+                    let extension = snapshot.image.output.extension();
+                    let name = format!("frame-{frame:05}.{extension}");
+                    let path = dir.join(name);
+                    let result =
+                        std::fs::File::create(&path).and_then(|file| {
+                            let mut writer = BufWriter::new(file);
+                            snapshot.write_image(&mut writer)?;
+                            writer.flush()
+                        });
+                    if let Err(source) = result {
+                        failed = true;
+                        common_data.fail(RunError::Progress {
+                            destination: format!("{path:?}"),
+                            source,
+                        });
                     }
-                    log::trace!(target: "barriers", "before progress barrier b");
-                    progress_barrier.wait().await;
-                    log::trace!(target: "barriers", "after progress barrier b");
-                }
-                let locked = locked.read().unwrap();
-                locked.image.write_to(&mut *writer).unwrap();
-                writer.flush().unwrap();
-                let mut data = vec![];
-                locked.image.write_to(&mut data).unwrap();
-                for _ in 0..progress_count {
-                    writer.write_all(&data).unwrap();
-                }
+                })
+                .await;
             })
         })
     }
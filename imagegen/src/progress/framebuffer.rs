@@ -2,14 +2,13 @@ use std::{
     fs::File,
     io::Error,
     mem::MaybeUninit,
-    os::fd::AsRawFd,
+    os::fd::{AsRawFd, RawFd},
     path::PathBuf,
     pin::Pin,
-    simd::{num::SimdFloat, simd_swizzle},
     sync::atomic::Ordering,
 };
 
-use crate::{color::Color, progress::NoOpProgressor};
+use crate::{pnmdata::quantize_8bit, progress::NoOpProgressor};
 
 use super::Progressor;
 
@@ -19,6 +18,90 @@ pub struct FramebufferProgressor {
     pub(crate) fb_path: PathBuf,
 }
 
+/// Where one color channel is within a framebuffer pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelLayout {
+    /// The position of the channel's least significant bit.
+    pub offset: u32,
+    /// How many bits the channel has.
+    pub length: u32,
+}
+
+impl ChannelLayout {
+    fn from_raw(bitfield: &raw::fb_bitfield) -> Self {
+        ChannelLayout { offset: bitfield.offset, length: bitfield.length }
+    }
+}
+
+/// The parts of a framebuffer's fixed and variable screen information that
+/// are needed to draw to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    /// The size of the framebuffer memory, in bytes (`smem_len`).
+    pub len: usize,
+    /// The size of each row, in bytes (`line_length`).
+    pub stride: usize,
+    /// The width, in pixels (`xres_virtual`).
+    pub width: usize,
+    /// The height, in pixels (`yres_virtual`).
+    pub height: usize,
+    pub bits_per_pixel: u32,
+    pub red: ChannelLayout,
+    pub green: ChannelLayout,
+    pub blue: ChannelLayout,
+}
+
+impl FramebufferInfo {
+    /// Reads the screen information of the framebuffer device open as `fd`.
+    pub fn query(fd: RawFd) -> Result<Self, Error> {
+        let mut finfo = MaybeUninit::<raw::fb_fix_screeninfo>::uninit();
+        let mut vinfo = MaybeUninit::<raw::fb_var_screeninfo>::uninit();
+        let context = |what: &str| {
+            let err = Error::last_os_error();
+            Error::new(err.kind(), format!("{what} information: {err}"))
+        };
+        unsafe {
+            if libc::ioctl(fd, raw::FBIOGET_FSCREENINFO, finfo.as_mut_ptr())
+                != 0
+            {
+                return Err(context("fixed"));
+            }
+            if libc::ioctl(fd, raw::FBIOGET_VSCREENINFO, vinfo.as_mut_ptr())
+                != 0
+            {
+                return Err(context("variable"));
+            }
+            Ok(Self::from_raw(&finfo.assume_init(), &vinfo.assume_init()))
+        }
+    }
+
+    /// Whether pixels are 32 bits, with 8-bit blue, green, and red channels
+    /// in that order from the least significant byte, which is the only
+    /// format the framebuffer progressor writes.
+    pub fn is_bgr32(&self) -> bool {
+        let channel = |offset| ChannelLayout { offset, length: 8 };
+        self.bits_per_pixel == 32
+            && (self.red, self.green, self.blue)
+                == (channel(16), channel(8), channel(0))
+    }
+
+    fn from_raw(
+        finfo: &raw::fb_fix_screeninfo,
+        vinfo: &raw::fb_var_screeninfo,
+    ) -> Self {
+        FramebufferInfo {
+            len: finfo.smem_len as usize,
+            stride: finfo.line_length as usize,
+            width: vinfo.xres_virtual as usize,
+            height: vinfo.yres_virtual as usize,
+            bits_per_pixel: vinfo.bits_per_pixel,
+            red: ChannelLayout::from_raw(&vinfo.red),
+            green: ChannelLayout::from_raw(&vinfo.green),
+            blue: ChannelLayout::from_raw(&vinfo.blue),
+        }
+    }
+}
+
 pub struct MmappedFramebuffer {
     ptr: *mut u8,
     len: usize,
@@ -70,6 +153,10 @@ impl Drop for MmappedFramebuffer {
 }
 
 impl Progressor for FramebufferProgressor {
+    fn needs_snapshot(&self) -> bool {
+        true
+    }
+
     fn make_supervised_progressor(
         &self,
     ) -> Box<
@@ -96,35 +183,6 @@ impl Progressor for FramebufferProgressor {
                 }
             };
 
-        let fbfd = fb.as_raw_fd();
-        let mut finfo = MaybeUninit::<raw::fb_fix_screeninfo>::uninit();
-        let mut vinfo = MaybeUninit::<raw::fb_var_screeninfo>::uninit();
-        let (finfo, vinfo) = unsafe {
-            // Get framebuffer fixed screen information
-            if libc::ioctl(fbfd, raw::FBIOGET_FSCREENINFO, finfo.as_mut_ptr())
-                != 0
-            {
-                let err = Error::last_os_error();
-                log::error!(
-                    "Failed to read framebuffer fixed information: {err}"
-                );
-                return noop_fallback.make_supervised_progressor();
-            }
-
-            // Get framebuffer variable screen information
-            if libc::ioctl(fbfd, raw::FBIOGET_VSCREENINFO, vinfo.as_mut_ptr())
-                != 0
-            {
-                let err = Error::last_os_error();
-                log::error!(
-                    "Failed to read framebuffer variable information: {err}"
-                );
-                return noop_fallback.make_supervised_progressor();
-            }
-
-            (finfo.assume_init(), vinfo.assume_init())
-        };
-
         if let Err(_) = usize::try_from(u32::MAX) {
             log::error!(
                 "This framebuffer code does not support 16-bit (How are you running linux on a 16-bit platform anyway?)"
@@ -132,8 +190,24 @@ impl Progressor for FramebufferProgressor {
             return noop_fallback.make_supervised_progressor();
         }
 
+        let fbfd = fb.as_raw_fd();
+        let info = match FramebufferInfo::query(fbfd) {
+            Ok(info) => info,
+            Err(err) => {
+                log::error!("Failed to read framebuffer {err}");
+                return noop_fallback.make_supervised_progressor();
+            }
+        };
+        log::debug!("framebuffer {:?}: {info:?}", self.fb_path);
+        if !info.is_bgr32() {
+            log::warn!(
+                "Framebuffer {:?} is not 32-bit BGR, colors may be wrong",
+                self.fb_path
+            );
+        }
+
         // Map framebuffer to user memory
-        let screensize = finfo.smem_len as usize;
+        let screensize = info.len;
 
         let ptr = unsafe {
             let ptr = libc::mmap(
@@ -158,9 +232,9 @@ impl Progressor for FramebufferProgressor {
         let mut framebuffer = MmappedFramebuffer {
             ptr,
             len: screensize,
-            stride: finfo.line_length as usize,
-            width: vinfo.xres_virtual as usize,
-            height: vinfo.yres_virtual as usize,
+            stride: info.stride,
+            width: info.width,
+            height: info.height,
         };
 
         Box::new({
@@ -170,7 +244,7 @@ impl Progressor for FramebufferProgressor {
                     log::error!(
                         "Image too wide for framebuffer {fb_path:?} ({} > {}).",
                         common_data.dimx,
-                        vinfo.xres_virtual
+                        info.width
                     );
                     return noop_fallback.make_supervised_progressor()(
                         progress_data,
@@ -181,7 +255,7 @@ impl Progressor for FramebufferProgressor {
                     log::error!(
                         "Image too tall for framebuffer {fb_path:?} ({} > {}).",
                         common_data.dimy,
-                        vinfo.yres_virtual
+                        info.height
                     );
                     return noop_fallback.make_supervised_progressor()(
                         progress_data,
@@ -200,25 +274,24 @@ impl Progressor for FramebufferProgressor {
                         log::trace!(target: "barriers", "after progress barrier a");
                         let now = Instant::now();
                         if now - last_update >= update_interval
-                            || common_data.finished.load(Ordering::SeqCst)
+                            || common_data.finished.load(Ordering::Acquire)
                         {
                             last_update = now;
-                            let locked = common_data.locked.read().unwrap();
+                            let snapshot = common_data
+                                .snapshot
+                                .expect("needs_snapshot is true")
+                                .read()
+                                .unwrap();
                             for y in 0..common_data.dimy.get() {
                                 for x in 0..common_data.dimx.get() {
-                                    let color = locked.image[(y, x)]
-                                        * Color::splat(255.0);
-                                    // framebuffer[y][x] =
-                                    // *color.cast().as_array();
-                                    framebuffer[y][x] = simd_swizzle!(
-                                        color.cast(),
-                                        [2, 1, 0, 3]
-                                    )
-                                    .to_array();
+                                    let [r, g, b, a] = quantize_8bit(
+                                        snapshot.displayed_color((y, x)),
+                                    );
+                                    framebuffer[y][x] = [b, g, r, a];
                                 }
                             }
                         }
-                        if common_data.finished.load(Ordering::SeqCst) {
+                        if common_data.finished.load(Ordering::Acquire) {
                             break;
                         }
                         log::trace!(target: "barriers", "before progress barrier b");
@@ -230,3 +303,50 @@ impl Progressor for FramebufferProgressor {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{raw, ChannelLayout, FramebufferInfo};
+
+    #[test]
+    fn info_from_raw() {
+        // SAFETY: the screen information structs are plain integers.
+        let mut finfo: raw::fb_fix_screeninfo = unsafe { std::mem::zeroed() };
+        let mut vinfo: raw::fb_var_screeninfo = unsafe { std::mem::zeroed() };
+        finfo.smem_len = 1920 * 4 * 2160;
+        finfo.line_length = 1920 * 4;
+        (vinfo.xres, vinfo.yres) = (1920, 1080);
+        (vinfo.xres_virtual, vinfo.yres_virtual) = (1920, 2160);
+        vinfo.bits_per_pixel = 32;
+        (vinfo.red.offset, vinfo.red.length) = (16, 8);
+        (vinfo.green.offset, vinfo.green.length) = (8, 8);
+        (vinfo.blue.offset, vinfo.blue.length) = (0, 8);
+        (vinfo.transp.offset, vinfo.transp.length) = (24, 8);
+
+        let info = FramebufferInfo::from_raw(&finfo, &vinfo);
+        assert_eq!(
+            info,
+            FramebufferInfo {
+                len: 1920 * 4 * 2160,
+                stride: 1920 * 4,
+                width: 1920,
+                height: 2160,
+                bits_per_pixel: 32,
+                red: ChannelLayout { offset: 16, length: 8 },
+                green: ChannelLayout { offset: 8, length: 8 },
+                blue: ChannelLayout { offset: 0, length: 8 },
+            }
+        );
+        assert!(info.is_bgr32());
+
+        // RGB565
+        vinfo.bits_per_pixel = 16;
+        (vinfo.red.offset, vinfo.red.length) = (11, 5);
+        (vinfo.green.offset, vinfo.green.length) = (5, 6);
+        (vinfo.blue.offset, vinfo.blue.length) = (0, 5);
+        let info = FramebufferInfo::from_raw(&finfo, &vinfo);
+        assert_eq!(info.red, ChannelLayout { offset: 11, length: 5 });
+        assert_eq!(info.green, ChannelLayout { offset: 5, length: 6 });
+        assert!(!info.is_bgr32());
+    }
+}
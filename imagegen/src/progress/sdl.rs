@@ -2,15 +2,17 @@ use std::{
     marker::PhantomData,
     ops::{Index, IndexMut},
     pin::Pin,
-    simd::num::SimdFloat,
     sync::atomic::Ordering,
 };
 
-use crate::{color::Color, progress::NoOpProgressor};
+use crate::{pnmdata::quantize_8bit, progress::NoOpProgressor};
 
 use super::Progressor;
 
-pub struct Sdl2Progressor {}
+pub struct Sdl2Progressor {
+    /// The window title.
+    pub title: String,
+}
 
 struct SdlSurfacePixelsMut<'a> {
     data: *mut u8,
@@ -59,7 +61,43 @@ impl<'a> IndexMut<(usize, usize)> for SdlSurfacePixelsMut<'a> {
     }
 }
 
+/// How the image is drawn onto a window surface: each image pixel becomes a
+/// `scale`x`scale` square, and the image is offset by `(x_offset, y_offset)`
+/// to center it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FitScaling {
+    scale: usize,
+    x_offset: usize,
+    y_offset: usize,
+}
+
+/// Computes the largest integer scaling at which an image of size
+/// `(image_width, image_height)` fits in a window of size
+/// `(window_width, window_height)`, centered.
+///
+/// If the window is smaller than the image, the scale is 1 and the image is
+/// drawn from the top-left corner (and clipped).
+fn fit_scaling(
+    (window_width, window_height): (usize, usize),
+    (image_width, image_height): (usize, usize),
+) -> FitScaling {
+    let scale = std::cmp::min(
+        window_width / image_width,
+        window_height / image_height,
+    )
+    .max(1);
+    FitScaling {
+        scale,
+        x_offset: window_width.saturating_sub(image_width * scale) / 2,
+        y_offset: window_height.saturating_sub(image_height * scale) / 2,
+    }
+}
+
 impl Progressor for Sdl2Progressor {
+    fn needs_snapshot(&self) -> bool {
+        true
+    }
+
     fn make_supervised_progressor(
         &self,
     ) -> Box<
@@ -72,6 +110,7 @@ impl Progressor for Sdl2Progressor {
             >,
     > {
         Box::new({
+            let title = self.title.clone();
             move |progress_data, common_data| {
                 let fut = async move {
                     let noop_fallback = NoOpProgressor;
@@ -121,11 +160,12 @@ impl Progressor for Sdl2Progressor {
 
                     let window = match video_subsystem
                         .window(
-                            "imagegen-rs",
+                            &title,
                             common_data.dimx.get().try_into().unwrap(),
                             common_data.dimy.get().try_into().unwrap(),
                         )
                         .position_centered()
+                        .resizable()
                         .build()
                     {
                         Ok(window) => window,
@@ -156,6 +196,9 @@ impl Progressor for Sdl2Progressor {
                     let update_interval = Duration::from_millis(300);
                     let mut last_update = Instant::now();
                     let mut quit_requested = false;
+                    // Whether the window surface needs to be cleared before
+                    // drawing, e.g. because the image moved after a resize.
+                    let mut needs_clear = true;
                     log::trace!(target: "sdl", "starting sdl loop on thread {:?}", std::thread::current().id());
                     loop {
                         log::trace!(target: "sdl", "inside sdl loop on thread {:?}", std::thread::current().id());
@@ -189,6 +232,17 @@ impl Progressor for Sdl2Progressor {
                                         quit_requested = true;
                                     }
                                 }
+                                sdl2::event::Event::Window {
+                                    win_event:
+                                        sdl2::event::WindowEvent::Resized(..)
+                                        | sdl2::event::WindowEvent::SizeChanged(
+                                            ..,
+                                        ),
+                                    ..
+                                } => {
+                                    log::trace!(target: "sdl", "window resized");
+                                    needs_clear = true;
+                                }
                                 _ => {}
                             }
                         }
@@ -197,13 +251,17 @@ impl Progressor for Sdl2Progressor {
                         let now = Instant::now();
                         if true
                             || now - last_update >= update_interval
-                            || common_data.finished.load(Ordering::SeqCst)
+                            || common_data.finished.load(Ordering::Acquire)
                         {
                             log::trace!(target: "sdl", "inside sdl loop on thread {:?} aaa bbb", std::thread::current().id());
                             last_update = now;
-                            let locked = common_data.locked.read().unwrap();
+                            let snapshot = common_data
+                                .snapshot
+                                .expect("needs_snapshot is true")
+                                .read()
+                                .unwrap();
                             log::trace!(target: "sdl", "inside sdl loop on thread {:?} aaa bbb", std::thread::current().id());
-                            let locked = &*locked;
+                            let locked = &*snapshot;
                             log::trace!(target: "sdl", "inside sdl loop on thread {:?} aaa bbb", std::thread::current().id());
                             // for y in 0..common_data.dimy.get() {
                             //     for x in 0..common_data.dimx.get() {
@@ -224,9 +282,37 @@ impl Progressor for Sdl2Progressor {
                                 }
                             };
 
+                            if needs_clear {
+                                // Unplaced pixels are never drawn, so they
+                                // show the background.
+                                let background =
+                                    quantize_8bit(locked.image.background);
+                                if let Err(error) = surface.fill_rect(
+                                    None,
+                                    sdl2::pixels::Color::RGB(
+                                        background[0],
+                                        background[1],
+                                        background[2],
+                                    ),
+                                ) {
+                                    log::error!(
+                                        "Failed to clear SDL2 window surface: {error}"
+                                    );
+                                }
+                                needs_clear = false;
+                            }
+
                             let byte_stride = surface.pitch() as usize;
                             let width = surface.width() as usize;
                             let height = surface.height() as usize;
+                            let FitScaling { scale, x_offset, y_offset } =
+                                fit_scaling(
+                                    (width, height),
+                                    (
+                                        common_data.dimx.get(),
+                                        common_data.dimy.get(),
+                                    ),
+                                );
                             surface.with_lock_mut(|data| {
                                 let mut data = unsafe {
                                     SdlSurfacePixelsMut::new_unchecked(
@@ -239,14 +325,24 @@ impl Progressor for Sdl2Progressor {
                                 log::debug!("sdl placing pixels");
                                 locked.placed_pixels.for_each_true(
                                     |row, col| {
-                                        let color = locked.image[(row, col)]
-                                            * Color::splat(255.0);
-                                        let color = color.cast::<u8>();
+                                        let color = locked.image[(row, col)];
+                                        let color = quantize_8bit(color);
                                         // let color =
                                         // u32::from_ne_bytes(color.to_array());
                                         // canvas.pixel(col as _, row as _,
                                         // color).unwrap();
-                                        data[(row, col)] = color.to_array();
+                                        let top = y_offset + row * scale;
+                                        let left = x_offset + col * scale;
+                                        // Clip to the surface, in case the
+                                        // window is smaller than the image.
+                                        for y in top..(top + scale).min(height)
+                                        {
+                                            for x in
+                                                left..(left + scale).min(width)
+                                            {
+                                                data[(y, x)] = color;
+                                            }
+                                        }
                                     },
                                 );
                                 log::debug!("sdl placed pixels");
@@ -255,7 +351,7 @@ impl Progressor for Sdl2Progressor {
                             log::debug!("Wrote image sdl");
                         }
                         log::trace!(target: "sdl", "inside sdl loop on thread {:?} aaa bbb", std::thread::current().id());
-                        if common_data.finished.load(Ordering::SeqCst) {
+                        if common_data.finished.load(Ordering::Acquire) {
                             log::debug!("sdl breaking out of loop");
                             break;
                         }
@@ -264,7 +360,7 @@ impl Progressor for Sdl2Progressor {
                         log::trace!(target: "barriers", "sdl after barrier b");
                         if quit_requested {
                             log::trace!("sdl quit requested");
-                            common_data.finished.store(true, Ordering::SeqCst);
+                            common_data.finished.store(true, Ordering::Release);
                         }
                     }
                 };
@@ -274,3 +370,24 @@ impl Progressor for Sdl2Progressor {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{fit_scaling, FitScaling};
+
+    #[test]
+    fn fit_scaling_test() {
+        #[rustfmt::skip]
+        let cases = [
+            ((256, 256), (256, 256), FitScaling { scale: 1, x_offset: 0, y_offset: 0 }),
+            ((512, 512), (256, 256), FitScaling { scale: 2, x_offset: 0, y_offset: 0 }),
+            ((800, 600), (256, 256), FitScaling { scale: 2, x_offset: 144, y_offset: 44 }),
+            ((300, 1000), (100, 50), FitScaling { scale: 3, x_offset: 0, y_offset: 425 }),
+            ((100, 100), (256, 256), FitScaling { scale: 1, x_offset: 0, y_offset: 0 }),
+            ((300, 100), (256, 256), FitScaling { scale: 1, x_offset: 22, y_offset: 0 }),
+        ];
+        for (window, image, expected) in cases {
+            assert_eq!(fit_scaling(window, image), expected);
+        }
+    }
+}
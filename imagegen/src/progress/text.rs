@@ -1,10 +1,15 @@
 use std::{
+    collections::VecDeque,
     future::Future,
+    num::NonZeroUsize,
     pin::Pin,
     sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
 };
 
-use super::{ProgressData, ProgressSupervisorData, Progressor};
+use super::{
+    ProgressData, ProgressSupervisorData, ProgressThrottle, Progressor,
+};
 
 pub struct TextProgressor<F: for<'a> FnMut(std::fmt::Arguments<'a>) + ?Sized> {
     callback: Arc<F>,
@@ -18,6 +23,53 @@ impl<F: for<'a> Fn(std::fmt::Arguments<'a>)> TextProgressor<F> {
     }
 }
 
+/// The recent placement rate, for estimating the remaining time.
+///
+/// Placement usually slows down near the end of generation, so this only uses
+/// the last few progress reports instead of the overall average rate.
+struct RateWindow {
+    /// (elapsed time, pixels placed) at each of the last `window + 1`
+    /// progress reports.
+    samples: VecDeque<(Duration, usize)>,
+    window: NonZeroUsize,
+}
+
+impl RateWindow {
+    fn new(window: NonZeroUsize) -> Self {
+        Self { samples: VecDeque::with_capacity(window.get() + 1), window }
+    }
+
+    fn push(&mut self, elapsed: Duration, pixels_placed: usize) {
+        if self.samples.len() > self.window.get() {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((elapsed, pixels_placed));
+    }
+
+    /// Estimates how long placing `remaining` more pixels will take, or
+    /// `None` if nothing was placed during the window.
+    fn eta(&self, remaining: usize) -> Option<Duration> {
+        let (&(first_time, first_placed), &(last_time, last_placed)) =
+            (self.samples.front()?, self.samples.back()?);
+        let placed = last_placed.checked_sub(first_placed)?;
+        if placed == 0 || last_time <= first_time {
+            return None;
+        }
+        let rate = placed as f64 / (last_time - first_time).as_secs_f64();
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+/// Formats `duration` like `1h02m03s`, `2m03s`, or `3s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
+    }
+}
+
 impl<F: for<'a> Fn(std::fmt::Arguments<'a>) + Sync + Send + ?Sized + 'static> Progressor
     for TextProgressor<F>
 {
@@ -35,7 +87,7 @@ impl<F: for<'a> Fn(std::fmt::Arguments<'a>) + Sync + Send + ?Sized + 'static> Pr
             move |progress_data, common_data| {
                 Box::pin(async move {
                     let ProgressData {
-                        progress_interval, ..
+                        progress_interval, eta_window, ..
                     } = progress_data;
                     let ProgressSupervisorData {
                         locked,
@@ -46,27 +98,38 @@ impl<F: for<'a> Fn(std::fmt::Arguments<'a>) + Sync + Send + ?Sized + 'static> Pr
                         size,
                         ..
                     } = *common_data;
-                    let mut step_count = 0;
+                    let mut throttle = ProgressThrottle::new(progress_interval, size);
                     let mut prev_edge_count = 0;
+                    let start = Instant::now();
+                    let mut rate_window = RateWindow::new(eta_window);
+                    let mut report = |pixels_placed: usize| {
+                        if let Ok(guard) = locked.try_read() {
+                            prev_edge_count = guard.edges.len();
+                        }
+                        let pixels_generated = pixels_generated.load(Ordering::Relaxed);
+                        let percent_done = 100.0 * pixels_placed as f64 / size.get() as f64;
+                        rate_window.push(start.elapsed(), pixels_placed);
+                        let remaining = size.get().saturating_sub(pixels_placed);
+                        let eta = match rate_window.eta(remaining) {
+                            Some(eta) => format_duration(eta),
+                            None => "unknown".to_string(),
+                        };
+                        callback(format_args!(
+                            "Approximately {percent_done:4.1}% done, ETA {eta} ({progress_interval}, {prev_edge_count} edges, {pixels_placed} pixels placed, {pixels_generated} pixels generated)",
+                        ));
+                    };
                     loop {
                         progress_barrier.wait().await;
-                        if finished.load(Ordering::SeqCst) {
+                        let pixels_placed = pixels_placed.load(Ordering::Relaxed);
+                        if finished.load(Ordering::Acquire) {
                             // Only read this betwee barriers, so we know generator thread wont change it under us
+                            if throttle.finish(pixels_placed) {
+                                report(pixels_placed);
+                            }
                             break;
                         }
-                        if step_count >= progress_interval {
-                            step_count = 0;
-                            if let Ok(guard) = locked.try_read() {
-                                prev_edge_count = guard.edges.len();
-                            }
-                            let pixels_placed = pixels_placed.load(Ordering::SeqCst);
-                            let pixels_generated = pixels_generated.load(Ordering::SeqCst);
-                            let percent_done = 100.0 * pixels_placed as f64 / size.get() as f64;
-                            callback(format_args!(
-                                "Approximately {percent_done:4.1}% done ({progress_interval}, {prev_edge_count} edges, {pixels_placed} pixels placed, {pixels_generated} pixels generated)",
-                            ));
-                        } else {
-                            step_count += 1;
+                        if throttle.ready(pixels_placed) {
+                            report(pixels_placed);
                         }
                         progress_barrier.wait().await;
                     }
@@ -75,3 +138,40 @@ impl<F: for<'a> Fn(std::fmt::Arguments<'a>) + Sync + Send + ?Sized + 'static> Pr
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use super::{format_duration, RateWindow};
+
+    #[test]
+    fn windowed_eta() {
+        // The rate drops by 10 pixels/s every second.
+        let mut window = RateWindow::new(NonZeroUsize::new(2).unwrap());
+        assert_eq!(window.eta(100), None);
+        let mut placed = 0;
+        window.push(Duration::ZERO, placed);
+        assert_eq!(window.eta(100), None);
+        for (second, rate) in (1..).zip((10..=100).rev().step_by(10)) {
+            placed += rate;
+            window.push(Duration::from_secs(second), placed);
+        }
+        assert_eq!(placed, 550);
+
+        // The last two seconds placed 30 pixels, 15 per second.
+        let remaining = 450;
+        let eta = window.eta(remaining).unwrap();
+        assert_eq!(eta, Duration::from_secs(30));
+        // The overall average is 55 per second.
+        let naive = Duration::from_secs_f64(remaining as f64 / 55.0);
+        assert!(eta > naive);
+    }
+
+    #[test]
+    fn format_duration_test() {
+        assert_eq!(format_duration(Duration::from_millis(3500)), "3s");
+        assert_eq!(format_duration(Duration::from_secs(123)), "2m03s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
@@ -1,115 +1,951 @@
 use std::{
     collections::VecDeque,
-    num::NonZeroUsize,
-    sync::{Arc, Barrier, RwLock},
+    num::{NonZeroU32, NonZeroUsize},
+    str::FromStr,
+    sync::{Arc, Barrier, Mutex, Once, RwLock},
 };
 
 use bitmap::BitMap;
 use getopt::{GetoptItem, Opt};
 use rand::{RngCore, SeedableRng};
 
-use crate::{color::Color, pnmdata::PnmData, CommonData, CommonLockedData};
+use crate::{
+    color::{parse_color, Color},
+    error::ConfigError,
+    generate::Pixel,
+    pnmdata::{OutputConfig, PnmData, PnmFormat, Tile, Tonemap},
+    CommonData, CommonLockedData,
+};
 
 pub fn opts() -> impl IntoIterator<Item = Opt> {
     [
         Opt::short_long('x', "x", getopt::HasArgument::Yes),
         Opt::short_long('y', "y", getopt::HasArgument::Yes),
         Opt::short_long('s', "size", getopt::HasArgument::Yes),
+        Opt::long("square", getopt::HasArgument::Yes),
         Opt::long("maxval", getopt::HasArgument::Yes),
+        Opt::long("depth", getopt::HasArgument::Yes),
+        Opt::long("plain", getopt::HasArgument::No),
+        Opt::long("dither", getopt::HasArgument::No),
+        Opt::long("tonemap", getopt::HasArgument::Yes),
+        Opt::long("tile", getopt::HasArgument::Yes),
+        Opt::long("mirror", getopt::HasArgument::No),
+        Opt::long("background", getopt::HasArgument::Yes),
+        Opt::long("mask", getopt::HasArgument::Yes),
+        Opt::long("strict", getopt::HasArgument::No),
+        Opt::long("max-pixels-allowed", getopt::HasArgument::Yes),
         Opt::short_long('S', "seed", getopt::HasArgument::Yes),
+        Opt::long("no-provenance", getopt::HasArgument::No),
     ]
 }
 
+/// Estimates how many bytes generating a `dimx`x`dimy` image needs, for the
+/// image data, the placed pixels bitmap, and the initial edge queue.
+pub fn estimate_memory(dimx: usize, dimy: usize) -> u128 {
+    let (dimx, dimy) = (dimx as u128, dimy as u128);
+    let image = dimx * dimy * std::mem::size_of::<Color>() as u128;
+    let bitmap = dimx.div_ceil(8) * dimy;
+    let edges = dimx.max(dimy) * 4 * std::mem::size_of::<Pixel>() as u128;
+    image + bitmap + edges
+}
+
+/// Formats a number of bytes with a binary unit prefix, e.g. `29 TiB`.
+pub fn format_bytes(bytes: u128) -> String {
+    const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 || value >= 10.0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Checks that a `dimx`x`dimy` image can be represented and allocated and is
+/// not larger than `max_pixels`, and returns its size in pixels.
+///
+/// If this succeeds, then so do the allocations in `SetupSettings::build`
+/// (short of running out of memory).
+fn check_size(
+    dimx: NonZeroUsize,
+    dimy: NonZeroUsize,
+    max_pixels: usize,
+) -> Result<NonZeroUsize, ConfigError> {
+    let (width, height) = (dimx.get(), dimy.get());
+    // Dimensions are stored as `u32` in `PnmData`, and coordinates as `i32`
+    // in `Pixel`.
+    let max = i32::MAX as usize;
+    if width > max || height > max {
+        return Err(ConfigError::DimensionTooLarge { width, height, max });
+    }
+    let bytes = estimate_memory(width, height);
+    match dimx.checked_mul(dimy) {
+        Some(size) if size.get() <= max_pixels => {
+            // No allocation may be larger than `isize::MAX` bytes, and the
+            // image data is the largest.
+            if bytes > isize::MAX as u128 {
+                return Err(ConfigError::ImageTooLarge { width, height });
+            }
+            Ok(size)
+        }
+        _ => Err(ConfigError::TooManyPixels {
+            width,
+            height,
+            bytes,
+            limit: max_pixels,
+        }),
+    }
+}
+
+/// With the `f32` feature, channels only have 24 bits of mantissa, which may
+/// not be enough for 16-bit output near the extremes.
+fn precision_loss(maxval: u32) -> Option<ConfigError> {
+    (cfg!(feature = "f32") && maxval > 255)
+        .then_some(ConfigError::PrecisionLoss { maxval })
+}
+
+/// Sizes that `--size` accepts by name (case-insensitively).
+const NAMED_SIZES: [(&str, usize, usize); 6] = [
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("1440p", 2560, 1440),
+    ("2160p", 3840, 2160),
+    ("4k", 3840, 2160),
+    ("8k", 7680, 4320),
+];
+
+/// Parses a `--size` value that is not given as `WxH`: either a named size
+/// like `1080p` or `4k`, or an aspect ratio and a pixel count like `16:9@2MP`
+/// or `1:1@65536` (see `fit_aspect_ratio`).
+///
+/// Returns `None` for anything else, including a ratio without a pixel count
+/// (or vice versa), so that e.g. `16:9` is not silently given some size.
+fn parse_computed_size(size: &str) -> Option<(NonZeroUsize, NonZeroUsize)> {
+    let size = size.to_ascii_lowercase();
+    let named = NAMED_SIZES.iter().find(|&&(name, ..)| name == size);
+    let (width, height) = match named {
+        Some(&(_, width, height)) => (width, height),
+        None => {
+            let (ratio, pixels) = size.split_once('@')?;
+            let (width, height) = ratio.split_once(':')?;
+            let valid = |value: f64| value.is_finite() && value > 0.0;
+            let width = width.parse().ok().filter(|&value| valid(value))?;
+            let height = height.parse().ok().filter(|&value| valid(value))?;
+            let pixels = match pixels.strip_suffix("mp") {
+                Some(megapixels) => megapixels.parse::<f64>().ok()? * 1e6,
+                None => pixels.parse::<usize>().ok()? as f64,
+            };
+            if !(pixels.is_finite() && pixels >= 1.0) {
+                return None;
+            }
+            fit_aspect_ratio(width / height, pixels)
+        }
+    };
+    Some((NonZeroUsize::new(width)?, NonZeroUsize::new(height)?))
+}
+
+/// Computes the `(width, height)` with aspect ratio `ratio` (width over
+/// height) that best fits `pixels` (which must be at least 1).
+///
+/// Both dimensions are rounded to the nearest integer, but at least 1. While
+/// the area is then more than `pixels`, whichever dimension is relatively
+/// furthest above its exact value (and is more than 1) is decreased by 1, so
+/// the area never exceeds `pixels`. If one dimension is 1, the other is at
+/// most `pixels`, so very narrow ratios at small pixel counts are distorted
+/// rather than exceeding the pixel count.
+fn fit_aspect_ratio(ratio: f64, pixels: f64) -> (usize, usize) {
+    let exact = [(pixels * ratio).sqrt(), (pixels / ratio).sqrt()];
+    // Float to integer casts saturate, and `check_size` rejects anything
+    // too large.
+    let mut dims = exact.map(|exact| (exact.round() as usize).max(1));
+    while (dims[0] as f64) * (dims[1] as f64) > pixels {
+        if dims.contains(&1) {
+            let max = pixels.floor() as usize;
+            dims = dims.map(|dim| dim.min(max));
+            break;
+        }
+        let overshoot = |i: usize| dims[i] as f64 / exact[i];
+        let i = if overshoot(0) >= overshoot(1) { 0 } else { 1 };
+        dims[i] -= 1;
+    }
+    (dims[0], dims[1])
+}
+
+/// Parses a `--seed` value: a decimal, `0x`-prefixed hexadecimal, or
+/// `0b`-prefixed binary `u64`, or any other string, which is hashed with
+/// 64-bit FNV-1a (offset basis `0xcbf29ce484222325`, prime `0x100000001b3`)
+/// over its UTF-8 bytes.
+pub fn parse_seed(seed: &str) -> u64 {
+    if let Ok(seed) = seed.parse() {
+        return seed;
+    }
+    for (prefix, radix) in [("0x", 16), ("0X", 16), ("0b", 2), ("0B", 2)] {
+        let digits = seed.strip_prefix(prefix);
+        if let Some(Ok(seed)) = digits.map(|d| u64::from_str_radix(d, radix)) {
+            return seed;
+        }
+    }
+    let hash = seed.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    log::info!("seed {seed:?} hashed to {hash}");
+    hash
+}
+
+/// Reads the image at `path` for `--mask`, and returns which of its pixels
+/// are masked out (black). It must be `dimx`x`dimy`, and leave at least one
+/// pixel to generate.
+fn read_mask(
+    path: &str,
+    dimx: NonZeroUsize,
+    dimy: NonZeroUsize,
+) -> Result<BitMap, ConfigError> {
+    let file = std::fs::File::open(path).map_err(|source| {
+        ConfigError::OpenFile { option: "mask", path: path.to_string(), source }
+    })?;
+    let image = PnmData::read_from(std::io::BufReader::new(file)).map_err(
+        |source| ConfigError::ReadFile {
+            option: "mask",
+            path: path.to_string(),
+            source,
+        },
+    )?;
+    if (image.dimx as usize, image.dimy as usize) != (dimx.get(), dimy.get())
+    {
+        return Err(ConfigError::MaskSize {
+            path: path.to_string(),
+            width: image.dimx,
+            height: image.dimy,
+            dimx: dimx.get(),
+            dimy: dimy.get(),
+        });
+    }
+    let mut masked = BitMap::new(dimy.get(), dimx.get())
+        .expect("check_size checked that the image fits in memory");
+    for (i, color) in image.rawdata.iter().enumerate() {
+        if color.as_array()[..3] == [0.0; 3] {
+            masked.set((i / dimx.get(), i % dimx.get()), true);
+        }
+    }
+    let unmasked = dimx.get() * dimy.get() - masked.count_ones();
+    if unmasked == 0 {
+        return Err(ConfigError::InvalidValue {
+            option: "mask",
+            value: path.to_string(),
+        });
+    }
+    log::info!("--mask {path:?} leaves {unmasked} pixels to generate");
+    Ok(masked)
+}
+
+/// The validated setup options, before anything is allocated.
+#[derive(Debug, Clone)]
+pub struct SetupSettings {
+    pub dimx: NonZeroUsize,
+    pub dimy: NonZeroUsize,
+    /// `dimx * dimy`.
+    pub size: NonZeroUsize,
+    pub output: OutputConfig,
+    /// The color of pixels that have not been placed when the image is
+    /// written or shown.
+    pub background: Color,
+    /// The pixels that are never generated, from `--mask`. They are marked
+    /// as placed from the start, and shown in the background color.
+    pub mask: Option<BitMap>,
+    pub seed: u64,
+    /// Whether to record how the image was made (seed, version, command line,
+    /// etc.) in its comments.
+    pub provenance: bool,
+}
+
+/// If `seed_override` is given, it is used instead of the `--seed` option
+/// (e.g. for the later runs of a `--count` invocation).
+///
+/// The `IMAGEGEN_WIDTH`, `IMAGEGEN_HEIGHT`, and `IMAGEGEN_MAXVAL` environment
+/// variables are used as defaults for the width, height, and `--maxval` if
+/// they are not given as options.
 pub fn handle_opts(
     opts: &[GetoptItem<'_>],
-) -> (Arc<CommonData>, impl RngCore + Send) {
+    seed_override: Option<u64>,
+) -> Result<SetupSettings, ConfigError> {
+    handle_opts_with_env(opts, seed_override, |var| std::env::var(var).ok())
+}
+
+/// Parses the environment variable `var` (looked up with `env`) as a default
+/// for an option, if it is set and not empty.
+fn env_default<T: FromStr>(
+    env: &impl Fn(&str) -> Option<String>,
+    var: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    env(var)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnvValue { var, value })
+        })
+        .transpose()
+}
+
+/// `handle_opts`, but with environment variables looked up with `env`.
+fn handle_opts_with_env(
+    opts: &[GetoptItem<'_>],
+    seed_override: Option<u64>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<SetupSettings, ConfigError> {
     let mut size = (None, None);
     let mut maxval = None;
+    let mut depth = None;
+    let mut tonemap = None;
+    let mut tile = None;
+    let mut mirror = false;
+    let mut background = None;
+    let mut mask = None;
+    let mut output = OutputConfig::default();
+    let mut strict = false;
+    let mut max_pixels = None;
     let mut seed = None;
+    let mut provenance = true;
+    let color255 = crate::color::color255(opts);
 
     macro_rules! set {
-        ($arg:expr => $e:expr => $field:literal) => {
+        ($arg:expr => $e:expr => $option:literal) => {
             match &mut $e {
-                Some(_) => panic!("multiple {} values specified", $field),
+                Some(_) => {
+                    return Err(ConfigError::Duplicate {
+                        option: $option,
+                        value: $arg.to_string(),
+                    })
+                }
                 None => match $arg.parse() {
                     Ok(value) => $e = Some(value),
-                    Err(_) => panic!("invalid {} value: {:?}", $field, $arg),
+                    Err(_) => {
+                        return Err(ConfigError::InvalidValue {
+                            option: $option,
+                            value: $arg.to_string(),
+                        })
+                    }
                 },
             }
         };
     }
 
+    // The first sizing option given. `-x` and `-y` go together, but `--size`
+    // and `--square` each set both dimensions, so they cannot be mixed with
+    // any other sizing option.
+    let mut sizing = None;
+    macro_rules! sizing {
+        ($option:literal) => {
+            let method = |option| if option == "y" { "x" } else { option };
+            match sizing {
+                Some(other) if method(other) != method($option) => {
+                    return Err(ConfigError::Conflict {
+                        option: $option,
+                        other,
+                    });
+                }
+                _ => sizing = Some($option),
+            }
+        };
+    }
+
     for opt in opts {
         match opt {
             GetoptItem::Opt { opt, arg: Some(width) } if opt.is_long("x") => {
-                set!(width => size.0 => "width");
+                sizing!("x");
+                set!(width => size.0 => "x");
             }
             GetoptItem::Opt { opt, arg: Some(height) } if opt.is_long("y") => {
-                set!(height => size.1 => "height");
+                sizing!("y");
+                set!(height => size.1 => "y");
+            }
+            GetoptItem::Opt { opt, arg: Some(side) }
+                if opt.is_long("square") =>
+            {
+                sizing!("square");
+                set!(side => size.0 => "square");
+                size.1 = size.0;
             }
             GetoptItem::Opt { opt, arg: Some(size_str) }
                 if opt.is_long("size") =>
             {
-                let (width, height) = size_str
+                sizing!("size");
+                if let Some((width, height)) = size_str
                     .split_once(',')
                     .or_else(|| size_str.split_once('x'))
-                    .expect("invalid size");
-                set!(width => size.0 => "width");
-                set!(height => size.1 => "height");
+                {
+                    set!(width => size.0 => "size");
+                    set!(height => size.1 => "size");
+                    continue;
+                }
+                let (width, height) = parse_computed_size(size_str)
+                    .ok_or_else(|| ConfigError::InvalidValue {
+                        option: "size",
+                        value: size_str.to_string(),
+                    })?;
+                if size.0.is_some() || size.1.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "size",
+                        value: size_str.to_string(),
+                    });
+                }
+                log::info!("--size {size_str} is {width}x{height}");
+                size = (Some(width), Some(height));
             }
             GetoptItem::Opt { opt, arg: Some(maxval_str) }
                 if opt.is_long("maxval") =>
             {
                 set!(maxval_str => maxval => "maxval");
+                if !matches!(maxval, Some(1..=65535)) {
+                    return Err(ConfigError::InvalidValue {
+                        option: "maxval",
+                        value: maxval_str.to_string(),
+                    });
+                }
+            }
+            GetoptItem::Opt { opt, arg: Some(depth_str) }
+                if opt.is_long("depth") =>
+            {
+                set!(depth_str => depth => "depth");
+                if !matches!(depth, Some(1 | 3)) {
+                    return Err(ConfigError::InvalidValue {
+                        option: "depth",
+                        value: depth_str.to_string(),
+                    });
+                }
+            }
+            GetoptItem::Opt { opt, arg: Some(max_pixels_str) }
+                if opt.is_long("max-pixels-allowed") =>
+            {
+                set!(max_pixels_str => max_pixels => "max-pixels-allowed");
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("strict") => {
+                strict = true;
+            }
+            GetoptItem::Opt { opt, arg: None }
+                if opt.is_long("no-provenance") =>
+            {
+                provenance = false;
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("plain") => {
+                output.format = PnmFormat::Plain;
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("dither") => {
+                output.dither = true;
+            }
+            GetoptItem::Opt { opt, arg: Some(tonemap_str) }
+                if opt.is_long("tonemap") =>
+            {
+                if tonemap.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "tonemap",
+                        value: tonemap_str.to_string(),
+                    });
+                }
+                tonemap = Some(match *tonemap_str {
+                    "clamp" => Tonemap::Clamp,
+                    "normalize" => Tonemap::Normalize,
+                    _ => {
+                        return Err(ConfigError::UnknownValue {
+                            option: "tonemap",
+                            value: tonemap_str.to_string(),
+                            expected: "clamp, normalize",
+                        })
+                    }
+                });
+            }
+            GetoptItem::Opt { opt, arg: Some(tile_str) }
+                if opt.is_long("tile") =>
+            {
+                if tile.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "tile",
+                        value: tile_str.to_string(),
+                    });
+                }
+                let parse = |s: &str| s.parse::<NonZeroU32>().ok();
+                tile = Some(
+                    tile_str
+                        .split_once('x')
+                        .and_then(|(across, down)| {
+                            Some((parse(across)?, parse(down)?))
+                        })
+                        .ok_or_else(|| ConfigError::InvalidValue {
+                            option: "tile",
+                            value: tile_str.to_string(),
+                        })?,
+                );
+            }
+            GetoptItem::Opt { opt, arg: None } if opt.is_long("mirror") => {
+                mirror = true;
+            }
+            GetoptItem::Opt { opt, arg: Some(background_str) }
+                if opt.is_long("background") =>
+            {
+                if background.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "background",
+                        value: background_str.to_string(),
+                    });
+                }
+                let color = parse_color(background_str, color255);
+                background = Some(color.map_err(|_| {
+                    ConfigError::InvalidValue {
+                        option: "background",
+                        value: background_str.to_string(),
+                    }
+                })?);
+            }
+            GetoptItem::Opt { opt, arg: Some(path) } if opt.is_long("mask") => {
+                if mask.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "mask",
+                        value: path.to_string(),
+                    });
+                }
+                mask = Some(*path);
             }
             GetoptItem::Opt { opt, arg: Some(seed_str) }
                 if opt.is_long("seed") =>
             {
-                set!(seed_str => seed => "seed");
+                if seed.is_some() {
+                    return Err(ConfigError::Duplicate {
+                        option: "seed",
+                        value: seed_str.to_string(),
+                    });
+                }
+                seed = Some(parse_seed(seed_str));
             }
             _ => {}
         }
     }
 
+    if size.0.is_none() {
+        size.0 = env_default(&env, "IMAGEGEN_WIDTH")?;
+    }
+    if size.1.is_none() {
+        size.1 = env_default(&env, "IMAGEGEN_HEIGHT")?;
+    }
+    if maxval.is_none() {
+        maxval = env_default(&env, "IMAGEGEN_MAXVAL")?;
+        if let Some(value @ (0 | 65536..)) = maxval {
+            return Err(ConfigError::InvalidEnvValue {
+                var: "IMAGEGEN_MAXVAL",
+                value: value.to_string(),
+            });
+        }
+    }
+
     const DEFAULT_SIZE: NonZeroUsize =
         unsafe { NonZeroUsize::new_unchecked(256) };
 
     let (dimx, dimy) =
         (size.0.unwrap_or(DEFAULT_SIZE), size.1.unwrap_or(DEFAULT_SIZE));
-    let maxval = maxval.unwrap_or(255);
-    let size =
-        NonZeroUsize::new(dimx.get().checked_mul(dimy.get()).unwrap()).unwrap();
-
-    let image = PnmData {
-        dimx: dimx.get() as u32,
-        dimy: dimy.get() as u32,
-        maxval,
-        depth: 3,
-        comments: vec![],
-        rawdata: vec![Color::default(); size.get()],
+    let max_pixels = max_pixels.unwrap_or(1 << 31);
+    let size = check_size(dimx, dimy, max_pixels)?;
+    let mask = mask.map(|path| read_mask(path, dimx, dimy)).transpose()?;
+    output.maxval = maxval.unwrap_or(output.maxval);
+    output.depth = depth.unwrap_or(output.depth);
+    output.tonemap = tonemap.unwrap_or(output.tonemap);
+    // `--mirror` alone mirrors the image into a 2x2 grid.
+    let two = NonZeroU32::new(2).unwrap();
+    output.tile = match (tile, mirror) {
+        (Some((across, down)), mirror) => Some(Tile { across, down, mirror }),
+        (None, true) => Some(Tile { across: two, down: two, mirror }),
+        (None, false) => None,
     };
+    if let Some(Tile { across, down, .. }) = output.tile {
+        // The tiled image is built in memory when it is written.
+        let widen = |dim: NonZeroUsize, by: NonZeroU32| {
+            dim.saturating_mul(NonZeroUsize::try_from(by).unwrap())
+        };
+        let (width, height) = (widen(dimx, across), widen(dimy, down));
+        check_size(width, height, max_pixels)?;
+    }
+    if let Some(err) = precision_loss(output.maxval) {
+        if strict {
+            return Err(err);
+        }
+        static WARNING: Once = Once::new();
+        WARNING.call_once(|| log::warn!("{err}"));
+    }
 
-    let seed = seed.unwrap_or_else(|| rand::thread_rng().next_u64());
-
-    let locked = CommonLockedData {
-        image,
-        placed_pixels: BitMap::new(dimy.get(), dimx.get()).unwrap(),
-        edges: VecDeque::with_capacity(std::cmp::max(dimx, dimy).get() * 4),
-    };
+    let seed = seed_override
+        .or(seed)
+        .unwrap_or_else(|| rand::thread_rng().next_u64());
+    log::info!("using seed {seed}");
 
-    let data = Arc::new(CommonData {
-        locked: RwLock::new(locked),
-        dimy,
+    Ok(SetupSettings {
         dimx,
-        size: dimy.checked_mul(dimx).unwrap(),
-        progress_barrier: Barrier::new(2),
-        finished: false.into(),
-        pixels_placed: 0.into(),
-        pixels_generated: 0.into(),
-        rng_seed: seed,
-    });
+        dimy,
+        size,
+        output,
+        background: background.unwrap_or_default(),
+        mask,
+        seed,
+        provenance,
+    })
+}
+
+impl SetupSettings {
+    /// Allocates the image and seeds the RNG.
+    pub fn build(self) -> (Arc<CommonData>, impl RngCore + Send) {
+        let SetupSettings {
+            dimx,
+            dimy,
+            size,
+            output,
+            background,
+            mask,
+            seed,
+            provenance,
+        } = self;
+        let mut image = PnmData {
+            dimx: dimx.get() as u32,
+            dimy: dimy.get() as u32,
+            output,
+            comments: vec![],
+            background,
+            rawdata: vec![Color::default(); size.get()],
+        };
+        if provenance {
+            // Record the numeric seed, so that the image can be reproduced
+            // even if it was given as a string and the hash changes.
+            image.add_comment(format!("seed: {seed}"));
+        }
 
-    let rng = rand_chacha::ChaCha12Rng::seed_from_u64(seed);
+        // Masked-out pixels count as placed, so only the others are generated.
+        // They are never edges, so their color is never compared to.
+        let (placed_pixels, size) = match mask {
+            Some(mask) => {
+                mask.for_each_true(|y, x| image[(y, x)] = background);
+                let unmasked = size.get() - mask.count_ones();
+                let size = NonZeroUsize::new(unmasked)
+                    .expect("read_mask checked that some pixels are unmasked");
+                (mask, size)
+            }
+            None => {
+                let placed_pixels = BitMap::new(dimy.get(), dimx.get())
+                    .expect("check_size checked that the image fits in memory");
+                (placed_pixels, size)
+            }
+        };
+        let locked = CommonLockedData {
+            image,
+            placed_pixels,
+            edges: VecDeque::with_capacity(
+                std::cmp::max(dimx, dimy).get() * 4,
+            ),
+            recently_placed: vec![],
+        };
+
+        let data = Arc::new(CommonData {
+            locked: RwLock::new(locked),
+            dimy,
+            dimx,
+            size,
+            progress_barrier: Barrier::new(2),
+            generator_barrier_waits: 0.into(),
+            no_progressor: false,
+            single_thread: false,
+            finished: false.into(),
+            interrupted: Arc::new(false.into()),
+            timed_out: false.into(),
+            progress_error: Mutex::new(None),
+            pixels_placed: 0.into(),
+            pixels_generated: 0.into(),
+            rng_seed: seed,
+        });
+
+        let rng = rand_chacha::ChaCha12Rng::seed_from_u64(seed);
+
+        (data, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use getopt::Getopt;
+
+    #[test]
+    fn invalid_opts() {
+        #[rustfmt::skip]
+        let args_iter: [(&[&str], &str); 15] = [
+            (&["-x3000000000", "-y1"], "requested 3000000000x1 is too large (each dimension must be at most 2147483647)"),
+            (&["-S1", "-S2"], "multiple values specified for --seed (extra value: \"2\")"),
+            (&["--maxval=65536"], "invalid value for --maxval: \"65536\""),
+            (&["--depth=2"], "invalid value for --depth: \"2\""),
+            (&["--tonemap=log"], "unknown value for --tonemap: \"log\" (expected one of: clamp, normalize)"),
+            (&["-s16"], "invalid value for --size: \"16\""),
+            (&["-s16x0"], "invalid value for --size: \"0\""),
+            (&["-x8", "-s16x16"], "--size cannot be used together with --x"),
+            (&["-x8", "-s1080p"], "--size cannot be used together with --x"),
+            (&["-s1080p", "-s4x4"], "multiple values specified for --size (extra value: \"4\")"),
+            (&["--square=128", "-x128"], "--x cannot be used together with --square"),
+            (&["-y8", "--square=8"], "--square cannot be used together with --y"),
+            (&["--square=0"], "invalid value for --square: \"0\""),
+            (&["--tile=2"], "invalid value for --tile: \"2\""),
+            (&["--tile=0x2"], "invalid value for --tile: \"0x2\""),
+        ];
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+
+        for (args, expected) in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let err = super::handle_opts(&opts, None).err().unwrap();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
 
-    (data, rng)
+    #[test]
+    fn computed_sizes() {
+        let cases = [
+            ("1080p", Some((1920, 1080))),
+            ("4K", Some((3840, 2160))),
+            ("16:9@2MP", Some((1886, 1060))),
+            ("16:9@2mp", Some((1886, 1060))),
+            ("1:1@65536", Some((256, 256))),
+            ("4:3@0.3MP", Some((632, 474))),
+            ("2.39:1@1000000", Some((1546, 646))),
+            // Degenerate ratios at tiny pixel counts.
+            ("1:10@10", Some((1, 10))),
+            ("1:10@5", Some((1, 5))),
+            ("10:1@1", Some((1, 1))),
+            ("1:1000@3", Some((1, 3))),
+            // Ambiguous or invalid.
+            ("16:9", None),
+            ("2MP", None),
+            ("16:9@", None),
+            ("@2MP", None),
+            ("16:9@0", None),
+            ("16:9@0.0000001MP", None),
+            ("16:9@-1MP", None),
+            ("0:9@2MP", None),
+            ("16:0@2MP", None),
+            ("inf:1@2MP", None),
+            ("16:9@2GP", None),
+            ("16:9@2MP@3", None),
+            ("16:9:1@2MP", None),
+            ("1080", None),
+        ];
+        for (size, expected) in cases {
+            let computed = super::parse_computed_size(size)
+                .map(|(width, height)| (width.get(), height.get()));
+            assert_eq!(computed, expected, "{size}");
+            if let Some((width, height)) = computed {
+                let size = size.to_ascii_lowercase();
+                if let Some((_, pixels)) = size.split_once('@') {
+                    let pixels = match pixels.strip_suffix("mp") {
+                        Some(megapixels) => {
+                            megapixels.parse::<f64>().unwrap() * 1e6
+                        }
+                        None => pixels.parse().unwrap(),
+                    };
+                    assert!((width * height) as f64 <= pixels, "{size}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeds() {
+        use super::parse_seed;
+
+        assert_eq!(parse_seed("42"), 42);
+        assert_eq!(parse_seed("18446744073709551615"), u64::MAX);
+        assert_eq!(parse_seed("0x2a"), 42);
+        assert_eq!(parse_seed("0XFFFFFFFFFFFFFFFF"), u64::MAX);
+        assert_eq!(parse_seed("0xdeadbeef"), 3735928559);
+        assert_eq!(parse_seed("0b101010"), 42);
+        assert_eq!(parse_seed("0B1"), 1);
+        // FNV-1a. These must not change, or string-seeded images will not be
+        // reproducible.
+        assert_eq!(parse_seed(""), 0xcbf29ce484222325);
+        assert_eq!(parse_seed("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(parse_seed("sunset-42"), 3230171818178135780);
+        // Not valid numbers, so they are hashed.
+        assert_eq!(parse_seed("0x"), 0x07fc5007b4bd8155);
+        assert_ne!(parse_seed("-1"), u64::MAX);
+        assert_ne!(parse_seed("0x1g"), 0x1);
+        assert_ne!(parse_seed("0b12"), 0b1);
+    }
+
+    #[test]
+    fn square() {
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let opts = getopt
+            .parse(["--square", "128"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let settings = super::handle_opts(&opts, None).unwrap();
+        assert_eq!(settings.dimx.get(), 128);
+        assert_eq!(settings.dimy.get(), 128);
+    }
+
+    #[test]
+    fn mask() {
+        let path = std::env::temp_dir().join(format!(
+            "imagegen-setup-mask-test-{}.pgm",
+            std::process::id()
+        ));
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let handle = |pgm: &[u8], size: &str| {
+            std::fs::write(&path, pgm).unwrap();
+            let mask = format!("--mask={}", path.display());
+            let opts = getopt
+                .parse(["-s", size, &mask])
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            super::handle_opts(&opts, None)
+        };
+
+        // Only black pixels are masked out.
+        let settings = handle(b"P2 3 2 9 0 1 9 0 0 5", "3x2").unwrap();
+        let mask = settings.mask.clone().unwrap();
+        assert_eq!(mask.count_ones(), 3);
+        assert!(mask.get((0, 0)) && mask.get((1, 0)) && mask.get((1, 1)));
+        let (common_data, _) = settings.build();
+        assert_eq!(common_data.size.get(), 3);
+        let locked = common_data.locked.read().unwrap();
+        assert_eq!(locked.placed_pixels, mask);
+
+        let path_str = path.display().to_string();
+        let err = handle(b"P2 3 2 9 0 1 9 0 0 5", "2x3").err().unwrap();
+        let expected =
+            format!("--mask image {path_str:?} is 3x2, but the image is 2x3");
+        assert_eq!(err.to_string(), expected);
+        // Nothing left to generate.
+        let err = handle(b"P2 2 1 9 0 0", "2x1").err().unwrap();
+        let expected = format!("invalid value for --mask: {path_str:?}");
+        assert_eq!(err.to_string(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn precision_loss() {
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let opts = getopt
+            .parse(["-s4x4", "--maxval=65535", "--strict"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let result = super::handle_opts(&opts, None);
+
+        assert!(super::precision_loss(255).is_none());
+        if cfg!(feature = "f32") {
+            assert!(super::precision_loss(256).is_some());
+            assert_eq!(
+                result.err().unwrap().to_string(),
+                "--maxval 65535 may lose precision with 32-bit float channels (build without the `f32` feature for deep color)"
+            );
+        } else {
+            assert!(super::precision_loss(65535).is_none());
+            assert!(result.is_ok());
+        }
+    }
+
+    #[test]
+    fn memory_estimate() {
+        use super::{estimate_memory, format_bytes};
+
+        // 32 bytes per `Color` (without the `f32` feature), 1 bit per pixel,
+        // and 4 edges per pixel along the longer side.
+        let color = std::mem::size_of::<crate::color::Color>() as u128;
+        assert_eq!(estimate_memory(8, 1), 8 * color + 1 + 8 * 4 * 8);
+        assert_eq!(estimate_memory(9, 2), 18 * color + 4 + 9 * 4 * 8);
+
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(10 << 20), "10 MiB");
+    }
+
+    #[test]
+    #[cfg_attr(feature = "f32", ignore = "estimates assume 64-bit channels")]
+    fn too_many_pixels() {
+        #[rustfmt::skip]
+        let args_iter: [(&[&str], &str); 4] = [
+            (&["-s1000000x1000000"], "requested 1000000x1000000 needs ~29 TiB, which is more than --max-pixels-allowed (2147483648 pixels)"),
+            (&["-s2147483647x2147483647", "--max-pixels-allowed=18446744073709551615"], "requested 2147483647x2147483647 is too large to allocate"),
+            (&["-s100x100", "--max-pixels-allowed=9999"], "requested 100x100 needs ~317 KiB, which is more than --max-pixels-allowed (9999 pixels)"),
+            // The tiled size counts, not just the tile's.
+            (&["-s32768x32768", "--tile=2x2"], "requested 65536x65536 needs ~129 GiB, which is more than --max-pixels-allowed (2147483648 pixels)"),
+        ];
+
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+
+        for (args, expected) in args_iter {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let err = super::handle_opts(&opts, None).err().unwrap();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        let opts = getopt
+            .parse(["-s100x100", "--max-pixels-allowed=10000"])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(super::handle_opts(&opts, None).is_ok());
+    }
+
+    #[test]
+    fn env_defaults() {
+        let getopt = Getopt::from_iter(super::opts()).unwrap();
+        let settings = |args: &[&str], vars: &[(&str, &str)]| {
+            let opts = getopt
+                .parse(args.iter().copied())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let env = |var: &str| {
+                let value = vars.iter().find(|&&(name, _)| name == var);
+                value.map(|(_, value)| value.to_string())
+            };
+            super::handle_opts_with_env(&opts, Some(0), env)
+                .map(|settings| {
+                    let super::SetupSettings { dimx, dimy, output, .. } =
+                        settings;
+                    (dimx.get(), dimy.get(), output.maxval)
+                })
+                .map_err(|err| err.to_string())
+        };
+        let vars = [
+            ("IMAGEGEN_WIDTH", "64"),
+            ("IMAGEGEN_HEIGHT", "48"),
+            ("IMAGEGEN_MAXVAL", "15"),
+        ];
+
+        assert_eq!(settings(&[], &vars), Ok((64, 48, 15)));
+        assert_eq!(settings(&["-x32"], &vars), Ok((32, 48, 15)));
+        assert_eq!(
+            settings(&["-s16x8", "--maxval=255"], &vars),
+            Ok((16, 8, 255))
+        );
+        assert_eq!(settings(&["-s16x8"], &vars[2..]), Ok((16, 8, 15)));
+        assert_eq!(settings(&[], &[]), Ok((256, 256, 255)));
+        assert_eq!(
+            settings(&[], &[("IMAGEGEN_WIDTH", ""), ("IMAGEGEN_MAXVAL", "")]),
+            Ok((256, 256, 255))
+        );
+
+        assert_eq!(
+            settings(&[], &[("IMAGEGEN_WIDTH", "wide")]),
+            Err("invalid value for $IMAGEGEN_WIDTH: \"wide\"".to_string())
+        );
+        assert_eq!(
+            settings(&[], &[("IMAGEGEN_MAXVAL", "65536")]),
+            Err("invalid value for $IMAGEGEN_MAXVAL: \"65536\"".to_string())
+        );
+        // Invalid defaults do not matter if the option is given.
+        assert!(settings(&["-x8"], &[("IMAGEGEN_WIDTH", "0")]).is_ok());
+    }
 }
@@ -0,0 +1,72 @@
+//! Runs the `imagegen` binary and interrupts it, to check what it writes and
+//! how it exits, which the unit tests can only check piecewise.
+#![cfg(unix)]
+
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+fn imagegen(size: &str, output: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_imagegen"));
+    command
+        .args(["-s", size, "-S1", "-T", "-I64", "--no-provenance", "-o"])
+        .arg(output)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    command
+}
+
+fn output_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "imagegen-interrupt-test-{name}-{}.ppm",
+        std::process::id()
+    ))
+}
+
+/// Checks that `path` is a whole `width`x`height` PPM, and returns how many
+/// of its pixels are still black, i.e. were not placed.
+fn unplaced_pixels(path: &Path, width: usize, height: usize) -> usize {
+    let image = std::fs::read(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    let header = format!("P6\n{width} {height}\n255\n");
+    assert!(image.starts_with(header.as_bytes()));
+    let pixels = &image[header.len()..];
+    assert_eq!(pixels.len(), width * height * 3);
+    pixels.chunks(3).filter(|pixel| *pixel == [0, 0, 0]).count()
+}
+
+#[test]
+fn finished() {
+    let path = output_path("finished");
+    let output = imagegen("16x16", &path).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(unplaced_pixels(&path, 16, 16), 0);
+}
+
+#[test]
+fn interrupted() {
+    let path = output_path("interrupted");
+    let mut child = imagegen("1024x1024", &path).spawn().unwrap();
+    // Progress is only reported once generation has started, by which point
+    // the SIGINT handler has been installed.
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("% done") {
+        line.clear();
+        assert!(stderr.read_line(&mut line).unwrap() > 0, "no progress");
+    }
+    let kill = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(kill.success());
+    // Keep reading, so that progress reports cannot fill the pipe.
+    std::io::copy(&mut stderr, &mut std::io::sink()).unwrap();
+
+    // The conventional status for SIGINT, but after writing the image.
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    let unplaced = unplaced_pixels(&path, 1024, 1024);
+    assert!(0 < unplaced && unplaced < 1024 * 1024, "{unplaced}");
+}